@@ -0,0 +1,59 @@
+//! Allocations per serialized event, counted with dhat so changes to how the serializer buffers
+//! can show what they save. Also writes `dhat-heap.json` for dhat's viewer.
+//!
+//! `cargo bench --bench allocations --features dhat-heap`
+
+use matrix_canonical_json::{from_slice, to_canonical_string, to_canonical_vec, EventGenerator};
+
+#[global_allocator]
+static ALLOCATOR: dhat::DhatAlloc = dhat::DhatAlloc;
+
+const EVENTS: usize = 1_000;
+
+/// Run `op` for every event and print the allocations it made on average.
+fn per_event(name: &str, mut op: impl FnMut(usize)) {
+    let before = dhat::HeapStats::get();
+    for idx in 0..EVENTS {
+        op(idx);
+    }
+    let after = dhat::HeapStats::get();
+
+    println!(
+        "{:<32} {:>8.1} allocations {:>10.1} bytes per event",
+        name,
+        (after.total_blocks - before.total_blocks) as f64 / EVENTS as f64,
+        (after.total_bytes - before.total_bytes) as f64 / EVENTS as f64,
+    );
+}
+
+fn main() {
+    let _dhat = dhat::Dhat::start_heap_profiling();
+
+    let events = EventGenerator::new(0xA110C)
+        .take(EVENTS)
+        .collect::<Vec<_>>();
+    let values = events
+        .iter()
+        .map(|event| serde_json::to_value(event).unwrap())
+        .collect::<Vec<_>>();
+    let json = events
+        .iter()
+        .map(|event| to_canonical_vec(event).unwrap())
+        .collect::<Vec<_>>();
+
+    per_event("to_canonical_vec", |idx| {
+        to_canonical_vec(&events[idx]).unwrap();
+    });
+    per_event("to_canonical_string", |idx| {
+        to_canonical_string(&events[idx]).unwrap();
+    });
+    per_event("to_canonical_vec serde_json", |idx| {
+        to_canonical_vec(&values[idx]).unwrap();
+    });
+    per_event("from_slice", |idx| {
+        from_slice(&json[idx]).unwrap();
+    });
+    per_event("round trip", |idx| {
+        to_canonical_vec(&from_slice(&json[idx]).unwrap()).unwrap();
+    });
+}
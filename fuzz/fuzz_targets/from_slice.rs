@@ -0,0 +1,51 @@
+//! Parse arbitrary bytes with `from_slice` and `from_slice_with_trailing`, anything that parses
+//! has to serialize to canonical JSON that parses back to the same value. Canonicalizing the
+//! text directly has to agree with that and be a fixed point.
+//!
+//! `cargo +nightly fuzz run from_slice fuzz/corpus/from_slice`
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use matrix_canonical_json::{
+    check_idempotent, from_slice, from_slice_with_trailing, is_canonical, to_canonical_cow,
+    CanonicalJsonValue, Options, DEFAULT_SIZE_LIMIT,
+};
+
+fn round_trip(value: &CanonicalJsonValue) {
+    let canonical = Options::new().size_limit(None).to_vec(value).unwrap();
+    assert_eq!(&from_slice(&canonical).unwrap(), value);
+    assert_eq!(
+        Options::new().size_limit(None).to_vec(value).unwrap(),
+        canonical
+    );
+    if canonical.len() <= DEFAULT_SIZE_LIMIT {
+        assert!(is_canonical(std::str::from_utf8(&canonical).unwrap()));
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let strict = from_slice(data);
+    if let Ok(value) = &strict {
+        round_trip(value);
+        let text = std::str::from_utf8(data).unwrap();
+        // Only the size limit can make canonicalizing fail here.
+        if to_canonical_cow(text).is_ok() {
+            let canonical = check_idempotent(text).unwrap();
+            assert_eq!(&from_slice(canonical.as_bytes()).unwrap(), value);
+        }
+    }
+
+    if let Ok((value, consumed)) = from_slice_with_trailing(data) {
+        assert!(consumed <= data.len());
+        assert_eq!(&from_slice(&data[..consumed]).unwrap(), &value);
+        round_trip(&value);
+    }
+    // Input the strict parser takes may only be followed by whitespace for the lenient one.
+    if let Ok(value) = strict {
+        let (lenient, consumed) = from_slice_with_trailing(data).unwrap();
+        assert_eq!(lenient, value);
+        assert!(data[consumed..]
+            .iter()
+            .all(|b| matches!(b, b' ' | b'\t' | b'\n' | b'\r')));
+    }
+});
@@ -0,0 +1,75 @@
+//! A serde adapter for fields whose type has no serde support of its own but can be written
+//! with `Display` and read back with `FromStr`, like `mime::Mime`:
+//!
+//! ```ignore
+//! #[derive(Serialize, Deserialize)]
+//! struct FileInfo {
+//!     #[serde(with = "matrix_canonical_json::as_str")]
+//!     mimetype: Mime,
+//! }
+//! ```
+//!
+//! `uuid::Uuid` and `url::Url` serialize as plain strings through this crate's serializer
+//! without the adapter. With the `uuid`, `url` and `mime` features all three types also
+//! convert into a `CanonicalJsonValue::String`.
+
+use std::{fmt::Display, str::FromStr};
+
+use serde::{de, Deserialize, Deserializer, Serializer};
+
+pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Display,
+    S: Serializer,
+{
+    serializer.collect_str(value)
+}
+
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: FromStr,
+    T::Err: Display,
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    value.parse().map_err(de::Error::custom)
+}
+
+#[cfg(all(feature = "ser", feature = "mime", feature = "url", feature = "uuid"))]
+#[test]
+fn common_types_as_strings() {
+    use crate::{to_canonical_string, to_canonical_value, CanonicalJsonValue};
+
+    #[derive(Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize)]
+    struct Upload {
+        id: uuid::Uuid,
+        url: url::Url,
+        #[serde(with = "crate::as_str")]
+        mimetype: mime::Mime,
+    }
+
+    let upload = Upload {
+        id: "67e55044-10b1-426f-9247-bb680e5fe0c8".parse().unwrap(),
+        url: url::Url::parse("https://example.org/media?a=1&b=é").unwrap(),
+        mimetype: "image/png; charset=utf-8".parse().unwrap(),
+    };
+    let json = to_canonical_string(&upload).unwrap();
+    assert_eq!(
+        json,
+        r#"{"id":"67e55044-10b1-426f-9247-bb680e5fe0c8","mimetype":"image/png; charset=utf-8","url":"https://example.org/media?a=1&b=%C3%A9"}"#
+    );
+    assert_eq!(serde_json::from_str::<Upload>(&json).unwrap(), upload);
+
+    assert_eq!(
+        to_canonical_value(&upload.id).unwrap(),
+        CanonicalJsonValue::from(upload.id)
+    );
+    assert_eq!(
+        to_canonical_value(&upload.url).unwrap(),
+        CanonicalJsonValue::from(upload.url.clone())
+    );
+    assert_eq!(
+        CanonicalJsonValue::from(upload.mimetype),
+        CanonicalJsonValue::String("image/png; charset=utf-8".to_owned())
+    );
+}
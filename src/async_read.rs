@@ -0,0 +1,141 @@
+#[cfg(feature = "futures")]
+use futures::stream::{self, Stream};
+#[cfg(feature = "futures")]
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+#[cfg(feature = "futures")]
+use crate::ndjson::{at_line, is_blank, parse_line};
+use crate::{CanonicalJsonValue, Error, Options, Result};
+
+/// Read a JSON document from `reader` and parse it into a `CanonicalJsonValue`, like
+/// `from_slice` does. See `Options::from_async_reader` for the limits that apply.
+pub async fn from_async_reader<R>(reader: R) -> Result<CanonicalJsonValue>
+where
+    R: AsyncRead + Unpin,
+{
+    Options::new().from_async_reader(reader).await
+}
+
+/// Read everything `reader` has, failing with `Error::SizeLimit` as soon as it is more than
+/// `limit` bytes.
+pub(crate) async fn read_limited<R>(mut reader: R, limit: Option<usize>) -> Result<Vec<u8>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut input = vec![];
+    let mut chunk = [0; 8 * 1024];
+    loop {
+        let read = reader.read(&mut chunk).await.map_err(Error::io)?;
+        if read == 0 {
+            return Ok(input);
+        }
+        if matches!(limit, Some(limit) if input.len() + read > limit) {
+            return Err(Error::SizeLimit);
+        }
+        input.extend_from_slice(&chunk[..read]);
+    }
+}
+
+/// Reads newline delimited JSON from an async reader like `NdjsonReader` does, as a `Stream` of
+/// one `CanonicalJsonValue` per line.
+#[cfg(feature = "futures")]
+pub struct AsyncNdjsonReader<R> {
+    reader: R,
+    strict: bool,
+}
+
+#[cfg(feature = "futures")]
+impl<R: AsyncBufRead + Unpin> AsyncNdjsonReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            strict: false,
+        }
+    }
+
+    /// Reject lines that are valid JSON but not already canonical JSON.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// The values of every line that is not blank. Errors are wrapped in `Error::Line` and the
+    /// stream keeps going after them.
+    pub fn into_stream(self) -> impl Stream<Item = Result<CanonicalJsonValue>> {
+        stream::unfold(
+            (self, vec![], 0),
+            |(mut this, mut buf, mut line)| async move {
+                loop {
+                    buf.clear();
+                    match this.reader.read_until(b'\n', &mut buf).await {
+                        Ok(0) => return None,
+                        Ok(_) => line += 1,
+                        Err(err) => return Some((Err(Error::io(err)), (this, buf, line))),
+                    }
+
+                    if is_blank(&buf) {
+                        continue;
+                    }
+                    let value = parse_line(&buf, this.strict).map_err(|e| at_line(line, e));
+                    return Some((value, (this, buf, line)));
+                }
+            },
+        )
+    }
+}
+
+#[test]
+fn read_async() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    let value = runtime
+        .block_on(from_async_reader(
+            r#"{"b": [1, "é"], "a": null}"#.as_bytes(),
+        ))
+        .unwrap();
+    assert_eq!(
+        crate::to_canonical_string(&value).unwrap(),
+        r#"{"a":null,"b":[1,"é"]}"#
+    );
+
+    let options = Options::new().size_limit(Some(5)).max_string_len(Some(2));
+    assert!(matches!(
+        runtime.block_on(options.from_async_reader(&b"[1, 2]"[..])),
+        Err(Error::SizeLimit)
+    ));
+    assert!(matches!(
+        runtime.block_on(options.from_async_reader(&br#""ab""#[..])),
+        Ok(CanonicalJsonValue::String(_))
+    ));
+    assert!(runtime
+        .block_on(options.from_async_reader(&br#""abc""#[..]))
+        .is_err());
+    assert!(runtime.block_on(from_async_reader(&b"[1.5]"[..])).is_err());
+}
+
+#[cfg(feature = "futures")]
+#[test]
+fn ndjson_stream() {
+    use futures::{executor::block_on, StreamExt};
+
+    let log = b"{\"b\":1}\n\n{\"a\": 2}\n[1.5]\n";
+    let lines = block_on(
+        AsyncNdjsonReader::new(&log[..])
+            .into_stream()
+            .collect::<Vec<_>>(),
+    );
+    assert_eq!(lines.len(), 3);
+    assert_eq!(
+        lines[1].as_ref().unwrap().get("a"),
+        Some(&CanonicalJsonValue::Integer(2))
+    );
+    assert!(matches!(&lines[2], Err(Error::Line { line: 4, .. })));
+
+    let strict = AsyncNdjsonReader::new(&log[..]).strict(true).into_stream();
+    let lines = block_on(strict.collect::<Vec<_>>());
+    assert!(lines[0].is_ok());
+    assert!(matches!(&lines[1], Err(Error::Line { line: 3, .. })));
+}
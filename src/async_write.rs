@@ -0,0 +1,169 @@
+use std::io;
+
+use serde::Serialize;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::{Error, Options, Result};
+
+/// Writes canonical JSON documents to an async writer, one after the other.
+///
+/// Writing is cancellation safe: a document is serialized completely before the first byte of
+/// it is written, and a `write` or `flush` future that is dropped part way, for example by a
+/// `select!`, keeps the rest of its document in the writer. The next `write` or `flush` sends
+/// that rest before anything else, so the writer never sees a truncated document followed by
+/// the next one. A document whose `write` was cancelled before any of it was written can be
+/// dropped with `discard_pending`.
+pub struct AsyncCanonicalWriter<W> {
+    writer: W,
+    options: Options,
+    pending: Vec<u8>,
+    written: usize,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncCanonicalWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            options: Options::new(),
+            pending: vec![],
+            written: 0,
+        }
+    }
+
+    /// The options documents are serialized with, `Options::new()` by default.
+    pub fn options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Serialize `value` and write it. A document left over by a cancelled `write` is finished
+    /// first. Nothing is written if serialization fails.
+    pub async fn write<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.write_pending().await?;
+        self.pending = self.options.to_vec(value)?;
+        self.write_pending().await
+    }
+
+    /// Finish a document left over by a cancelled `write` and flush the writer.
+    pub async fn flush(&mut self) -> Result<()> {
+        self.write_pending().await?;
+        self.writer.flush().await.map_err(Error::io)
+    }
+
+    /// Whether a cancelled `write` left (part of) a document that was not written yet.
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Drop the document a cancelled `write` left, if none of it was written yet. Returns
+    /// `false` if there is nothing to drop or the writer already got part of it, in which case
+    /// it is kept.
+    pub fn discard_pending(&mut self) -> bool {
+        if self.pending.is_empty() || self.written > 0 {
+            return false;
+        }
+        self.pending.clear();
+        true
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// The writer, anything written to it directly ends up in the middle of a pending
+    /// document.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// The writer, a pending document is lost.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    async fn write_pending(&mut self) -> Result<()> {
+        // `AsyncWriteExt::write` either writes some bytes or none when it is cancelled, so
+        // `written` always matches what the writer got.
+        while self.written < self.pending.len() {
+            let n = self
+                .writer
+                .write(&self.pending[self.written..])
+                .await
+                .map_err(Error::io)?;
+            if n == 0 {
+                return Err(Error::io(io::ErrorKind::WriteZero.into()));
+            }
+            self.written += n;
+        }
+        self.pending.clear();
+        self.written = 0;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "futures")]
+#[test]
+fn cancelled_writes() {
+    use std::{
+        future::Future,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use futures::{executor::block_on, task::noop_waker_ref};
+
+    /// Takes 3 bytes at a time and is only ready every other time it is polled.
+    #[derive(Default)]
+    struct Slow {
+        out: Vec<u8>,
+        stall: bool,
+    }
+
+    impl AsyncWrite for Slow {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.stall = !self.stall;
+            if !self.stall {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let n = buf.len().min(3);
+            self.out.extend_from_slice(&buf[..n]);
+            Poll::Ready(Ok(n))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    let mut writer = AsyncCanonicalWriter::new(Slow::default());
+    let mut cx = Context::from_waker(noop_waker_ref());
+    let value = serde_json::json!({ "b": 1, "a": 2 });
+    {
+        // Cancelled after the first 3 bytes.
+        let mut write = Box::pin(writer.write(&value));
+        assert!(write.as_mut().poll(&mut cx).is_pending());
+    }
+    assert!(writer.has_pending());
+    assert!(!writer.discard_pending());
+    assert_eq!(writer.get_ref().out, b"{\"a".to_vec());
+
+    block_on(writer.write(&[true])).unwrap();
+    assert_eq!(writer.get_ref().out, br#"{"a":2,"b":1}[true]"#.to_vec());
+    assert!(!writer.has_pending());
+
+    assert!(block_on(writer.write(&1.5)).is_err());
+    block_on(writer.flush()).unwrap();
+    assert_eq!(writer.into_inner().out, br#"{"a":2,"b":1}[true]"#.to_vec());
+}
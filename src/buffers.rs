@@ -0,0 +1,38 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::Serialize;
+
+use crate::{to_canonical_vec, Options, Result};
+
+/// Serialize `value` as canonical JSON into a `Bytes` that can be handed to hyper or tokio
+/// without copying it again.
+pub fn to_canonical_bytes<T>(value: &T) -> Result<Bytes>
+where
+    T: ?Sized + Serialize,
+{
+    to_canonical_vec(value).map(Bytes::from)
+}
+
+/// Append `value` as canonical JSON to `buf`, returning the number of bytes written. `buf` is
+/// left as it was if serialization fails.
+pub fn to_canonical_bytes_mut<T>(buf: &mut BytesMut, value: &T) -> Result<usize>
+where
+    T: ?Sized + Serialize,
+{
+    let len = buf.len();
+    let res = Options::new().to_writer((&mut *buf).writer(), value);
+    if res.is_err() {
+        buf.truncate(len);
+    }
+    res
+}
+
+#[test]
+fn bytes_output() {
+    let json = serde_json::json!({ "b": 2, "a": 1 });
+    assert_eq!(to_canonical_bytes(&json).unwrap(), r#"{"a":1,"b":2}"#);
+
+    let mut buf = BytesMut::from(&b"prefix "[..]);
+    assert_eq!(to_canonical_bytes_mut(&mut buf, &json).unwrap(), 13);
+    assert!(to_canonical_bytes_mut(&mut buf, &[1.5]).is_err());
+    assert_eq!(buf.freeze(), r#"prefix {"a":1,"b":2}"#);
+}
@@ -0,0 +1,157 @@
+#[cfg(feature = "hash")]
+use std::cell::Cell;
+use std::{
+    cell::{Ref, RefCell},
+    ops::{Deref, DerefMut},
+};
+
+use serde::{Serialize, Serializer};
+
+use crate::{to_canonical_vec, Result};
+
+/// A value that remembers its canonical JSON, so serializing or hashing the same event over and
+/// over only does the work once.
+///
+/// The cache is dropped whenever the value is changed through `get_mut`.
+pub struct Cached<T> {
+    value: T,
+    cache: RefCell<Option<Cache>>,
+}
+
+struct Cache {
+    bytes: Vec<u8>,
+    /// Set through a shared borrow so it can be filled while `canonical` is borrowed.
+    #[cfg(feature = "hash")]
+    sha256: Cell<Option<[u8; 32]>>,
+}
+
+impl<T: Serialize> Cached<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            value,
+            cache: RefCell::new(None),
+        }
+    }
+
+    /// The canonical JSON of the value, serialized the first time it is asked for.
+    pub fn canonical(&self) -> Result<Ref<'_, [u8]>> {
+        self.fill()?;
+        Ok(Ref::map(self.cache.borrow(), |cache| {
+            cache.as_ref().expect("cache was filled").bytes.as_slice()
+        }))
+    }
+
+    /// The SHA-256 hash of the canonical JSON of the value.
+    #[cfg(feature = "hash")]
+    pub fn sha256(&self) -> Result<[u8; 32]> {
+        use sha2::{Digest, Sha256};
+
+        self.fill()?;
+        let cache = self.cache.borrow();
+        let cache = cache.as_ref().expect("cache was filled");
+        if let Some(hash) = cache.sha256.get() {
+            return Ok(hash);
+        }
+        let mut hash = [0; 32];
+        hash.copy_from_slice(&Sha256::digest(&cache.bytes));
+        cache.sha256.set(Some(hash));
+        Ok(hash)
+    }
+
+    /// Mutably borrow the value, the cache is dropped once the guard is.
+    pub fn get_mut(&mut self) -> CachedMut<'_, T> {
+        CachedMut { cached: self }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+
+    fn fill(&self) -> Result<()> {
+        if self.cache.borrow().is_none() {
+            let bytes = to_canonical_vec(&self.value)?;
+            *self.cache.borrow_mut() = Some(Cache {
+                bytes,
+                #[cfg(feature = "hash")]
+                sha256: Cell::new(None),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<T> Deref for Cached<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: Serialize> Serialize for Cached<T> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.value.serialize(serializer)
+    }
+}
+
+/// A guard mutably borrowing the value of a `Cached`, returned by `Cached::get_mut`.
+pub struct CachedMut<'a, T> {
+    cached: &'a mut Cached<T>,
+}
+
+impl<'a, T> Deref for CachedMut<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.cached.value
+    }
+}
+
+impl<'a, T> DerefMut for CachedMut<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.cached.value
+    }
+}
+
+impl<'a, T> Drop for CachedMut<'a, T> {
+    fn drop(&mut self) {
+        *self.cached.cache.get_mut() = None;
+    }
+}
+
+#[test]
+fn caches_until_mutated() {
+    let mut cached = Cached::new(serde_json::json!({ "b": 2, "a": 1 }));
+
+    assert_eq!(&*cached.canonical().unwrap(), br#"{"a":1,"b":2}"#);
+    assert!(cached.cache.borrow().is_some());
+
+    cached.get_mut()["c"] = serde_json::json!(3);
+    assert!(cached.cache.borrow().is_none());
+    assert_eq!(&*cached.canonical().unwrap(), br#"{"a":1,"b":2,"c":3}"#);
+}
+
+#[cfg(feature = "hash")]
+#[test]
+fn caches_hash() {
+    let cached = Cached::new(serde_json::json!({ "b": 2, "a": 1 }));
+    let hex = cached
+        .sha256()
+        .unwrap()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+
+    assert_eq!(
+        hex,
+        "43258cff783fe7036d8a43033f830adfc60ec037382473548ac742b888292777"
+    );
+
+    let cached = Cached::new(serde_json::json!({ "b": 2, "a": 1 }));
+    let canonical = cached.canonical().unwrap();
+    assert_eq!(cached.sha256().unwrap(), cached.sha256().unwrap());
+    assert_eq!(&*canonical, br#"{"a":1,"b":2}"#);
+}
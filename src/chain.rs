@@ -0,0 +1,213 @@
+use std::{fmt::Write, str};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    from_slice, is_canonical, to_canonical_object, CanonicalJsonValue, Error, Options, Result,
+};
+
+/// The key every record but the first of a chain holds the SHA-256 hash of the previous record
+/// under, as lowercase hex.
+pub const PREV_SHA256_KEY: &str = "prev_sha256";
+
+/// Links objects into a hash chain: every record gets the hash of the canonical JSON of the
+/// one before it under `PREV_SHA256_KEY`, so changing, dropping or reordering a record breaks
+/// the chain from there on. `ChainVerifier` checks a chain.
+///
+/// The records can be written with a `JournalWriter` using `append_raw` or as NDJSON, one record
+/// followed by a newline per line.
+#[derive(Debug, Default)]
+pub struct HashChain {
+    options: Options,
+    prev: Option<[u8; 32]>,
+}
+
+impl HashChain {
+    /// Start a new chain, the first record has no `PREV_SHA256_KEY`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Continue a chain whose last record hashed to `prev`, see `last_hash`.
+    pub fn resume(prev: [u8; 32]) -> Self {
+        Self {
+            options: Options::new(),
+            prev: Some(prev),
+        }
+    }
+
+    /// The options records are serialized with, `Options::new()` by default.
+    pub fn options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// The hash of the last record, the next one will hold it.
+    pub fn last_hash(&self) -> Option<[u8; 32]> {
+        self.prev
+    }
+
+    /// Serialize `value`, which has to be an object without a `PREV_SHA256_KEY` field, as the
+    /// next record of the chain.
+    pub fn link<T>(&mut self, value: &T) -> Result<Vec<u8>>
+    where
+        T: ?Sized + Serialize,
+    {
+        let mut object = to_canonical_object(value)?;
+        if object.contains_key(PREV_SHA256_KEY) {
+            return Err(Error::InvalidInput(format!(
+                "record already has a `{}` field",
+                PREV_SHA256_KEY
+            )));
+        }
+        if let Some(prev) = &self.prev {
+            object.insert(
+                PREV_SHA256_KEY.to_owned(),
+                CanonicalJsonValue::String(hex(prev)),
+            );
+        }
+
+        let record = self.options.to_vec(&object)?;
+        self.prev = Some(sha256(&record));
+        Ok(record)
+    }
+}
+
+/// Checks the records of a `HashChain` one after the other.
+#[derive(Debug, Default)]
+pub struct ChainVerifier {
+    prev: Option<[u8; 32]>,
+    records: usize,
+}
+
+impl ChainVerifier {
+    /// Verify a chain from its first record.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Verify the rest of a chain whose last verified record hashed to `prev`.
+    pub fn resume(prev: [u8; 32]) -> Self {
+        Self {
+            prev: Some(prev),
+            records: 0,
+        }
+    }
+
+    /// The hash of the last verified record.
+    pub fn last_hash(&self) -> Option<[u8; 32]> {
+        self.prev
+    }
+
+    /// The number of records verified.
+    pub fn records(&self) -> usize {
+        self.records
+    }
+
+    /// Check that `record` is canonical JSON and holds the hash of the record before it.
+    pub fn verify(&mut self, record: &[u8]) -> Result<()> {
+        let record_no = self.records + 1;
+        let value = from_slice(record)?;
+        // `from_slice` already made sure the record is valid UTF-8.
+        if !str::from_utf8(record).map(is_canonical).unwrap_or(false) {
+            return Err(Error::InvalidInput(format!(
+                "record {} is not canonical JSON",
+                record_no
+            )));
+        }
+
+        let found = match value.get(PREV_SHA256_KEY) {
+            Some(CanonicalJsonValue::String(hash)) => Some(hash.as_str()),
+            None => None,
+            Some(_) => {
+                return Err(Error::InvalidInput(format!(
+                    "`{}` of record {} is not a string",
+                    PREV_SHA256_KEY, record_no
+                )))
+            }
+        };
+        let expected = self.prev.as_ref().map(hex);
+        if found != expected.as_deref() {
+            return Err(Error::InvalidInput(format!(
+                "record {} does not follow the one before it, expected `{}` of {} found {}",
+                record_no,
+                PREV_SHA256_KEY,
+                expected.as_deref().unwrap_or("none"),
+                found.unwrap_or("none")
+            )));
+        }
+
+        self.prev = Some(sha256(record));
+        self.records = record_no;
+        Ok(())
+    }
+}
+
+/// Verify every record of a chain starting at its first record, for example the records of a
+/// `JournalReader`. Returns the number of records.
+pub fn verify_chain<I>(records: I) -> Result<usize>
+where
+    I: IntoIterator<Item = Result<Vec<u8>>>,
+{
+    let mut verifier = ChainVerifier::new();
+    for record in records {
+        verifier.verify(&record?)?;
+    }
+    Ok(verifier.records())
+}
+
+fn sha256(record: &[u8]) -> [u8; 32] {
+    let mut hash = [0; 32];
+    hash.copy_from_slice(&Sha256::digest(record));
+    hash
+}
+
+fn hex(hash: &[u8; 32]) -> String {
+    let mut hex = String::with_capacity(64);
+    for byte in hash {
+        write!(hex, "{:02x}", byte).expect("writing to a String can't fail");
+    }
+    hex
+}
+
+#[test]
+fn chained_records() {
+    let mut chain = HashChain::new();
+    let mut journal = vec![];
+    for body in &["a", "b", "c"] {
+        let record = chain.link(&serde_json::json!({ "body": body })).unwrap();
+        journal.extend_from_slice(&(record.len() as u32).to_be_bytes());
+        journal.extend_from_slice(&record);
+    }
+    let records = crate::JournalReader::new(&journal[..])
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(records[0], br#"{"body":"a"}"#.to_vec());
+    assert_eq!(
+        str::from_utf8(&records[1]).unwrap(),
+        format!(
+            r#"{{"body":"b","prev_sha256":"{}"}}"#,
+            hex(&sha256(&records[0]))
+        )
+    );
+    assert_eq!(verify_chain(records.iter().cloned().map(Ok)).unwrap(), 3);
+
+    // Resuming an export part way through.
+    let mut verifier = ChainVerifier::resume(sha256(&records[0]));
+    verifier.verify(&records[1]).unwrap();
+    verifier.verify(&records[2]).unwrap();
+    assert_eq!(verifier.last_hash(), chain.last_hash());
+
+    let mut tampered = records.clone();
+    tampered[0] = br#"{"body":"x"}"#.to_vec();
+    assert!(verify_chain(tampered.into_iter().map(Ok)).is_err());
+    let reordered = vec![records[0].clone(), records[2].clone(), records[1].clone()];
+    assert!(verify_chain(reordered.into_iter().map(Ok)).is_err());
+    assert!(verify_chain(vec![Ok(br#"{ "body":"a"}"#.to_vec())]).is_err());
+
+    assert!(chain.link(&1).is_err());
+    assert!(chain
+        .link(&serde_json::json!({ "prev_sha256": "" }))
+        .is_err());
+}
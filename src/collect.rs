@@ -0,0 +1,106 @@
+use std::cell::Cell;
+
+use serde::{Serialize, Serializer};
+
+use crate::{to_canonical_string, to_canonical_vec, Result};
+
+/// Serializes the pairs of an iterator as a map, the iterator can only be serialized once.
+struct PairIter<I>(Cell<Option<I>>);
+
+impl<I, K, V> Serialize for PairIter<I>
+where
+    I: Iterator<Item = (K, V)>,
+    K: Serialize,
+    V: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_map(self.0.take().expect("the iterator is only serialized once"))
+    }
+}
+
+/// Serializes the items of an iterator as a sequence, the iterator can only be serialized once.
+struct SeqIter<I>(Cell<Option<I>>);
+
+impl<I> Serialize for SeqIter<I>
+where
+    I: Iterator,
+    I::Item: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(self.0.take().expect("the iterator is only serialized once"))
+    }
+}
+
+/// Serialize the pairs of `iter` as a canonical JSON object, for data that is produced lazily
+/// and never lives in a map. The keys are sorted like those of any other object.
+pub fn to_canonical_from_iter<I, K, V>(iter: I) -> Result<String>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: Serialize,
+    V: Serialize,
+{
+    to_canonical_string(&PairIter(Cell::new(Some(iter.into_iter()))))
+}
+
+/// Like `to_canonical_from_iter` but returns the bytes, the crate level version of
+/// `Serializer::collect_map`.
+pub fn collect_canonical_map<I, K, V>(iter: I) -> Result<Vec<u8>>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: Serialize,
+    V: Serialize,
+{
+    to_canonical_vec(&PairIter(Cell::new(Some(iter.into_iter()))))
+}
+
+/// Serialize the items of `iter` as a canonical JSON array, the crate level version of
+/// `Serializer::collect_seq`.
+pub fn collect_canonical_seq<I>(iter: I) -> Result<Vec<u8>>
+where
+    I: IntoIterator,
+    I::Item: Serialize,
+{
+    to_canonical_vec(&SeqIter(Cell::new(Some(iter.into_iter()))))
+}
+
+#[test]
+fn object_from_iter() {
+    let pairs = vec![("b", 2), ("a", 1), ("c", 3)];
+    assert_eq!(
+        to_canonical_from_iter(pairs).unwrap(),
+        r#"{"a":1,"b":2,"c":3}"#
+    );
+
+    let values = (0..3).map(|i| (format!("k{}", 2 - i), vec![i; i]));
+    assert_eq!(
+        to_canonical_from_iter(values).unwrap(),
+        r#"{"k0":[2,2],"k1":[1],"k2":[]}"#
+    );
+
+    let canonical = vec![("x", crate::CanonicalJsonValue::Bool(true))];
+    assert_eq!(to_canonical_from_iter(canonical).unwrap(), r#"{"x":true}"#);
+}
+
+#[test]
+fn collectors() {
+    let rows = vec![("@bob:example.org", 50), ("@alice:example.org", 100)];
+    assert_eq!(
+        collect_canonical_map(rows.iter().cloned()).unwrap(),
+        &br#"{"@alice:example.org":100,"@bob:example.org":50}"#[..]
+    );
+    assert_eq!(
+        collect_canonical_seq(rows.iter().map(|(user, _)| user)).unwrap(),
+        &br#"["@bob:example.org","@alice:example.org"]"#[..]
+    );
+    assert_eq!(
+        collect_canonical_seq((0..2).map(|i| serde_json::json!({ "b": i, "a": i }))).unwrap(),
+        &br#"[{"a":0,"b":0},{"a":1,"b":1}]"#[..]
+    );
+    assert!(collect_canonical_seq(vec![1.5]).is_err());
+}
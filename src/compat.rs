@@ -0,0 +1,55 @@
+//! Wrappers shaped like the API of the `canonical_json` crate, so its users can switch by
+//! changing their imports from `canonical_json::ser::to_string` to
+//! `matrix_canonical_json::compat::ser::to_string`.
+
+use std::{error::Error as StdError, fmt};
+
+use crate::Error;
+
+/// The error of `ser::to_string`.
+#[derive(Debug)]
+pub struct CanonicalJsonError(pub Error);
+
+impl fmt::Display for CanonicalJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl StdError for CanonicalJsonError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.0)
+    }
+}
+
+impl From<Error> for CanonicalJsonError {
+    fn from(err: Error) -> Self {
+        Self(err)
+    }
+}
+
+pub mod ser {
+    use serde_json::Value;
+
+    use super::CanonicalJsonError;
+    use crate::to_canonical_string_unlimited;
+
+    /// Serialize `input` as canonical JSON. Like with the `canonical_json` crate there is no
+    /// size limit, unlike it floats are rejected as canonical JSON has no room for them.
+    pub fn to_string(input: &Value) -> Result<String, CanonicalJsonError> {
+        to_canonical_string_unlimited(input).map_err(CanonicalJsonError)
+    }
+}
+
+#[test]
+fn canonical_json_to_string() {
+    let value = serde_json::json!({ "b": "\u{1f}", "a": [1, true] });
+    assert_eq!(
+        ser::to_string(&value).unwrap(),
+        r#"{"a":[1,true],"b":"\u001f"}"#
+    );
+
+    let err = ser::to_string(&serde_json::json!(1.5)).unwrap_err();
+    assert!(matches!(err.0, Error::InvalidInput(_)));
+    assert!(err.source().is_some());
+}
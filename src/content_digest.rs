@@ -0,0 +1,61 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{Options, Result};
+
+/// Serialize `value` as canonical JSON and compute the RFC 9530 `Content-Digest` header value
+/// of it, `sha-256=:<base64>:`, while the bytes are written instead of hashing them afterwards.
+pub fn to_canonical_with_digest<T>(value: &T) -> Result<(Vec<u8>, String)>
+where
+    T: ?Sized + Serialize,
+{
+    let mut writer = DigestWriter {
+        inner: Vec::with_capacity(128),
+        hasher: Sha256::new(),
+    };
+    Options::new().to_writer(&mut writer, value)?;
+    let digest = header_value(&writer.hasher.finalize());
+    Ok((writer.inner, digest))
+}
+
+/// The `Content-Digest` header value of a body that was already serialized.
+pub fn content_digest(body: &[u8]) -> String {
+    header_value(&Sha256::digest(body))
+}
+
+fn header_value(hash: &[u8]) -> String {
+    format!("sha-256=:{}:", base64::encode(hash))
+}
+
+/// Hashes everything written to it on the way through.
+struct DigestWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> Write for DigestWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[test]
+fn body_digest() {
+    let (body, digest) = to_canonical_with_digest(&serde_json::json!({ "b": 2, "a": 1 })).unwrap();
+
+    assert_eq!(body, br#"{"a":1,"b":2}"#);
+    assert_eq!(
+        digest,
+        "sha-256=:QyWM/3g/5wNtikMDP4MK38YOwDc4JHNUisdCuIgpJ3c=:"
+    );
+    assert_eq!(content_digest(&body), digest);
+    assert!(to_canonical_with_digest(&1.5).is_err());
+}
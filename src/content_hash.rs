@@ -0,0 +1,71 @@
+use std::collections::BTreeMap;
+
+use sha2::{Digest, Sha256};
+
+use crate::{CanonicalJsonObject, CanonicalJsonValue, Options, Result};
+
+/// What `check_declared_hashes` found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashCheck {
+    /// `hashes.sha256` is the content hash of the event.
+    Valid,
+    /// `hashes.sha256` is missing or not the content hash of the event, which has to be redacted
+    /// before it is used any further.
+    Redact,
+}
+
+/// The content hash of a PDU as it goes into `hashes.sha256`: the SHA-256 of the canonical
+/// JSON of the event without `unsigned`, `signatures` and `hashes`, as unpadded base64.
+///
+/// The event is hashed without the size limit, `is_federation_sendable` checks its size.
+pub fn content_hash(event: &CanonicalJsonObject) -> Result<String> {
+    let hashed = event
+        .iter()
+        .filter(|(key, _)| !matches!(key.as_str(), "unsigned" | "signatures" | "hashes"))
+        .collect::<BTreeMap<_, _>>();
+    let json = Options::new().size_limit(None).to_vec(&hashed)?;
+    Ok(base64::encode_config(
+        Sha256::digest(&json),
+        base64::STANDARD_NO_PAD,
+    ))
+}
+
+/// Recompute the content hash of a received PDU and compare it to the `hashes.sha256` it
+/// declares. An event that fails the check is not rejected, it is redacted and used as that.
+pub fn check_declared_hashes(event: &CanonicalJsonObject) -> Result<HashCheck> {
+    let declared = match event.get("hashes").and_then(|hashes| hashes.get("sha256")) {
+        Some(CanonicalJsonValue::String(hash)) => hash.trim_end_matches('='),
+        _ => return Ok(HashCheck::Redact),
+    };
+    if content_hash(event)? == declared {
+        Ok(HashCheck::Valid)
+    } else {
+        Ok(HashCheck::Redact)
+    }
+}
+
+#[test]
+fn declared_hashes() {
+    let mut event = crate::to_canonical_object(&serde_json::json!({
+        "content": { "body": "hi", "msgtype": "m.text" },
+        "origin_server_ts": 1_600_000_000_000_u64,
+        "room_id": "!r:example.org",
+        "sender": "@alice:example.org",
+        "type": "m.room.message",
+        "unsigned": { "age": 45 },
+        "signatures": {}
+    }))
+    .unwrap();
+    let hash = "yQnb7C3u/cJSPmKUpyEB8GQtHQfm6w001u7tXb+glMc";
+    assert_eq!(content_hash(&event).unwrap(), hash);
+    assert_eq!(check_declared_hashes(&event).unwrap(), HashCheck::Redact);
+
+    let mut hashes = CanonicalJsonObject::new();
+    hashes.insert("sha256".to_owned(), hash.into());
+    event.insert("hashes".to_owned(), hashes.into());
+    assert_eq!(check_declared_hashes(&event).unwrap(), HashCheck::Valid);
+    assert_eq!(content_hash(&event).unwrap(), hash);
+
+    event.insert("depth".to_owned(), 3.into());
+    assert_eq!(check_declared_hashes(&event).unwrap(), HashCheck::Redact);
+}
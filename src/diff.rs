@@ -0,0 +1,148 @@
+use crate::{Event, Parser};
+
+/// Where two JSON encodings stop being the same, see `first_divergence`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiffReport {
+    /// The first byte that differs.
+    pub offset: usize,
+    /// The JSON pointer of the value or key holding that byte.
+    pub pointer: String,
+    /// The token of the first input at `offset`.
+    pub left: String,
+    /// The token of the second input at `offset`.
+    pub right: String,
+}
+
+/// Find the first difference between two encodings of what should be the same value, useful
+/// when another server's signature does not match.
+///
+/// Returns `None` when the inputs are identical.
+pub fn first_divergence(a: &[u8], b: &[u8]) -> Option<DiffReport> {
+    let offset = a
+        .iter()
+        .zip(b)
+        .position(|(x, y)| x != y)
+        .unwrap_or_else(|| a.len().min(b.len()));
+    if offset == a.len() && offset == b.len() {
+        return None;
+    }
+
+    let (pointer, left) = locate(a, offset);
+    let (_, right) = locate(b, offset);
+    Some(DiffReport {
+        offset,
+        pointer,
+        left,
+        right,
+    })
+}
+
+enum Frame {
+    /// The index the next element gets.
+    Array(usize),
+    /// The key of the current entry.
+    Object(String),
+}
+
+/// The pointer and text of the token of `input` containing the byte at `offset`.
+fn locate(input: &[u8], offset: usize) -> (String, String) {
+    if offset >= input.len() {
+        return (String::new(), "end of input".to_owned());
+    }
+
+    let mut parser = Parser::new(input);
+    let mut frames: Vec<Frame> = vec![];
+    loop {
+        let start = parser.offset();
+        let event = match parser.next_event() {
+            Ok(Some(event)) => event,
+            // The document is broken or done before `offset`, show what is there.
+            _ => return (pointer(&frames), token(&input[start..])),
+        };
+        let end = parser.offset();
+
+        if let Event::Key(key) = &event {
+            if let Some(Frame::Object(current)) = frames.last_mut() {
+                *current = key.unescape().into_owned();
+            }
+        }
+        if offset < end {
+            // The end of a container belongs to the container, not its last entry.
+            let depth = match event {
+                Event::ObjectEnd | Event::ArrayEnd => frames.len() - 1,
+                _ => frames.len(),
+            };
+            return (pointer(&frames[..depth]), token(&input[start..end]));
+        }
+
+        match event {
+            Event::ObjectStart => frames.push(Frame::Object(String::new())),
+            Event::ArrayStart => frames.push(Frame::Array(0)),
+            Event::ObjectEnd | Event::ArrayEnd => {
+                frames.pop();
+                value_done(&mut frames);
+            }
+            Event::Key(_) => {}
+            Event::String(_) | Event::Number(_) | Event::Bool(_) | Event::Null => {
+                value_done(&mut frames)
+            }
+        }
+    }
+}
+
+fn value_done(frames: &mut [Frame]) {
+    if let Some(Frame::Array(idx)) = frames.last_mut() {
+        *idx += 1;
+    }
+}
+
+fn pointer(frames: &[Frame]) -> String {
+    frames
+        .iter()
+        .map(|frame| match frame {
+            Frame::Array(idx) => format!("/{}", idx),
+            Frame::Object(key) => format!("/{}", key.replace('~', "~0").replace('/', "~1")),
+        })
+        .collect()
+}
+
+/// The token in `span` without the punctuation in front of it, shortened for display.
+fn token(span: &[u8]) -> String {
+    let start = span
+        .iter()
+        .position(|b| !matches!(b, b',' | b':' | b' ' | b'\t' | b'\n' | b'\r'))
+        .unwrap_or(span.len());
+    let span = &span[start..];
+    String::from_utf8_lossy(&span[..span.len().min(64)]).into_owned()
+}
+
+#[test]
+fn finds_first_divergence() {
+    let ours = br#"{"content":{"body":"hi","msgtype":"m.text"},"depth":12}"#;
+    let theirs = br#"{"content":{"body":"hi","msgtype":"m.notice"},"depth":12}"#;
+
+    assert_eq!(
+        first_divergence(ours, theirs),
+        Some(DiffReport {
+            offset: 37,
+            pointer: "/content/msgtype".to_owned(),
+            left: r#""m.text""#.to_owned(),
+            right: r#""m.notice""#.to_owned(),
+        })
+    );
+    assert_eq!(first_divergence(ours, ours), None);
+
+    let report = first_divergence(br#"{"a":[1,2,30]}"#, br#"{"a":[1,2,31]}"#).unwrap();
+    assert_eq!(report.pointer, "/a/2");
+    assert_eq!((report.left.as_str(), report.right.as_str()), ("30", "31"));
+
+    let report = first_divergence(br#"{"a":1}"#, br#"{"a":1,"b":2}"#).unwrap();
+    assert_eq!(report.pointer, "");
+    assert_eq!(
+        (report.left.as_str(), report.right.as_str()),
+        ("}", r#""b""#)
+    );
+
+    let report = first_divergence(b"[1]", b"[1] ").unwrap();
+    assert_eq!(report.left, "end of input");
+}
@@ -0,0 +1,101 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use futures::sink::Sink;
+use serde::Serialize;
+
+use crate::{Error, Options, Result};
+
+/// Serializes every item sent to it as canonical JSON and passes it on as one `Bytes` chunk to
+/// a `futures::Sink`, like the sink half of a websocket.
+///
+/// `poll_ready` is the one of the inner sink, so a slow peer holds up the sender instead of
+/// documents piling up in memory: at most the document being sent is buffered. Sinks that fail
+/// with another error type can be adapted with `SinkExt::sink_map_err`, for example
+/// `sink_map_err(Error::io)`. A document that fails to serialize is not sent and the sink can
+/// still be used.
+pub struct DocumentSink<S> {
+    sink: S,
+    options: Options,
+}
+
+impl<S> DocumentSink<S> {
+    pub fn new(sink: S) -> Self {
+        Self {
+            sink,
+            options: Options::new(),
+        }
+    }
+
+    /// The options documents are serialized with, `Options::new()` by default.
+    pub fn options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.sink
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+}
+
+impl<S, T> Sink<T> for DocumentSink<S>
+where
+    S: Sink<Bytes, Error = Error> + Unpin,
+    T: Serialize,
+{
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().sink).poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<()> {
+        let this = self.get_mut();
+        let document = this.options.to_vec(&item)?;
+        Pin::new(&mut this.sink).start_send(Bytes::from(document))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().sink).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().sink).poll_close(cx)
+    }
+}
+
+#[test]
+fn document_sink() {
+    use futures::{channel::mpsc, executor::block_on, task::noop_waker_ref, SinkExt, StreamExt};
+
+    let (tx, mut rx) = mpsc::channel(0);
+    let mut sink = DocumentSink::new(tx.sink_map_err(|err| Error::Custom(err.to_string())));
+
+    block_on(sink.feed(serde_json::json!({ "b": 1, "a": 2 }))).unwrap();
+    // The channel holds one chunk, the next has to wait until it is received.
+    let mut cx = Context::from_waker(noop_waker_ref());
+    assert!(SinkExt::<u8>::poll_ready_unpin(&mut sink, &mut cx).is_pending());
+    assert_eq!(
+        block_on(rx.next()).unwrap(),
+        Bytes::from_static(br#"{"a":2,"b":1}"#)
+    );
+
+    assert!(block_on(sink.feed(&[1.5])).is_err());
+    block_on(sink.feed(&["x"])).unwrap();
+    drop(sink);
+    assert_eq!(
+        block_on(rx.collect::<Vec<_>>()),
+        vec![Bytes::from_static(br#"["x"]"#)]
+    );
+}
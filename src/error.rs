@@ -1,11 +1,41 @@
 use std::{error::Error as StdError, fmt, io};
 
+#[cfg(all(feature = "ser", feature = "de"))]
+use crate::GoldenMismatch;
+use crate::Violation;
+
+/// The errors of this crate. Some variants only exist with some features enabled, so matching
+/// on it needs a wildcard arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     Custom(String),
     IOError(io::Error),
     InvalidInput(String),
     SizeLimit,
+    /// Sorting the keys of objects needed more memory than `Options` allow.
+    BufferLimit,
+    /// The input is not valid JSON, `offset` is the byte where the problem was found.
+    Syntax {
+        msg: String,
+        offset: usize,
+    },
+    /// An error found on a specific line of a multi-line input, counting from 1.
+    Line {
+        line: usize,
+        error: Box<Error>,
+    },
+    /// An integer outside of the range the profile allows. `pointer` is the JSON pointer of the
+    /// field holding it, empty for the root.
+    IntegerOutOfRange {
+        pointer: String,
+        value: String,
+    },
+    /// The value failed validation, every problem that was found is listed.
+    Violations(Vec<Violation>),
+    /// Golden cases whose canonical form was not the expected one, see `check_golden`.
+    #[cfg(all(feature = "ser", feature = "de"))]
+    GoldenMismatch(Vec<GoldenMismatch>),
 }
 
 impl fmt::Display for Error {
@@ -14,7 +44,41 @@ impl fmt::Display for Error {
             Error::Custom(msg) => write!(f, "{}", msg),
             Error::IOError(err) => write!(f, "{}", err),
             Error::InvalidInput(msg) => write!(f, "Found invalid input: {}", msg),
-            Error::SizeLimit => write!(f, "canonical JSON larger than 65,535 bytes is not allowed"),
+            Error::SizeLimit => write!(
+                f,
+                "canonical JSON larger than the size limit is not allowed"
+            ),
+            Error::BufferLimit => write!(
+                f,
+                "sorting object keys needed more memory than the buffer limit allows"
+            ),
+            Error::Syntax { msg, offset } => write!(f, "{} at byte {}", msg, offset),
+            Error::Line { line, error } => write!(f, "line {}: {}", line, error),
+            Error::IntegerOutOfRange { pointer, value } if pointer.is_empty() => write!(
+                f,
+                "integer {} is outside of the canonical JSON range",
+                value
+            ),
+            Error::IntegerOutOfRange { pointer, value } => write!(
+                f,
+                "integer {} at {} is outside of the canonical JSON range",
+                value, pointer
+            ),
+            Error::Violations(violations) => {
+                write!(f, "value failed validation")?;
+                for (idx, violation) in violations.iter().enumerate() {
+                    write!(f, "{} {}", if idx == 0 { ":" } else { ";" }, violation)?;
+                }
+                Ok(())
+            }
+            #[cfg(all(feature = "ser", feature = "de"))]
+            Error::GoldenMismatch(mismatches) => {
+                write!(f, "{} golden cases did not match", mismatches.len())?;
+                for mismatch in mismatches {
+                    write!(f, "\n{}", mismatch)?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -28,10 +92,52 @@ impl serde::ser::Error for Error {
     }
 }
 
+impl serde::de::Error for Error {
+    fn custom<T>(msg: T) -> Self
+    where
+        T: fmt::Display,
+    {
+        Self::Custom(msg.to_string())
+    }
+}
+
 impl StdError for Error {}
 
 impl Error {
+    /// Prepend `segment` to the pointer of errors that name a field, done while the error is
+    /// passed up through the containers so the path costs nothing until something fails.
+    pub(crate) fn at(self, segment: &str) -> Self {
+        match self {
+            Error::IntegerOutOfRange { pointer, value } => Error::IntegerOutOfRange {
+                pointer: format!(
+                    "/{}{}",
+                    segment.replace('~', "~0").replace('/', "~1"),
+                    pointer
+                ),
+                value,
+            },
+            err => err,
+        }
+    }
+
     pub fn io(err: io::Error) -> Self {
-        Self::IOError(err)
+        match err
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<Error>())
+        {
+            Some(Error::SizeLimit) => Self::SizeLimit,
+            _ => Self::IOError(err),
+        }
     }
 }
+
+pub(crate) fn key_must_be_a_string() -> Error {
+    Error::Custom("key must be a string".to_owned())
+}
+
+pub(crate) fn duplicate_key(key: &str) -> Error {
+    Error::InvalidInput(format!(
+        "duplicate key `{}` is not valid in canonical JSON",
+        key
+    ))
+}
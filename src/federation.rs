@@ -0,0 +1,291 @@
+use std::{
+    io::{self, Write},
+    time::SystemTime,
+};
+
+use serde::Serialize;
+
+use crate::{
+    system_time_to_millis, to_canonical_vec, Error, LimitedWriter, Options, Result,
+    MAX_SAFE_INTEGER,
+};
+
+/// The largest a complete event may be when sent over federation, including its `signatures`
+/// and `hashes`.
+pub const MAX_PDU_SIZE: usize = 65_536;
+
+/// The most PDUs a federation transaction may hold.
+pub const MAX_TRANSACTION_PDUS: usize = 50;
+
+/// The most EDUs a federation transaction may hold.
+pub const MAX_TRANSACTION_EDUS: usize = 100;
+
+/// The canonical size of an event measured against `MAX_PDU_SIZE`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FederationSize {
+    /// The event fits, holding its length in bytes.
+    Sendable(usize),
+    /// The event is `over` bytes larger than `MAX_PDU_SIZE`.
+    TooLarge { len: usize, over: usize },
+}
+
+impl FederationSize {
+    pub fn is_sendable(&self) -> bool {
+        matches!(self, FederationSize::Sendable(_))
+    }
+}
+
+/// Measure the canonical encoding of the whole `event` against the spec's federation limit.
+///
+/// This is separate from the 65,535 byte guard of `to_canonical_string`; the event is serialized
+/// without that guard into a writer that drops the output, only its length is kept. Objects are
+/// still buffered while their keys are sorted.
+pub fn is_federation_sendable<T>(event: &T) -> Result<FederationSize>
+where
+    T: ?Sized + Serialize,
+{
+    let len = Options::new()
+        .size_limit(None)
+        .to_writer(io::sink(), event)?;
+    if len > MAX_PDU_SIZE {
+        Ok(FederationSize::TooLarge {
+            len,
+            over: len - MAX_PDU_SIZE,
+        })
+    } else {
+        Ok(FederationSize::Sendable(len))
+    }
+}
+
+/// Collects PDUs and EDUs into the body of a federation `/send/{txnId}` request.
+///
+/// Every PDU and EDU is serialized as canonical JSON once when it is added, the body is then
+/// written piece by piece without serializing or buffering it as a whole. The fields of the
+/// body are in canonical order so the body is canonical JSON too.
+#[derive(Clone, Debug)]
+pub struct TransactionBuilder {
+    origin: String,
+    origin_server_ts: Option<i64>,
+    size_limit: Option<usize>,
+    pdus: Vec<Vec<u8>>,
+    edus: Vec<Vec<u8>>,
+    /// The bytes of `pdus` and `edus` together.
+    len: usize,
+}
+
+impl TransactionBuilder {
+    /// Start an empty transaction sent by the server `origin`.
+    pub fn new<O: Into<String>>(origin: O) -> Self {
+        Self {
+            origin: origin.into(),
+            origin_server_ts: None,
+            size_limit: None,
+            pdus: vec![],
+            edus: vec![],
+            len: 0,
+        }
+    }
+
+    /// The timestamp in milliseconds since the unix epoch, the current time when the body is
+    /// written if it is not set.
+    pub fn origin_server_ts(mut self, ts: i64) -> Self {
+        self.origin_server_ts = Some(ts);
+        self
+    }
+
+    /// The largest the whole body may be, `None`, the default, only limits the number of PDUs
+    /// and EDUs.
+    pub fn size_limit(mut self, limit: Option<usize>) -> Self {
+        self.size_limit = limit;
+        self
+    }
+
+    /// Add a PDU, failing with `Error::SizeLimit` if it is larger than `MAX_PDU_SIZE`.
+    ///
+    /// Returns `false` without adding it if the transaction already holds `MAX_TRANSACTION_PDUS`
+    /// or the body would grow past the size limit, the transaction is full and the PDU goes into
+    /// the next one.
+    pub fn push_pdu<T>(&mut self, pdu: &T) -> Result<bool>
+    where
+        T: ?Sized + Serialize,
+    {
+        let pdu = Options::new().size_limit(Some(MAX_PDU_SIZE)).to_vec(pdu)?;
+        if self.pdus.len() == MAX_TRANSACTION_PDUS || !self.fits(pdu.len(), true)? {
+            return Ok(false);
+        }
+        self.len += pdu.len();
+        self.pdus.push(pdu);
+        Ok(true)
+    }
+
+    /// Add an EDU, returns `false` without adding it if the transaction already holds
+    /// `MAX_TRANSACTION_EDUS` or the body would grow past the size limit.
+    pub fn push_edu<T>(&mut self, edu: &T) -> Result<bool>
+    where
+        T: ?Sized + Serialize,
+    {
+        let edu = Options::new().size_limit(None).to_vec(edu)?;
+        if self.edus.len() == MAX_TRANSACTION_EDUS || !self.fits(edu.len(), false)? {
+            return Ok(false);
+        }
+        self.len += edu.len();
+        self.edus.push(edu);
+        Ok(true)
+    }
+
+    pub fn pdu_count(&self) -> usize {
+        self.pdus.len()
+    }
+
+    pub fn edu_count(&self) -> usize {
+        self.edus.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pdus.is_empty() && self.edus.is_empty()
+    }
+
+    /// Whether the body still fits the size limit with one more PDU, or EDU, of `len` bytes. An
+    /// item that doesn't even fit into an empty transaction is an error, it would never be sent.
+    fn fits(&self, len: usize, pdu: bool) -> Result<bool> {
+        let limit = match self.size_limit {
+            Some(limit) => limit,
+            None => return Ok(true),
+        };
+        // Room for the longest timestamp when it is only taken once the body is written.
+        let ts_len = match self.origin_server_ts {
+            Some(ts) => ts.to_string().len(),
+            None => MAX_SAFE_INTEGER.to_string().len(),
+        };
+        let overhead = r#"{"edus":[],"origin":"","origin_server_ts":,"pdus":[]}"#.len()
+            + to_canonical_vec(&self.origin)?.len()
+            - 2
+            + ts_len;
+        let (pdus, edus) = if pdu {
+            (self.pdus.len() + 1, self.edus.len())
+        } else {
+            (self.pdus.len(), self.edus.len() + 1)
+        };
+        // A comma before every item that is not the first of its array.
+        let commas = pdus.saturating_sub(1) + edus.saturating_sub(1);
+        if overhead + len > limit {
+            return Err(Error::SizeLimit);
+        }
+        Ok(overhead + self.len + len + commas <= limit)
+    }
+
+    /// Write the canonical JSON body of the transaction to `writer`, returning the number of
+    /// bytes written.
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<usize> {
+        let ts = match self.origin_server_ts {
+            Some(ts) => ts,
+            None => system_time_to_millis(SystemTime::now())?,
+        };
+        let origin = to_canonical_vec(&self.origin)?;
+        let mut writer = LimitedWriter::new(writer, None);
+        self.write_body(&mut writer, &origin, ts)
+            .map_err(Error::io)?;
+        Ok(writer.written())
+    }
+
+    fn write_body<W: Write>(&self, writer: &mut W, origin: &[u8], ts: i64) -> io::Result<()> {
+        writer.write_all(br#"{"edus":["#)?;
+        write_items(writer, &self.edus)?;
+        writer.write_all(br#"],"origin":"#)?;
+        writer.write_all(origin)?;
+        write!(writer, r#","origin_server_ts":{},"pdus":["#, ts)?;
+        write_items(writer, &self.pdus)?;
+        writer.write_all(b"]}")
+    }
+
+    /// The canonical JSON body of the transaction.
+    pub fn to_vec(&self) -> Result<Vec<u8>> {
+        let mut body = Vec::with_capacity(self.len + 128);
+        self.write_to(&mut body)?;
+        Ok(body)
+    }
+}
+
+/// Write the already serialized `items` separated by commas.
+fn write_items<W: Write>(writer: &mut W, items: &[Vec<u8>]) -> io::Result<()> {
+    for (idx, item) in items.iter().enumerate() {
+        if idx > 0 {
+            writer.write_all(b",")?;
+        }
+        writer.write_all(item)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn sendable_event() {
+    let event = serde_json::json!({
+        "content": { "body": "hello" },
+        "hashes": { "sha256": "abc" },
+        "signatures": { "example.org": { "ed25519:1": "def" } },
+        "type": "m.room.message"
+    });
+
+    let len = crate::to_canonical_string(&event).unwrap().len();
+    assert_eq!(
+        is_federation_sendable(&event).unwrap(),
+        FederationSize::Sendable(len)
+    )
+}
+
+#[test]
+fn too_large_event() {
+    // `{"body":"` + body + `"}` is 11 bytes of overhead.
+    let event = serde_json::json!({ "body": "a".repeat(MAX_PDU_SIZE) });
+
+    assert_eq!(
+        is_federation_sendable(&event).unwrap(),
+        FederationSize::TooLarge {
+            len: MAX_PDU_SIZE + 11,
+            over: 11
+        }
+    )
+}
+
+#[cfg(feature = "de")]
+#[test]
+fn transactions() {
+    let mut txn = TransactionBuilder::new("example.org").origin_server_ts(1_600_000_000_000);
+    assert!(txn.is_empty());
+    assert!(txn
+        .push_pdu(&serde_json::json!({ "type": "m.room.message", "depth": 2 }))
+        .unwrap());
+    assert!(txn
+        .push_edu(&serde_json::json!({ "edu_type": "m.typing", "content": {} }))
+        .unwrap());
+    assert!(txn.push_pdu(&[1]).unwrap());
+
+    let body = txn.to_vec().unwrap();
+    assert_eq!(
+        std::str::from_utf8(&body).unwrap(),
+        r#"{"edus":[{"content":{},"edu_type":"m.typing"}],"origin":"example.org","origin_server_ts":1600000000000,"pdus":[{"depth":2,"type":"m.room.message"},[1]]}"#
+    );
+    assert!(crate::is_canonical(std::str::from_utf8(&body).unwrap()));
+    assert_eq!(txn.write_to(io::sink()).unwrap(), body.len());
+
+    // Exactly the size of the body so far, one more PDU does not fit.
+    let mut full = txn.clone().size_limit(Some(body.len()));
+    assert!(!full.push_pdu(&[1]).unwrap());
+    assert_eq!(full.pdu_count(), 2);
+    let mut small = TransactionBuilder::new("example.org").size_limit(Some(90));
+    assert!(matches!(
+        small.push_pdu(&"a".repeat(10)),
+        Err(Error::SizeLimit)
+    ));
+    assert!(matches!(
+        txn.push_pdu(&"a".repeat(MAX_PDU_SIZE)),
+        Err(Error::SizeLimit)
+    ));
+
+    for _ in 2..MAX_TRANSACTION_PDUS {
+        assert!(txn.push_pdu(&1).unwrap());
+    }
+    assert!(!txn.push_pdu(&1).unwrap());
+    assert_eq!(txn.pdu_count(), MAX_TRANSACTION_PDUS);
+    assert_eq!(txn.edu_count(), 1);
+}
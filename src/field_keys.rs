@@ -0,0 +1,47 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use serde::ser;
+
+use crate::{Result, Serializer};
+
+/// The most field names remembered per thread, a `&'static str` can also come from a leaked
+/// `String` so there is no telling how many there are.
+const MAX_CACHED: usize = 1024;
+
+thread_local! {
+    /// The quoted and escaped form of struct field names by the address and length of the name.
+    static QUOTED: RefCell<HashMap<(usize, usize), Rc<[u8]>>> = RefCell::new(HashMap::new());
+}
+
+/// Append `key` quoted and escaped to `buf`. Struct field names are the same string every
+/// time, so they are only escaped the first time and copied after that.
+pub(crate) fn write_quoted(buf: &mut Vec<u8>, key: &'static str) -> Result<()> {
+    QUOTED.with(|cache| {
+        let id = (key.as_ptr() as usize, key.len());
+        let cached = cache.borrow().get(&id).cloned();
+        match cached {
+            Some(quoted) => buf.extend_from_slice(&quoted),
+            None => {
+                let start = buf.len();
+                ser::Serializer::serialize_str(&mut Serializer::new(&mut *buf), key)?;
+                let mut cache = cache.borrow_mut();
+                if cache.len() < MAX_CACHED {
+                    cache.insert(id, Rc::from(&buf[start..]));
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn quoted_field_names() {
+    static KEY: &str = "a\"\u{1}";
+
+    let mut buf = vec![];
+    write_quoted(&mut buf, KEY).unwrap();
+    write_quoted(&mut buf, KEY).unwrap();
+    write_quoted(&mut buf, "plain").unwrap();
+    assert_eq!(buf, br#""a\"\u0001""a\"\u0001""plain""#.to_vec());
+    assert!(QUOTED.with(|cache| cache.borrow().contains_key(&(KEY.as_ptr() as usize, 3))));
+}
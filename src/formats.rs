@@ -0,0 +1,72 @@
+use serde::Serialize;
+
+use crate::{lint_value, to_canonical_string, Error, Result, Severity};
+
+/// Parse a YAML document and emit it as canonical JSON. Anything canonical JSON can not
+/// represent, like a float, fails with the JSON pointer of the offending value.
+#[cfg(feature = "yaml")]
+pub fn canonicalize_yaml_str(yaml: &str) -> Result<String> {
+    let value = serde_yaml::from_str::<serde_yaml::Value>(yaml)
+        .map_err(|e| Error::Custom(e.to_string()))?;
+    canonicalize(&value)
+}
+
+/// Parse a TOML document and emit it as canonical JSON. Anything canonical JSON can not
+/// represent, like a float, fails with the JSON pointer of the offending value.
+#[cfg(feature = "toml")]
+pub fn canonicalize_toml_str(toml: &str) -> Result<String> {
+    let value = toml::from_str::<toml::Value>(toml).map_err(|e| Error::Custom(e.to_string()))?;
+    canonicalize(&value)
+}
+
+fn canonicalize<T: Serialize>(value: &T) -> Result<String> {
+    if let Some(violation) = lint_value(value)?
+        .into_iter()
+        .find(|v| v.severity == Severity::Error)
+    {
+        return Err(Error::InvalidInput(violation.to_string()));
+    }
+    to_canonical_string(value)
+}
+
+#[cfg(feature = "yaml")]
+#[test]
+fn yaml_to_canonical() {
+    let yaml = "
+type: m.policy.rule.user
+content:
+  recommendation: m.ban
+  entity: '@spam:example.org'
+  weight: 10
+";
+    assert_eq!(
+        canonicalize_yaml_str(yaml).unwrap(),
+        r#"{"content":{"entity":"@spam:example.org","recommendation":"m.ban","weight":10},"type":"m.policy.rule.user"}"#
+    );
+
+    assert!(matches!(
+        canonicalize_yaml_str("bridge:\n  ratio: 0.5\n"),
+        Err(Error::InvalidInput(msg)) if msg.starts_with("/bridge/ratio: float")
+    ));
+}
+
+#[cfg(feature = "toml")]
+#[test]
+fn toml_to_canonical() {
+    let toml = r#"
+type = "m.policy.rule.user"
+
+[content]
+recommendation = "m.ban"
+entity = "@spam:example.org"
+"#;
+    assert_eq!(
+        canonicalize_toml_str(toml).unwrap(),
+        r#"{"content":{"entity":"@spam:example.org","recommendation":"m.ban"},"type":"m.policy.rule.user"}"#
+    );
+
+    assert!(matches!(
+        canonicalize_toml_str("[bridge]\nratio = 0.5\n"),
+        Err(Error::InvalidInput(msg)) if msg.starts_with("/bridge/ratio: float")
+    ));
+}
@@ -1,6 +1,6 @@
 use std::io;
 
-use crate::serializer::CharEscape;
+use crate::{serializer::CharEscape, Sink};
 
 /// This trait abstracts away serializing the JSON control characters, which allows the user to
 /// optionally pretty print the JSON output.
@@ -9,7 +9,7 @@ pub trait Formatter {
     #[inline]
     fn write_null<W>(&mut self, writer: &mut W) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         writer.write_all(b"null")
     }
@@ -18,7 +18,7 @@ pub trait Formatter {
     #[inline]
     fn write_bool<W>(&mut self, writer: &mut W, value: bool) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         let s = if value {
             b"true" as &[u8]
@@ -32,7 +32,7 @@ pub trait Formatter {
     #[inline]
     fn write_i8<W>(&mut self, writer: &mut W, value: i8) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         let mut buffer = itoa::Buffer::new();
         let s = buffer.format(value);
@@ -43,7 +43,7 @@ pub trait Formatter {
     #[inline]
     fn write_i16<W>(&mut self, writer: &mut W, value: i16) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         let mut buffer = itoa::Buffer::new();
         let s = buffer.format(value);
@@ -54,7 +54,7 @@ pub trait Formatter {
     #[inline]
     fn write_i32<W>(&mut self, writer: &mut W, value: i32) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         let mut buffer = itoa::Buffer::new();
         let s = buffer.format(value);
@@ -65,7 +65,7 @@ pub trait Formatter {
     #[inline]
     fn write_i64<W>(&mut self, writer: &mut W, value: i64) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         let mut buffer = itoa::Buffer::new();
         let s = buffer.format(value);
@@ -76,7 +76,7 @@ pub trait Formatter {
     #[inline]
     fn write_u8<W>(&mut self, writer: &mut W, value: u8) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         let mut buffer = itoa::Buffer::new();
         let s = buffer.format(value);
@@ -87,7 +87,7 @@ pub trait Formatter {
     #[inline]
     fn write_u16<W>(&mut self, writer: &mut W, value: u16) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         let mut buffer = itoa::Buffer::new();
         let s = buffer.format(value);
@@ -98,7 +98,7 @@ pub trait Formatter {
     #[inline]
     fn write_u32<W>(&mut self, writer: &mut W, value: u32) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         let mut buffer = itoa::Buffer::new();
         let s = buffer.format(value);
@@ -109,7 +109,7 @@ pub trait Formatter {
     #[inline]
     fn write_u64<W>(&mut self, writer: &mut W, value: u64) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         let mut buffer = itoa::Buffer::new();
         let s = buffer.format(value);
@@ -120,7 +120,7 @@ pub trait Formatter {
     #[inline]
     fn write_f32<W>(&mut self, writer: &mut W, value: f32) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         let mut buffer = ryu::Buffer::new();
         let s = buffer.format_finite(value);
@@ -131,7 +131,7 @@ pub trait Formatter {
     #[inline]
     fn write_f64<W>(&mut self, writer: &mut W, value: f64) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         let mut buffer = ryu::Buffer::new();
         let s = buffer.format_finite(value);
@@ -142,7 +142,7 @@ pub trait Formatter {
     #[inline]
     fn write_number_str<W>(&mut self, writer: &mut W, value: &str) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         writer.write_all(value.as_bytes())
     }
@@ -152,7 +152,7 @@ pub trait Formatter {
     #[inline]
     fn begin_string<W>(&mut self, writer: &mut W) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         writer.write_all(b"\"")
     }
@@ -162,7 +162,7 @@ pub trait Formatter {
     #[inline]
     fn end_string<W>(&mut self, writer: &mut W) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         writer.write_all(b"\"")
     }
@@ -172,7 +172,7 @@ pub trait Formatter {
     #[inline]
     fn write_string_fragment<W>(&mut self, writer: &mut W, fragment: &str) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         writer.write_all(fragment.as_bytes())
     }
@@ -181,7 +181,7 @@ pub trait Formatter {
     #[inline]
     fn write_char_escape<W>(&mut self, writer: &mut W, char_escape: CharEscape) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         use self::CharEscape::*;
 
@@ -216,7 +216,7 @@ pub trait Formatter {
     #[inline]
     fn begin_array<W>(&mut self, writer: &mut W) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         writer.write_all(b"[")
     }
@@ -226,7 +226,7 @@ pub trait Formatter {
     #[inline]
     fn end_array<W>(&mut self, writer: &mut W) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         writer.write_all(b"]")
     }
@@ -236,7 +236,7 @@ pub trait Formatter {
     #[inline]
     fn begin_array_value<W>(&mut self, writer: &mut W, first: bool) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         if first {
             Ok(())
@@ -249,7 +249,7 @@ pub trait Formatter {
     #[inline]
     fn end_array_value<W>(&mut self, _writer: &mut W) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         Ok(())
     }
@@ -259,7 +259,7 @@ pub trait Formatter {
     #[inline]
     fn begin_object<W>(&mut self, writer: &mut W) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         writer.write_all(b"{")
     }
@@ -269,7 +269,7 @@ pub trait Formatter {
     #[inline]
     fn end_object<W>(&mut self, writer: &mut W) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         writer.write_all(b"}")
     }
@@ -278,7 +278,7 @@ pub trait Formatter {
     #[inline]
     fn begin_object_key<W>(&mut self, writer: &mut W, first: bool) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         if first {
             Ok(())
@@ -293,7 +293,7 @@ pub trait Formatter {
     #[inline]
     fn end_object_key<W>(&mut self, _writer: &mut W) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         Ok(())
     }
@@ -304,7 +304,7 @@ pub trait Formatter {
     #[inline]
     fn begin_object_value<W>(&mut self, writer: &mut W) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         writer.write_all(b":")
     }
@@ -313,7 +313,7 @@ pub trait Formatter {
     #[inline]
     fn end_object_value<W>(&mut self, _writer: &mut W) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         Ok(())
     }
@@ -323,7 +323,7 @@ pub trait Formatter {
     #[inline]
     fn write_raw_fragment<W>(&mut self, writer: &mut W, fragment: &str) -> io::Result<()>
     where
-        W: ?Sized + io::Write,
+        W: ?Sized + Sink,
     {
         writer.write_all(fragment.as_bytes())
     }
@@ -0,0 +1,156 @@
+use crate::{CanonicalJsonObject, CanonicalJsonValue, MAX_SAFE_INTEGER, MIN_SAFE_INTEGER};
+
+const EVENT_TYPES: &[&str] = &[
+    "m.room.message",
+    "m.room.member",
+    "m.room.topic",
+    "m.reaction",
+    "org.example.custom",
+];
+
+/// Characters strings and keys are made of: ASCII, characters that have to be escaped, and ones
+/// that take two, three or four bytes of UTF-8 or a UTF-16 surrogate pair.
+const CHARS: &str = "abzA0_. \"\\/\n\t\u{0}\u{1f}\u{7f}éß€中\u{fb33}\u{ffff}😀\u{1f3f3}";
+
+/// Generates random but realistic Matrix events from a seed, the same seed always gives the
+/// same events on every platform and version of the crate.
+///
+/// Every event is valid canonical JSON: integers stay within `MIN_SAFE_INTEGER..=
+/// MAX_SAFE_INTEGER` and `content` nests objects and arrays a few levels deep with keys made
+/// of arbitrary Unicode.
+#[derive(Clone, Debug)]
+pub struct EventGenerator {
+    state: u64,
+}
+
+impl EventGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// The next event.
+    pub fn event(&mut self) -> CanonicalJsonObject {
+        let mut event = CanonicalJsonObject::new();
+        let kind = EVENT_TYPES[self.below(EVENT_TYPES.len())];
+        let server = format!("server{}.example.org", self.below(4));
+        event.insert("type".to_owned(), kind.into());
+        event.insert(
+            "room_id".to_owned(),
+            format!("!{}:{}", self.id(), server).into(),
+        );
+        event.insert(
+            "sender".to_owned(),
+            format!("@{}:{}", self.id(), server).into(),
+        );
+        event.insert("event_id".to_owned(), format!("${}", self.id()).into());
+        event.insert(
+            "origin_server_ts".to_owned(),
+            CanonicalJsonValue::Integer(1_500_000_000_000 + self.below(1 << 40) as i64),
+        );
+        event.insert(
+            "depth".to_owned(),
+            CanonicalJsonValue::Integer(self.below(1 << 20) as i64),
+        );
+        if kind == "m.room.member" {
+            event.insert(
+                "state_key".to_owned(),
+                format!("@{}:{}", self.id(), server).into(),
+            );
+        }
+        event.insert("content".to_owned(), self.object(3).into());
+        event
+    }
+
+    /// SplitMix64, small and good enough for test data.
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`.
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    fn id(&mut self) -> String {
+        (0..12)
+            .map(|_| (b'a' + self.below(26) as u8) as char)
+            .collect()
+    }
+
+    fn string(&mut self) -> String {
+        let len = self.below(12);
+        let chars = CHARS.chars().count();
+        (0..len)
+            .map(|_| {
+                CHARS
+                    .chars()
+                    .nth(self.below(chars))
+                    .expect("index is in bounds")
+            })
+            .collect()
+    }
+
+    fn integer(&mut self) -> i64 {
+        match self.below(4) {
+            0 => MAX_SAFE_INTEGER,
+            1 => MIN_SAFE_INTEGER,
+            _ => self.below(2_001) as i64 - 1_000,
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> CanonicalJsonObject {
+        (0..self.below(6))
+            .map(|_| (self.string(), self.value(depth)))
+            .collect()
+    }
+
+    fn value(&mut self, depth: usize) -> CanonicalJsonValue {
+        let kinds = if depth == 0 { 4 } else { 6 };
+        match self.below(kinds) {
+            0 => CanonicalJsonValue::Null,
+            1 => CanonicalJsonValue::Bool(self.below(2) == 0),
+            2 => CanonicalJsonValue::Integer(self.integer()),
+            3 => CanonicalJsonValue::String(self.string()),
+            4 => CanonicalJsonValue::Array(
+                (0..self.below(4)).map(|_| self.value(depth - 1)).collect(),
+            ),
+            _ => CanonicalJsonValue::Object(self.object(depth - 1)),
+        }
+    }
+}
+
+impl Iterator for EventGenerator {
+    type Item = CanonicalJsonObject;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.event())
+    }
+}
+
+#[cfg(all(feature = "ser", feature = "de"))]
+#[test]
+fn generated_events_round_trip() {
+    use crate::{from_slice, is_canonical, to_canonical_string};
+
+    assert_eq!(
+        EventGenerator::new(7).take(5).collect::<Vec<_>>(),
+        EventGenerator::new(7).take(5).collect::<Vec<_>>()
+    );
+    assert_ne!(
+        EventGenerator::new(7).event(),
+        EventGenerator::new(8).event()
+    );
+
+    for event in EventGenerator::new(0x5EED).take(500) {
+        let json = to_canonical_string(&event).unwrap();
+        assert!(is_canonical(&json), "{}", json);
+        assert_eq!(
+            from_slice(json.as_bytes()).unwrap(),
+            CanonicalJsonValue::Object(event)
+        );
+    }
+}
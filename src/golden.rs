@@ -0,0 +1,148 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{first_divergence, is_canonical, to_canonical_cow, DiffReport, Error, Result};
+
+/// A golden case whose canonical form is not what was expected, see `check_golden`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GoldenMismatch {
+    /// The directory of the case.
+    pub case: PathBuf,
+    /// The contents of `expected.canonical`.
+    pub expected: String,
+    /// What canonicalizing `input.json` gave, or the error it failed with.
+    pub actual: String,
+    /// Where `actual` first differs from `expected`.
+    pub divergence: Option<DiffReport>,
+}
+
+impl fmt::Display for GoldenMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}:", self.case.display())?;
+        writeln!(f, "  expected: {}", self.expected)?;
+        write!(f, "  actual:   {}", self.actual)?;
+        if let Some(diff) = &self.divergence {
+            write!(
+                f,
+                "\n  first difference at byte {} ({:?}): expected `{}` found `{}`",
+                diff.offset, diff.pointer, diff.left, diff.right
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Run every golden case in `dir`, a directory with one subdirectory per case holding an
+/// `input.json` and the `expected.canonical` form of it. A single trailing newline of
+/// `expected.canonical` is ignored.
+///
+/// Fails with `Error::GoldenMismatch` listing every case that did not match, so a corpus of
+/// regression cases can be checked with `check_golden("tests/golden").unwrap()`.
+pub fn check_golden<P: AsRef<Path>>(dir: P) -> Result<()> {
+    let mut cases = fs::read_dir(dir)
+        .map_err(Error::io)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()
+        .map_err(Error::io)?;
+    cases.retain(|case| case.is_dir());
+    cases.sort();
+
+    let mut mismatches = vec![];
+    for case in cases {
+        let input = fs::read_to_string(case.join("input.json")).map_err(Error::io)?;
+        let expected = fs::read_to_string(case.join("expected.canonical")).map_err(Error::io)?;
+        let expected = expected.strip_suffix('\n').unwrap_or(&expected).to_owned();
+
+        let (actual, divergence) = match to_canonical_cow(&input) {
+            Ok(actual) => match first_divergence(expected.as_bytes(), actual.as_bytes()) {
+                None => continue,
+                divergence => (actual.into_owned(), divergence),
+            },
+            Err(err) => (format!("error: {}", err), None),
+        };
+        mismatches.push(GoldenMismatch {
+            case,
+            expected,
+            actual,
+            divergence,
+        });
+    }
+
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::GoldenMismatch(mismatches))
+    }
+}
+
+/// Canonicalize `input`, then canonicalize the result again and check that nothing changed,
+/// returning the canonical form. Canonicalizing has to be a fixed point, downstream test suites
+/// and the fuzz targets run their inputs through this to catch any pass that is not.
+///
+/// Fails with `Error::InvalidInput` naming the first byte that changed, or if `is_canonical`
+/// rejects the canonical form.
+pub fn check_idempotent(input: &str) -> Result<String> {
+    let once = to_canonical_cow(input)?.into_owned();
+    let twice = to_canonical_cow(&once)?;
+    if let Some(diff) = first_divergence(once.as_bytes(), twice.as_bytes()) {
+        return Err(Error::InvalidInput(format!(
+            "canonicalizing twice changed byte {} ({:?}) from `{}` to `{}`",
+            diff.offset, diff.pointer, diff.left, diff.right
+        )));
+    }
+    if !is_canonical(&once) {
+        return Err(Error::InvalidInput(format!(
+            "the canonical form `{}` is not canonical JSON",
+            once
+        )));
+    }
+    Ok(once)
+}
+
+#[test]
+fn golden_cases() {
+    let dir = std::env::temp_dir().join(format!("canonical-json-golden-{}", std::process::id()));
+    let cases = [
+        (
+            "sorted",
+            r#"{ "b": 1, "a": [true, null] }"#,
+            "{\"a\":[true,null],\"b\":1}\n",
+        ),
+        ("wrong", r#"{"a": "b"}"#, r#"{"a":"c"}"#),
+        ("float", "[1.5]", "[1.5]"),
+    ];
+    for (name, input, expected) in cases.iter() {
+        fs::create_dir_all(dir.join(name)).unwrap();
+        fs::write(dir.join(name).join("input.json"), input).unwrap();
+        fs::write(dir.join(name).join("expected.canonical"), expected).unwrap();
+    }
+
+    let res = check_golden(&dir);
+    fs::remove_dir_all(&dir).unwrap();
+    let mismatches = match res {
+        Err(Error::GoldenMismatch(mismatches)) => mismatches,
+        other => panic!("expected mismatches found {:?}", other),
+    };
+
+    assert_eq!(mismatches.len(), 2);
+    assert!(mismatches[0].case.ends_with("float"));
+    assert!(mismatches[0].actual.starts_with("error: "));
+    assert!(mismatches[1].case.ends_with("wrong"));
+    assert_eq!(mismatches[1].divergence.as_ref().unwrap().pointer, "/a");
+    assert!(mismatches[1]
+        .to_string()
+        .contains("expected `\"c\"` found `\"b\"`"));
+}
+
+#[test]
+fn idempotent_inputs() {
+    assert_eq!(
+        check_idempotent(r#" { "b": "\u00e9\/", "a": [ {"d": 1, "c": -0} ] } "#).unwrap(),
+        r#"{"a":[{"c":0,"d":1}],"b":"é/"}"#
+    );
+    assert_eq!(check_idempotent("\"\\u001F\"").unwrap(), r#""\u001f""#);
+    assert!(check_idempotent("[1.5]").is_err());
+    assert!(check_idempotent(r#"{"a":1,"a":2}"#).is_err());
+}
@@ -0,0 +1,88 @@
+use std::{collections::HashSet, sync::Arc};
+
+#[cfg(feature = "de")]
+use crate::Str;
+
+/// A cache of object keys shared as `Arc<str>`, so the same key showing up millions of times in
+/// a bulk import is only allocated once.
+///
+/// `PushParser::intern_keys` interns the keys it yields, or use it with the keys the `Parser`
+/// yields. To share one interner between threads wrap it in a `Mutex`, it does no locking
+/// itself.
+#[derive(Clone, Debug, Default)]
+pub struct KeyInterner {
+    keys: HashSet<Arc<str>>,
+}
+
+impl KeyInterner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The shared copy of `key`, allocated the first time it is seen.
+    pub fn intern(&mut self, key: &str) -> Arc<str> {
+        if let Some(key) = self.keys.get(key) {
+            return Arc::clone(key);
+        }
+        let key: Arc<str> = Arc::from(key);
+        self.keys.insert(Arc::clone(&key));
+        key
+    }
+
+    /// Like `intern` for a key produced by the `Parser`, keys without escapes are looked up
+    /// without unescaping them first.
+    #[cfg(feature = "de")]
+    pub fn intern_str(&mut self, key: &Str<'_>) -> Arc<str> {
+        self.intern(&key.unescape())
+    }
+
+    /// The number of distinct keys seen.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Forget every key, keys handed out before stay valid.
+    pub fn clear(&mut self) {
+        self.keys.clear()
+    }
+}
+
+#[cfg(feature = "de")]
+#[test]
+fn interns_repeated_keys() {
+    use crate::{Event, Parser};
+
+    let json = br#"[{"type":"m.room.message","sender":"@a:b"},{"type":"m.room.member","sender":"@c:d","type":1}]"#;
+
+    let mut interner = KeyInterner::new();
+    let keys = Parser::new(json)
+        .filter_map(|event| match event.unwrap() {
+            Event::Key(key) => Some(interner.intern_str(&key)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(interner.len(), 2);
+    assert!(Arc::ptr_eq(&keys[0], &keys[2]));
+    assert!(Arc::ptr_eq(&keys[0], &keys[4]));
+    assert!(Arc::ptr_eq(&keys[1], &keys[3]));
+
+    let mut parser = crate::PushParser::new().intern_keys(interner);
+    let mut events = parser.feed(br#"[{"type":1},{"ty"#).unwrap();
+    events.extend(parser.feed(br#"pe":2,"state_key":""}]"#).unwrap());
+    events.extend(parser.finish().unwrap());
+    let keys = events
+        .into_iter()
+        .filter_map(|event| match event {
+            crate::OwnedEvent::Key(key) => Some(key),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert!(Arc::ptr_eq(&keys[0], &keys[1]));
+    assert_eq!(&*keys[2], "state_key");
+    assert_eq!(parser.into_interner().unwrap().len(), 3);
+}
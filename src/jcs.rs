@@ -0,0 +1,109 @@
+use crate::{Error, Result};
+
+/// Format `value` the way ECMAScript's `Number.prototype.toString` does, as RFC 8785 requires.
+pub(crate) fn format_f64(value: f64) -> Result<String> {
+    format_shortest(value, format!("{:e}", value.abs()))
+}
+
+/// Like `format_f64` but with the shortest digits that read back as the same `f32`, which are
+/// fewer than those of the `f64` it widens to.
+pub(crate) fn format_f32(value: f32) -> Result<String> {
+    format_shortest(f64::from(value), format!("{:e}", value.abs()))
+}
+
+/// `sci` is the absolute value as `d.ddde-n` with the shortest digits that round trip, which is
+/// what Rust's `LowerExp` gives.
+fn format_shortest(value: f64, sci: String) -> Result<String> {
+    if !value.is_finite() {
+        return Err(Error::InvalidInput(format!(
+            "{} is not valid in JCS canonical JSON",
+            value
+        )));
+    }
+    // This also covers `-0`.
+    if value == 0.0 {
+        return Ok("0".to_owned());
+    }
+
+    let (mantissa, exp) = sci.split_at(sci.find('e').unwrap());
+    let digits = mantissa.replace('.', "");
+    let exp = exp[1..].parse::<i32>().unwrap();
+
+    // `k` and `n` are named after the variables in the ECMAScript spec, the value is
+    // `digits * 10^(n - k)`.
+    let k = digits.len() as i32;
+    let n = exp + 1;
+
+    let mut out = String::with_capacity(k as usize + 8);
+    if value < 0.0 {
+        out.push('-');
+    }
+
+    if k <= n && n <= 21 {
+        out.push_str(&digits);
+        out.extend((0..n - k).map(|_| '0'));
+    } else if 0 < n && n <= 21 {
+        out.push_str(&digits[..n as usize]);
+        out.push('.');
+        out.push_str(&digits[n as usize..]);
+    } else if -6 < n && n <= 0 {
+        out.push_str("0.");
+        out.extend((0..-n).map(|_| '0'));
+        out.push_str(&digits);
+    } else {
+        out.push_str(&digits[..1]);
+        if k > 1 {
+            out.push('.');
+            out.push_str(&digits[1..]);
+        }
+        out.push('e');
+        if n > 0 {
+            out.push('+');
+        }
+        out.push_str(&(n - 1).to_string());
+    }
+
+    Ok(out)
+}
+
+/// Compare two object keys by their UTF-16 code units, the order RFC 8785 sorts keys in.
+pub(crate) fn cmp_keys(a: &str, b: &str) -> std::cmp::Ordering {
+    a.encode_utf16().cmp(b.encode_utf16())
+}
+
+#[test]
+fn es6_number_formatting() {
+    let cases = [
+        (0.0, "0"),
+        (-0.0, "0"),
+        (1.0, "1"),
+        (-1.5, "-1.5"),
+        (4.5, "4.5"),
+        (0.002, "0.002"),
+        (0.000001, "0.000001"),
+        (1e-7, "1e-7"),
+        (1e21, "1e+21"),
+        (1e20, "100000000000000000000"),
+        (123456789012345680000.0, "123456789012345680000"),
+        (333333333.3333332, "333333333.3333332"),
+        (5e-324, "5e-324"),
+        (1.7976931348623157e308, "1.7976931348623157e+308"),
+        (-1.2345e-10, "-1.2345e-10"),
+    ];
+    for (value, expected) in cases.iter() {
+        assert_eq!(format_f64(*value).unwrap(), *expected);
+    }
+    assert!(format_f64(f64::NAN).is_err());
+    assert!(format_f64(f64::INFINITY).is_err());
+}
+
+#[test]
+fn utf16_key_order() {
+    let mut keys = vec!["\u{20ac}", "\r", "\u{1f600}", "1", "\u{80}", "\u{fb33}"];
+    keys.sort_by(|a, b| cmp_keys(a, b));
+    // The example from RFC 8785 section 3.2.3, the emoji sorts before U+FB33 in UTF-16.
+    assert_eq!(
+        keys,
+        vec!["\r", "1", "\u{80}", "\u{20ac}", "\u{1f600}", "\u{fb33}"]
+    );
+}
@@ -0,0 +1,210 @@
+use std::{
+    convert::TryFrom,
+    fs::{File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use serde::Serialize;
+
+use crate::{Error, Options, Result};
+
+/// Appends canonical JSON documents to a file, each one prefixed by its length as a big endian
+/// `u32`, so the exact bytes that were signed can be kept as an audit log and read back with a
+/// `JournalReader`.
+///
+/// Records are buffered until `sync` is called, or every `sync_every` records, which flushes
+/// them and waits for the file to reach the disk. Dropping the writer flushes but does not sync.
+pub struct JournalWriter {
+    file: BufWriter<File>,
+    options: Options,
+    sync_every: Option<usize>,
+    unsynced: usize,
+}
+
+impl JournalWriter {
+    /// Open the journal at `path` for appending, creating it if it does not exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(Error::io)?;
+        Ok(Self::new(file))
+    }
+
+    /// Append to `file`, which should be opened for appending.
+    pub fn new(file: File) -> Self {
+        Self {
+            file: BufWriter::new(file),
+            options: Options::new(),
+            sync_every: None,
+            unsynced: 0,
+        }
+    }
+
+    /// The options documents are serialized with, `Options::new()` by default.
+    pub fn options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sync after every `records` records, `None`, the default, only syncs when `sync` is
+    /// called.
+    pub fn sync_every(mut self, records: Option<usize>) -> Self {
+        self.sync_every = records;
+        self
+    }
+
+    /// Serialize `value` and append it as one record. Nothing is written if serialization
+    /// fails.
+    pub fn append<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let record = self.options.to_vec(value)?;
+        self.append_raw(&record)
+    }
+
+    /// Append `record` as it is, for bytes that are already canonical JSON.
+    pub fn append_raw(&mut self, record: &[u8]) -> Result<()> {
+        let len = u32::try_from(record.len()).map_err(|_| {
+            Error::InvalidInput(format!(
+                "record of {} bytes is too long for a journal",
+                record.len()
+            ))
+        })?;
+        self.file
+            .write_all(&len.to_be_bytes())
+            .and_then(|_| self.file.write_all(record))
+            .map_err(Error::io)?;
+
+        self.unsynced += 1;
+        match self.sync_every {
+            Some(records) if self.unsynced >= records => self.sync(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Flush every record appended so far and wait until they are on disk.
+    pub fn sync(&mut self) -> Result<()> {
+        self.file.flush().map_err(Error::io)?;
+        self.file.get_ref().sync_data().map_err(Error::io)?;
+        self.unsynced = 0;
+        Ok(())
+    }
+}
+
+/// Reads the records of a journal written by `JournalWriter`, yielding the bytes of each
+/// document exactly as they were written.
+///
+/// A journal that ends in the middle of a record, like one that was being written when the
+/// process died, yields an `Error::InvalidInput` for the last record.
+pub struct JournalReader<R> {
+    reader: R,
+    done: bool,
+}
+
+impl JournalReader<BufReader<File>> {
+    /// Read the journal at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path).map_err(Error::io)?;
+        Ok(Self::new(BufReader::new(file)))
+    }
+}
+
+impl<R: Read> JournalReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            done: false,
+        }
+    }
+
+    fn read_record(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut len = [0; 4];
+        let mut read = 0;
+        while read < len.len() {
+            match self.reader.read(&mut len[read..]) {
+                Ok(0) if read == 0 => return Ok(None),
+                Ok(0) => return Err(truncated()),
+                Ok(n) => read += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(Error::io(err)),
+            }
+        }
+
+        // Grow the record as it is read, a corrupt length must not allocate up to 4 GiB.
+        let len = u64::from(u32::from_be_bytes(len));
+        let mut record = vec![];
+        (&mut self.reader)
+            .take(len)
+            .read_to_end(&mut record)
+            .map_err(Error::io)?;
+        if record.len() as u64 != len {
+            return Err(truncated());
+        }
+        Ok(Some(record))
+    }
+}
+
+impl<R: Read> Iterator for JournalReader<R> {
+    type Item = Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let record = self.read_record();
+        // After an error the position in the journal is unknown, so nothing after it can be read.
+        self.done = !matches!(record, Ok(Some(_)));
+        record.transpose()
+    }
+}
+
+fn truncated() -> Error {
+    Error::InvalidInput("journal ends in the middle of a record".to_owned())
+}
+
+#[test]
+fn journal_round_trip() {
+    let path = std::env::temp_dir().join(format!("canonical-json-journal-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let mut writer = JournalWriter::open(&path).unwrap().sync_every(Some(2));
+    writer
+        .append(&serde_json::json!({ "b": 1, "a": "x" }))
+        .unwrap();
+    assert!(writer.append(&1.5).is_err());
+    writer.append(&[1, 2]).unwrap();
+    drop(writer);
+
+    let mut writer = JournalWriter::open(&path).unwrap();
+    writer.append_raw(b"null").unwrap();
+    writer.sync().unwrap();
+    drop(writer);
+
+    let records = JournalReader::open(&path)
+        .unwrap()
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(
+        records,
+        vec![
+            br#"{"a":"x","b":1}"#.to_vec(),
+            b"[1,2]".to_vec(),
+            b"null".to_vec()
+        ]
+    );
+
+    let mut bytes = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    bytes.truncate(bytes.len() - 2);
+    let records = JournalReader::new(&bytes[..]).collect::<Vec<_>>();
+    assert_eq!(records.len(), 3);
+    assert!(matches!(records[2], Err(Error::InvalidInput(_))));
+
+    let corrupt = [&u32::MAX.to_be_bytes()[..], b"null"].concat();
+    let records = JournalReader::new(&corrupt[..]).collect::<Vec<_>>();
+    assert!(matches!(records[..], [Err(Error::InvalidInput(_))]));
+}
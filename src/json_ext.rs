@@ -0,0 +1,102 @@
+use serde_json::{Map, Value};
+
+use crate::{
+    to_canonical_string, to_canonical_value, to_canonical_vec, CanonicalJsonValue, Result,
+};
+#[cfg(test)]
+use crate::{Options, Profile};
+
+/// Canonical JSON conversions as methods of `serde_json::Value` and `serde_json::Map`, so code
+/// that already passes those around can call `value.to_canonical_string()`.
+///
+/// Keys come out sorted whether or not serde_json's `preserve_order` feature is enabled, every
+/// object is sorted by the serializer no matter what order the map iterates in.
+pub trait CanonicalJsonExt {
+    /// See `to_canonical_string`.
+    fn to_canonical_string(&self) -> Result<String>;
+
+    /// See `to_canonical_vec`.
+    fn to_canonical_vec(&self) -> Result<Vec<u8>>;
+
+    /// See `to_canonical_value`, fails if the value holds a float.
+    fn to_canonical_value(&self) -> Result<CanonicalJsonValue>;
+}
+
+impl CanonicalJsonExt for Value {
+    fn to_canonical_string(&self) -> Result<String> {
+        to_canonical_string(self)
+    }
+
+    fn to_canonical_vec(&self) -> Result<Vec<u8>> {
+        to_canonical_vec(self)
+    }
+
+    fn to_canonical_value(&self) -> Result<CanonicalJsonValue> {
+        to_canonical_value(self)
+    }
+}
+
+impl CanonicalJsonExt for Map<String, Value> {
+    fn to_canonical_string(&self) -> Result<String> {
+        to_canonical_string(self)
+    }
+
+    fn to_canonical_vec(&self) -> Result<Vec<u8>> {
+        to_canonical_vec(self)
+    }
+
+    fn to_canonical_value(&self) -> Result<CanonicalJsonValue> {
+        to_canonical_value(self)
+    }
+}
+
+#[test]
+fn serde_json_methods() {
+    let value = serde_json::json!({ "b": [1, "x"], "a": null });
+    assert_eq!(
+        value.to_canonical_string().unwrap(),
+        r#"{"a":null,"b":[1,"x"]}"#
+    );
+    assert_eq!(
+        value.as_object().unwrap().to_canonical_vec().unwrap(),
+        br#"{"a":null,"b":[1,"x"]}"#
+    );
+    assert_eq!(
+        value.to_canonical_value().unwrap().get("b"),
+        Some(&CanonicalJsonValue::Array(vec![1.into(), "x".into()]))
+    );
+    assert!(serde_json::json!([1.5]).to_canonical_string().is_err());
+}
+
+#[test]
+fn insertion_ordered_maps() {
+    let mut inner = Map::new();
+    inner.insert("d".to_owned(), 1.into());
+    inner.insert("c".to_owned(), 2.into());
+    let mut map = Map::new();
+    map.insert("b".to_owned(), Value::Array(vec![Value::Object(inner)]));
+    map.insert("é".to_owned(), Value::Null);
+    map.insert("x".to_owned(), Value::Null);
+    map.insert("a".to_owned(), "z".into());
+    // With `preserve_order` this moves `a` to where `x` was.
+    map.remove("x");
+    #[cfg(feature = "preserve_order")]
+    assert_eq!(map.keys().collect::<Vec<_>>(), vec!["b", "é", "a"]);
+
+    let canonical = r#"{"a":"z","b":[{"c":2,"d":1}],"é":null}"#;
+    assert_eq!(map.to_canonical_string().unwrap(), canonical);
+    let value = Value::Object(map);
+    assert_eq!(value.to_canonical_string().unwrap(), canonical);
+    assert_eq!(
+        to_canonical_string(&value.to_canonical_value().unwrap()).unwrap(),
+        canonical
+    );
+    assert_eq!(crate::compat::ser::to_string(&value).unwrap(), canonical);
+    assert_eq!(
+        Options::new()
+            .profile(Profile::Jcs)
+            .to_string(&value)
+            .unwrap(),
+        canonical
+    );
+}
@@ -0,0 +1,126 @@
+//! Property tests for the order object keys are written in. Keys are compared unescaped, so
+//! the empty key, keys holding quotes, backslashes or control characters and keys that are
+//! prefixes of each other have to come out in code point order (UTF-16 order for JCS) through
+//! every path that sorts: the serializer, the serializer spilling to disk, `to_canonical_cow`
+//! and the checks of `verify_sorted` and `is_canonical`.
+
+use std::collections::HashSet;
+
+use serde::{
+    ser::{SerializeMap, Serializer},
+    Serialize,
+};
+
+use crate::{
+    gen::EventGenerator,
+    is_canonical,
+    read::{Event, Parser},
+    to_canonical_cow, to_canonical_string, verify_sorted, KeyOrder, Options, Profile,
+};
+
+/// What keys are made of, every character here changes its position relative to the others
+/// when it is escaped.
+const PIECES: &[&str] = &[
+    "", "a", "b", "aa", "\"", "\\", "/", " ", "\u{0}", "\u{1f}", "\u{7f}", "é", "\u{ffff}",
+    "\u{fb33}", "😀",
+];
+
+/// An object whose entries are serialized in the order they are listed in.
+struct Entries<'a>(&'a [String]);
+
+impl Serialize for Entries<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (idx, key) in self.0.iter().enumerate() {
+            map.serialize_entry(key, &idx)?;
+        }
+        map.end()
+    }
+}
+
+/// The keys of the objects in `json` in the order they are written.
+fn keys_of(json: &str) -> Vec<String> {
+    let mut parser = Parser::new(json.as_bytes());
+    let mut keys = vec![];
+    while let Some(event) = parser.next_event().unwrap() {
+        if let Event::Key(key) = event {
+            keys.push(key.unescape().into_owned());
+        }
+    }
+    keys
+}
+
+fn random_keys(rng: &mut EventGenerator) -> Vec<String> {
+    let mut seen = HashSet::new();
+    (0..rng.below(12))
+        .map(|_| {
+            (0..rng.below(4))
+                .map(|_| PIECES[rng.below(PIECES.len())])
+                .collect::<String>()
+        })
+        .filter(|key| seen.insert(key.clone()))
+        .collect()
+}
+
+#[test]
+fn pathological_keys() {
+    let keys = ["b", "a\"", "", "a", "a\\", "a\u{0}", "\u{0}"]
+        .iter()
+        .map(|key| key.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        to_canonical_string(&Entries(&keys)).unwrap(),
+        r#"{"":2,"\u0000":6,"a":3,"a\u0000":5,"a\"":1,"a\\":4,"b":0}"#
+    );
+
+    // U+FB33 comes after the surrogate pair of U+1F600 in UTF-16 but before it as a code point.
+    let keys = vec!["\u{fb33}".to_owned(), "😀".to_owned()];
+    assert_eq!(
+        keys_of(&to_canonical_string(&Entries(&keys)).unwrap()),
+        keys
+    );
+    assert_eq!(
+        keys_of(
+            &Options::new()
+                .profile(Profile::Jcs)
+                .to_string(&Entries(&keys))
+                .unwrap()
+        ),
+        vec!["😀".to_owned(), "\u{fb33}".to_owned()]
+    );
+}
+
+#[test]
+fn keys_sort_by_code_point() {
+    let mut rng = EventGenerator::new(0x6B65_7973);
+    for _ in 0..2_000 {
+        let keys = random_keys(&mut rng);
+        let mut sorted = keys.clone();
+        sorted.sort();
+
+        let canonical = to_canonical_string(&Entries(&keys)).unwrap();
+        assert_eq!(keys_of(&canonical), sorted, "{}", canonical);
+        assert!(verify_sorted(&canonical), "{}", canonical);
+        assert!(is_canonical(&canonical), "{}", canonical);
+
+        let spilled = Options::new()
+            .spill_threshold(Some(2))
+            .to_string(&Entries(&keys))
+            .unwrap();
+        assert_eq!(spilled, canonical);
+
+        let unsorted = Options::new()
+            .key_order(KeyOrder::Insertion)
+            .to_string(&Entries(&keys))
+            .unwrap();
+        assert_eq!(keys_of(&unsorted), keys);
+        assert_eq!(to_canonical_cow(&unsorted).unwrap(), canonical);
+
+        let jcs = Options::new()
+            .profile(Profile::Jcs)
+            .to_string(&Entries(&keys))
+            .unwrap();
+        sorted.sort_by(|a, b| a.encode_utf16().cmp(b.encode_utf16()));
+        assert_eq!(keys_of(&jcs), sorted, "{}", jcs);
+    }
+}
@@ -1,80 +1,455 @@
-use std::{fmt, io};
-
+#[cfg(feature = "ser")]
+use std::{
+    fmt, io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+#[cfg(feature = "ser")]
 use serde::{ser, serde_if_integer128, Serialize};
 
+pub mod as_str;
+#[cfg(all(feature = "ser", feature = "de", feature = "tokio"))]
+mod async_read;
+#[cfg(all(feature = "ser", feature = "tokio"))]
+mod async_write;
+#[cfg(all(feature = "ser", feature = "bytes"))]
+mod buffers;
+#[cfg(feature = "ser")]
+mod cached;
+#[cfg(all(feature = "hash", feature = "de"))]
+mod chain;
+#[cfg(feature = "ser")]
+mod collect;
+#[cfg(all(feature = "ser", feature = "serde_json"))]
+pub mod compat;
+#[cfg(feature = "hash")]
+mod content_digest;
+#[cfg(feature = "hash")]
+mod content_hash;
+#[cfg(feature = "de")]
+mod diff;
+#[cfg(all(feature = "ser", feature = "bytes", feature = "futures"))]
+mod document_sink;
 mod error;
+#[cfg(feature = "ser")]
+mod federation;
+#[cfg(feature = "ser")]
+mod field_keys;
+#[cfg(all(feature = "ser", any(feature = "yaml", feature = "toml")))]
+mod formats;
+#[cfg(feature = "ser")]
 mod formatter;
+#[cfg(any(test, feature = "gen"))]
+mod gen;
+#[cfg(all(feature = "ser", feature = "de"))]
+mod golden;
+mod intern;
+#[cfg(feature = "ser")]
+mod jcs;
+#[cfg(feature = "ser")]
+mod journal;
+#[cfg(all(feature = "ser", feature = "serde_json"))]
+mod json_ext;
+#[cfg(feature = "jws")]
+mod jws;
+#[cfg(all(test, feature = "ser", feature = "de"))]
+mod key_order;
+mod lint;
+#[cfg(feature = "ser")]
 mod map_key;
+#[cfg(feature = "de")]
+mod ndjson;
+#[cfg(feature = "ser")]
+mod options;
+pub mod origin_server_ts;
+mod pdu;
+#[cfg(all(feature = "hash", feature = "de"))]
+mod pdu_response;
+#[cfg(feature = "ser")]
+mod profiles;
+#[cfg(feature = "de")]
+mod read;
+#[cfg(all(test, feature = "reference-tests"))]
+mod reference;
+#[cfg(all(feature = "ser", feature = "http"))]
+mod request;
+#[cfg(feature = "schema")]
+mod schema;
+#[cfg(feature = "ser")]
 mod serializer;
-
+#[cfg(feature = "ser")]
+mod sink;
+#[cfg(feature = "ser")]
+mod spill;
+#[cfg(all(feature = "ser", feature = "metrics"))]
+mod stats;
+#[cfg(feature = "de")]
+mod transcode;
+mod value;
+#[cfg(feature = "ser")]
+mod writer;
+
+#[cfg(all(feature = "ser", feature = "de", feature = "tokio"))]
+pub use async_read::from_async_reader;
+#[cfg(all(
+    feature = "ser",
+    feature = "de",
+    feature = "tokio",
+    feature = "futures"
+))]
+pub use async_read::AsyncNdjsonReader;
+#[cfg(all(feature = "ser", feature = "tokio"))]
+pub use async_write::AsyncCanonicalWriter;
+#[cfg(all(feature = "ser", feature = "bytes"))]
+pub use buffers::{to_canonical_bytes, to_canonical_bytes_mut};
+#[cfg(feature = "ser")]
+pub use cached::{Cached, CachedMut};
+#[cfg(all(feature = "hash", feature = "de"))]
+pub use chain::{verify_chain, ChainVerifier, HashChain, PREV_SHA256_KEY};
+#[cfg(feature = "ser")]
+pub use collect::{collect_canonical_map, collect_canonical_seq, to_canonical_from_iter};
+#[cfg(feature = "hash")]
+pub use content_digest::{content_digest, to_canonical_with_digest};
+#[cfg(feature = "hash")]
+pub use content_hash::{check_declared_hashes, content_hash, HashCheck};
+#[cfg(feature = "de")]
+pub use diff::{first_divergence, DiffReport};
+#[cfg(all(feature = "ser", feature = "bytes", feature = "futures"))]
+pub use document_sink::DocumentSink;
 pub use error::Error;
+#[cfg(feature = "ser")]
+pub use federation::{
+    is_federation_sendable, FederationSize, TransactionBuilder, MAX_PDU_SIZE, MAX_TRANSACTION_EDUS,
+    MAX_TRANSACTION_PDUS,
+};
+#[cfg(all(feature = "ser", feature = "toml"))]
+pub use formats::canonicalize_toml_str;
+#[cfg(feature = "yaml")]
+pub use formats::canonicalize_yaml_str;
+#[cfg(feature = "ser")]
 pub use formatter::Formatter;
+#[cfg(feature = "gen")]
+pub use gen::EventGenerator;
+#[cfg(all(feature = "ser", feature = "de"))]
+pub use golden::{check_golden, check_idempotent, GoldenMismatch};
+pub use intern::KeyInterner;
+#[cfg(feature = "ser")]
+pub use journal::{JournalReader, JournalWriter};
+#[cfg(all(feature = "ser", feature = "serde_json"))]
+pub use json_ext::CanonicalJsonExt;
+#[cfg(feature = "jws")]
+pub use jws::{sign_jws, Algorithm};
+pub use lint::{
+    lint_value, Severity, Violation, ViolationKind, MAX_SAFE_INTEGER, MIN_SAFE_INTEGER,
+};
+#[cfg(feature = "ser")]
 pub use map_key::MapKeySerializer;
+#[cfg(feature = "de")]
+pub use ndjson::{NdjsonReader, TypedNdjsonReader};
+#[cfg(feature = "ser")]
+pub use options::{
+    FloatPolicy, IntegerPolicy, KeyOrder, Options, Profile, UnitPolicy, DEFAULT_SIZE_LIMIT,
+};
+#[cfg(feature = "chrono")]
+pub use origin_server_ts::chrono_to_millis;
+#[cfg(feature = "time")]
+pub use origin_server_ts::time_to_millis;
+pub use origin_server_ts::{millis_to_system_time, system_time_to_millis};
+pub use pdu::{check_pdu, insert_age, insert_age_ts, strip_age, EventBuilder};
+#[cfg(all(feature = "hash", feature = "de"))]
+pub use pdu_response::{check_pdu_response, CheckedPdu};
+#[cfg(feature = "ser")]
+pub use profiles::ProfileRegistry;
+#[cfg(feature = "de")]
+pub use read::{Event, OwnedEvent, Parser, PushParser, Str};
+#[cfg(all(feature = "ser", feature = "http"))]
+pub use request::canonical_request;
+#[cfg(feature = "schema")]
+pub use schema::canonicalize_validated;
+#[cfg(feature = "ser")]
 pub use serializer::{Compound, Serializer};
+#[cfg(feature = "ser")]
+pub(crate) use sink::Sink;
+#[cfg(feature = "de")]
+pub use transcode::{is_canonical, verify_sorted, Validator};
+#[cfg(all(feature = "ser", feature = "de"))]
+pub use transcode::{to_canonical_cow, to_canonical_with_report, CanonicalizationReport};
+pub use value::{
+    from_canonical_value, to_canonical_object, to_canonical_value, CanonicalJsonObject,
+    CanonicalJsonValue,
+};
+#[cfg(feature = "de")]
+pub use value::{from_slice, from_slice_with_trailing, NumberPolicy};
+#[cfg(feature = "ser")]
+pub use writer::LimitedWriter;
+
+#[cfg(feature = "ser")]
+use error::duplicate_key;
+#[cfg(feature = "ser")]
+use value::ObjectKeySerializer;
+#[cfg(feature = "ser")]
+use writer::FmtWriter;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Serialize `value` as canonical JSON into `writer`, returning the number of bytes written.
+///
+/// The 65,535 byte size limit is enforced while writing. The output is handed to `writer` in
+/// chunks of up to 8 KiB, so when serialization fails the chunks before the failure have already
+/// been written.
 #[inline]
-fn to_canonical_writer<W, T>(writer: W, value: &T) -> Result<()>
+#[cfg(feature = "ser")]
+pub fn to_canonical_writer<W, T>(writer: W, value: &T) -> Result<usize>
 where
     W: io::Write,
     T: ?Sized + Serialize,
 {
-    let mut ser = CanonicalJson::new(writer);
-    value.serialize(&mut ser)?;
-    Ok(())
+    Options::new().to_writer(writer, value)
+}
+
+#[cfg(feature = "ser")]
+pub fn to_canonical_vec<T>(value: &T) -> Result<Vec<u8>>
+where
+    T: ?Sized + Serialize,
+{
+    Options::new().to_vec(value)
+}
+
+/// Like `to_canonical_vec` but the output buffer starts out with room for `capacity` bytes.
+#[cfg(feature = "ser")]
+pub fn to_canonical_vec_with_capacity<T>(capacity: usize, value: &T) -> Result<Vec<u8>>
+where
+    T: ?Sized + Serialize,
+{
+    Options::new().to_vec_with_capacity(capacity, value)
 }
 
+/// Serialize `value` as canonical JSON into a `fmt::Write` sink such as a `String` or the
+/// `fmt::Formatter` of a `Display` impl. Returns the number of bytes written.
 #[inline]
-fn to_canonical_vec<T>(value: &T) -> Result<Vec<u8>>
+#[cfg(feature = "ser")]
+pub fn to_canonical_fmt<W, T>(writer: W, value: &T) -> Result<usize>
+where
+    W: fmt::Write,
+    T: ?Sized + Serialize,
+{
+    to_canonical_writer(FmtWriter::new(writer), value)
+}
+
+/// Serialize `value` into `buf`, replacing its contents but keeping its allocation so one buffer
+/// can be reused for many values. `buf` is left empty if serialization fails.
+#[cfg(feature = "ser")]
+pub fn to_canonical_into<T>(buf: &mut Vec<u8>, value: &T) -> Result<()>
 where
     T: ?Sized + Serialize,
 {
-    let mut writer = Vec::with_capacity(128);
-    to_canonical_writer(&mut writer, value)?;
-    if writer.len() > 65_535 {
-        return Err(Error::SizeLimit);
+    buf.clear();
+    match to_canonical_writer(&mut *buf, value) {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            buf.clear();
+            Err(err)
+        }
     }
-    Ok(writer)
 }
 
+#[cfg(feature = "ser")]
 pub fn to_canonical_string<T>(value: &T) -> Result<String>
 where
     T: ?Sized + Serialize,
 {
-    let vec = to_canonical_vec(value)?;
-    Ok(
-        // serde_json does this so we can too.
-        unsafe { String::from_utf8_unchecked(vec) },
-    )
+    Options::new().to_string(value)
 }
 
+/// Like `to_canonical_string` but without the 65,535 byte size limit, for payloads that are not
+/// events.
+#[cfg(feature = "ser")]
+pub fn to_canonical_string_unlimited<T>(value: &T) -> Result<String>
+where
+    T: ?Sized + Serialize,
+{
+    Options::new().size_limit(None).to_string(value)
+}
+
+#[cfg(feature = "ser")]
 pub struct CanonicalJson<W> {
     ser: Serializer<W>,
+    options: Options,
+    /// The bytes buffered for sorting by this serializer and every one nested in it.
+    buffered: Arc<AtomicUsize>,
+    /// The JSON pointer of the value being serialized, only kept up to date when `options` has
+    /// hooks.
+    pointer: String,
 }
 
-impl<W: io::Write> CanonicalJson<W> {
+#[cfg(feature = "ser")]
+impl<W: Sink> CanonicalJson<W> {
     pub fn new(writer: W) -> Self {
+        Self::with_options(writer, Options::new())
+    }
+
+    /// Creates a new serializer following the rules of `profile`.
+    pub fn with_profile(writer: W, profile: Profile) -> Self {
+        Self::with_options(writer, Options::new().profile(profile))
+    }
+
+    /// Creates a new serializer configured by `options`, the size limit is not enforced by the
+    /// serializer itself but by `Options::to_writer`.
+    pub fn with_options(writer: W, options: Options) -> Self {
         Self {
             ser: Serializer::new(writer),
+            options,
+            buffered: Arc::new(AtomicUsize::new(0)),
+            pointer: String::new(),
         }
     }
+
+    /// A serializer for the value of `key` nested in this one, sharing its options and buffer
+    /// accounting.
+    fn nested<'b>(&self, buf: &'b mut Vec<u8>, key: &str) -> CanonicalJson<&'b mut Vec<u8>> {
+        let mut pointer = String::new();
+        if self.options.has_hooks() {
+            pointer = format!(
+                "{}/{}",
+                self.pointer,
+                key.replace('~', "~0").replace('/', "~1")
+            );
+        }
+        CanonicalJson {
+            ser: Serializer::new(buf),
+            options: self.options.clone(),
+            buffered: Arc::clone(&self.buffered),
+            pointer,
+        }
+    }
+
+    /// Whether an integer is left to `integer_out_of_range`, the same for every integer type
+    /// and profile.
+    fn is_out_of_range(&self, value: i128) -> bool {
+        value > i128::from(MAX_SAFE_INTEGER) || value < i128::from(MIN_SAFE_INTEGER)
+    }
+
+    /// Write an integer outside of the canonical range as `Options::integer_policy` says.
+    fn integer_out_of_range<T: fmt::Display>(&mut self, value: T) -> Result<()> {
+        match self.options.integer_policy {
+            IntegerPolicy::Error => Err(Error::IntegerOutOfRange {
+                pointer: String::new(),
+                value: value.to_string(),
+            }),
+            IntegerPolicy::String => {
+                ser::Serializer::serialize_str(&mut self.ser, &value.to_string())
+            }
+        }
+    }
+
+    fn check_string(&self, value: &str) -> Result<()> {
+        match self.options.max_string_len {
+            Some(max) if value.len() > max => Err(Error::InvalidInput(format!(
+                "string of {} bytes is longer than the maximum length of {}",
+                value.len(),
+                max
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Write the start of the `{"variant":` object an externally tagged variant is wrapped in.
+    /// Returns the length of the pointer to restore once the variant is closed.
+    fn open_variant(&mut self, variant: &str) -> Result<usize> {
+        self.check_string(variant)?;
+        self.ser.writer.write_all(b"{").map_err(Error::io)?;
+        ser::Serializer::serialize_str(&mut self.ser, variant)?;
+        self.ser.writer.write_all(b":").map_err(Error::io)?;
+
+        let pointer_len = self.pointer.len();
+        if self.options.has_hooks() {
+            self.options.visit_key(&self.pointer, variant);
+            self.pointer.push_str(&format!(
+                "/{}",
+                variant.replace('~', "~0").replace('/', "~1")
+            ));
+        }
+        Ok(pointer_len)
+    }
+
+    fn close_variant(&mut self, pointer_len: usize) -> Result<()> {
+        self.pointer.truncate(pointer_len);
+        self.ser.writer.write_all(b"}").map_err(Error::io)
+    }
+
+    fn serialize_float(&mut self, value: f64) -> Result<()> {
+        let num = jcs::format_f64(value)?;
+        self.ser.writer.write_all(num.as_bytes()).map_err(Error::io)
+    }
+
+    /// Write a float with the Matrix profile as `Options::float_policy` says, `format` gives the
+    /// shortest digits of `value` in its own precision.
+    fn float_out_of_profile<T: fmt::Display>(
+        &mut self,
+        ty: &str,
+        value: T,
+        float: f64,
+        format: fn(T) -> Result<String>,
+    ) -> Result<()> {
+        match self.options.float_policy {
+            FloatPolicy::Error => Err(Error::InvalidInput(format!(
+                "{} is not valid in canonical JSON found {}",
+                ty, value
+            ))),
+            FloatPolicy::String if !float.is_finite() => Err(Error::InvalidInput(format!(
+                "{} is not valid in canonical JSON",
+                value
+            ))),
+            FloatPolicy::String => {
+                let num = format(value)?;
+                if float.fract() == 0.0 && float.abs() <= MAX_SAFE_INTEGER as f64 {
+                    return self.ser.writer.write_all(num.as_bytes()).map_err(Error::io);
+                }
+                ser::Serializer::serialize_str(&mut self.ser, &num)
+            }
+        }
+    }
+
+    /// Mutably borrow the `Writer` of the `CanonicalJson` serializer.
+    #[inline]
+    pub fn writer_mut(&mut self) -> &mut W {
+        &mut self.ser.writer
+    }
+
+    /// Unwrap the `Writer` from the `CanonicalJson` serializer.
+    #[inline]
+    pub fn into_inner(self) -> W {
+        self.ser.into_inner()
+    }
+}
+
+#[cfg(feature = "ser")]
+impl CanonicalJson<Vec<u8>> {
+    /// Creates a new serializer writing into a buffer with room for `capacity` bytes.
+    #[inline]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::new(Vec::with_capacity(capacity))
+    }
 }
 
+#[cfg(feature = "ser")]
 impl<'a, W> ser::Serializer for &'a mut CanonicalJson<W>
 where
-    W: io::Write,
+    W: Sink,
 {
     type Ok = ();
     type Error = Error;
 
-    type SerializeSeq = Compound<'a, W>;
-    type SerializeTuple = Compound<'a, W>;
-    type SerializeTupleStruct = Compound<'a, W>;
-    type SerializeTupleVariant = Compound<'a, W>;
+    type SerializeSeq = SeqCanonical<'a, W>;
+    type SerializeTuple = SeqCanonical<'a, W>;
+    type SerializeTupleStruct = SeqCanonical<'a, W>;
+    type SerializeTupleVariant = SeqCanonical<'a, W>;
     type SerializeMap = MapKeySorted<'a, W>;
     type SerializeStruct = MapKeySorted<'a, W>;
-    type SerializeStructVariant = Compound<'a, W>;
+    type SerializeStructVariant = MapKeySorted<'a, W>;
 
     #[inline]
     fn serialize_bool(self, value: bool) -> Result<()> {
@@ -98,11 +473,17 @@ where
 
     #[inline]
     fn serialize_i64(self, value: i64) -> Result<()> {
+        if self.is_out_of_range(i128::from(value)) {
+            return self.integer_out_of_range(value);
+        }
         self.ser.serialize_i64(value)
     }
 
     serde_if_integer128! {
         fn serialize_i128(self, value: i128) -> Result<()> {
+            if self.is_out_of_range(value) {
+                return self.integer_out_of_range(value);
+            }
             self.ser.serialize_i128(value)
         }
 
@@ -125,29 +506,35 @@ where
 
     #[inline]
     fn serialize_u64(self, value: u64) -> Result<()> {
+        if self.is_out_of_range(i128::from(value)) {
+            return self.integer_out_of_range(value);
+        }
         self.ser.serialize_u64(value)
     }
 
     serde_if_integer128! {
         fn serialize_u128(self, value: u128) -> Result<()> {
+            if value > MAX_SAFE_INTEGER as u128 {
+                return self.integer_out_of_range(value);
+            }
             self.ser.serialize_u128(value)
         }
     }
 
     #[inline]
     fn serialize_f32(self, value: f32) -> Result<()> {
-        Err(Error::InvalidInput(format!(
-            "f32 is not valid in canonical JSON found {}",
-            value
-        )))
+        if self.options.profile == Profile::Jcs {
+            return self.serialize_float(f64::from(value));
+        }
+        self.float_out_of_profile("f32", value, f64::from(value), jcs::format_f32)
     }
 
     #[inline]
     fn serialize_f64(self, value: f64) -> Result<()> {
-        Err(Error::InvalidInput(format!(
-            "f64 is not valid in canonical JSON found {}",
-            value
-        )))
+        if self.options.profile == Profile::Jcs {
+            return self.serialize_float(value);
+        }
+        self.float_out_of_profile("f64", value, value, jcs::format_f64)
     }
 
     #[inline]
@@ -159,6 +546,8 @@ where
 
     #[inline]
     fn serialize_str(self, value: &str) -> Result<()> {
+        self.check_string(value)?;
+        self.options.visit_string(&self.pointer, value);
         self.ser.serialize_str(value)
     }
 
@@ -178,8 +567,15 @@ where
     }
 
     #[inline]
-    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
-        self.serialize_unit()
+    fn serialize_unit_struct(self, name: &'static str) -> Result<()> {
+        match self.options.unit_policy {
+            UnitPolicy::Null => self.serialize_unit(),
+            UnitPolicy::EmptyObject => self.ser.writer.write_all(b"{}").map_err(Error::io),
+            UnitPolicy::Error => Err(Error::InvalidInput(format!(
+                "unit struct {} is not allowed by the unit policy",
+                name
+            ))),
+        }
     }
 
     #[inline]
@@ -204,16 +600,17 @@ where
     #[inline]
     fn serialize_newtype_variant<T>(
         self,
-        name: &'static str,
-        variant_index: u32,
+        _name: &'static str,
+        _variant_index: u32,
         variant: &'static str,
         value: &T,
     ) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        self.ser
-            .serialize_newtype_variant(name, variant_index, variant, value)
+        let pointer_len = self.open_variant(variant)?;
+        value.serialize(&mut *self).map_err(|err| err.at(variant))?;
+        self.close_variant(pointer_len)
     }
 
     #[inline]
@@ -230,8 +627,14 @@ where
     }
 
     #[inline]
-    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
-        self.ser.serialize_seq(len)
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        self.ser.writer.write_all(b"[").map_err(Error::io)?;
+        Ok(SeqCanonical {
+            ser: self,
+            state: State::First,
+            index: 0,
+            variant: None,
+        })
     }
 
     #[inline]
@@ -251,13 +654,15 @@ where
     #[inline]
     fn serialize_tuple_variant(
         self,
-        name: &'static str,
-        variant_index: u32,
+        _name: &'static str,
+        _variant_index: u32,
         variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
-        self.ser
-            .serialize_tuple_variant(name, variant_index, variant, len)
+        let pointer_len = self.open_variant(variant)?;
+        let mut seq = self.serialize_seq(Some(len))?;
+        seq.variant = Some((pointer_len, variant));
+        Ok(seq)
     }
 
     #[inline]
@@ -265,6 +670,10 @@ where
         Ok(MapKeySorted {
             ser: self,
             pairs: vec![],
+            next_key: None,
+            runs: vec![],
+            buffered: 0,
+            variant: None,
         })
     }
 
@@ -276,102 +685,387 @@ where
     #[inline]
     fn serialize_struct_variant(
         self,
-        name: &'static str,
-        variant_index: u32,
+        _name: &'static str,
+        _variant_index: u32,
         variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStructVariant> {
-        self.ser
-            .serialize_struct_variant(name, variant_index, variant, len)
+        let pointer_len = self.open_variant(variant)?;
+        let mut map = self.serialize_map(Some(len))?;
+        map.variant = Some((pointer_len, variant));
+        Ok(map)
     }
 
     fn collect_str<T>(self, value: &T) -> Result<()>
     where
         T: ?Sized + fmt::Display,
     {
+        if self.options.max_string_len.is_some() || self.options.has_hooks() {
+            return self.serialize_str(&value.to_string());
+        }
         self.ser.collect_str(value)
     }
 }
 
+#[cfg(feature = "ser")]
 pub enum State {
     Empty,
     First,
     Rest,
 }
 
-pub struct MapKeySorted<'a, W> {
+/// Writes the elements of a sequence, each one through `CanonicalJson` so nested objects are
+/// sorted too.
+#[cfg(feature = "ser")]
+pub struct SeqCanonical<'a, W> {
     ser: &'a mut CanonicalJson<W>,
-    pairs: Vec<String>,
+    state: State,
+    /// The index of the next element, for pointers.
+    index: usize,
+    /// Set for the elements of a tuple variant, the length of the pointer to restore when
+    /// the variant is closed and the name of the variant.
+    variant: Option<(usize, &'static str)>,
 }
 
-impl<'a, W> ser::SerializeMap for MapKeySorted<'a, W>
+#[cfg(feature = "ser")]
+impl<'a, W> SeqCanonical<'a, W>
 where
-    W: io::Write,
+    W: Sink,
+{
+    fn element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        if let State::First = self.state {
+            self.state = State::Rest;
+        } else {
+            self.ser.ser.writer.write_all(b",").map_err(Error::io)?;
+        }
+        let index = self.index;
+        self.index += 1;
+        let res = if self.ser.options.has_hooks() {
+            let len = self.ser.pointer.len();
+            self.ser.pointer.push_str(&format!("/{}", index));
+            let res = value.serialize(&mut *self.ser);
+            self.ser.pointer.truncate(len);
+            res
+        } else {
+            value.serialize(&mut *self.ser)
+        };
+
+        res.map_err(|err| {
+            let err = err.at(&index.to_string());
+            match self.variant {
+                Some((_, variant)) => err.at(variant),
+                None => err,
+            }
+        })
+    }
+
+    fn end(self) -> Result<()> {
+        self.ser.ser.writer.write_all(b"]").map_err(Error::io)?;
+        match self.variant {
+            Some((pointer_len, _)) => self.ser.close_variant(pointer_len),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "ser")]
+impl<'a, W> ser::SerializeSeq for SeqCanonical<'a, W>
+where
+    W: Sink,
 {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_entry<K: ?Sized, V: ?Sized>(&mut self, key: &K, value: &V) -> Result<()>
+    #[inline]
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
     where
-        K: Serialize,
-        V: Serialize,
+        T: ?Sized + Serialize,
     {
-        let mut buf = vec![];
-        let mut ser = Serializer::new(&mut buf);
+        self.element(value)
+    }
 
-        key.serialize(MapKeySerializer { ser: &mut ser })?;
-        buf.push(b':');
-        value.serialize(&mut Serializer::new(&mut buf))?;
+    #[inline]
+    fn end(self) -> Result<()> {
+        SeqCanonical::end(self)
+    }
+}
 
-        let pair = unsafe { String::from_utf8_unchecked(buf) };
-        self.pairs.push(pair);
+#[cfg(feature = "ser")]
+impl<'a, W> ser::SerializeTuple for SeqCanonical<'a, W>
+where
+    W: Sink,
+{
+    type Ok = ();
+    type Error = Error;
 
-        Ok(())
+    #[inline]
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.element(value)
+    }
+
+    #[inline]
+    fn end(self) -> Result<()> {
+        SeqCanonical::end(self)
+    }
+}
+
+#[cfg(feature = "ser")]
+impl<'a, W> ser::SerializeTupleStruct for SeqCanonical<'a, W>
+where
+    W: Sink,
+{
+    type Ok = ();
+    type Error = Error;
+
+    #[inline]
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.element(value)
+    }
+
+    #[inline]
+    fn end(self) -> Result<()> {
+        SeqCanonical::end(self)
+    }
+}
+
+#[cfg(feature = "ser")]
+impl<'a, W> ser::SerializeTupleVariant for SeqCanonical<'a, W>
+where
+    W: Sink,
+{
+    type Ok = ();
+    type Error = Error;
+
+    #[inline]
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.element(value)
     }
 
-    fn serialize_key<T: ?Sized>(&mut self, _key: &T) -> Result<()>
+    #[inline]
+    fn end(self) -> Result<()> {
+        SeqCanonical::end(self)
+    }
+}
+
+#[cfg(feature = "ser")]
+pub struct MapKeySorted<'a, W> {
+    ser: &'a mut CanonicalJson<W>,
+    /// The unescaped key and the `"key":value` pair written for it.
+    pairs: Vec<(String, String)>,
+    /// The key passed to `serialize_key` waiting for its value.
+    next_key: Option<String>,
+    /// Sorted chunks of `pairs` spilled to disk.
+    runs: Vec<spill::Run>,
+    /// The bytes held in `pairs`.
+    buffered: usize,
+    /// Set for the fields of a struct variant, the length of the pointer to restore when the
+    /// variant is closed and the name of the variant.
+    variant: Option<(usize, &'static str)>,
+}
+
+#[cfg(feature = "ser")]
+impl<'a, W> ser::SerializeMap for MapKeySorted<'a, W>
+where
+    W: Sink,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<()>
     where
         T: Serialize,
     {
+        let key = key.serialize(ObjectKeySerializer)?;
+        self.ser.check_string(&key)?;
+        self.ser.options.visit_key(&self.ser.pointer, &key);
+        self.next_key = Some(key);
         Ok(())
     }
 
-    fn serialize_value<T: ?Sized>(&mut self, _value: &T) -> Result<()>
+    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<()>
     where
         T: Serialize,
     {
-        Ok(())
+        let key = self
+            .next_key
+            .take()
+            .expect("serialize_value is only called after serialize_key");
+
+        let mut buf = vec![];
+        ser::Serializer::serialize_str(&mut Serializer::new(&mut buf), &key)?;
+        self.push_pair(key, buf, value)
     }
 
     fn end(mut self) -> Result<Self::Ok> {
-        // Sort the "pairs", this is a Vec<String> that looks like
-        // `"key": value` so this will always sort correctly
-        self.pairs.sort();
-        let count = self.pairs.len();
-        self.ser.ser.writer.write_all(&[b'{']).map_err(Error::io)?;
-        for (idx, pair) in self.pairs.drain(..).enumerate() {
-            self.ser
-                .ser
-                .writer
-                .write_all(pair.as_bytes())
-                .map_err(Error::io)?;
-
-            // not at last item so add a comma
-            if count != idx + 1 {
-                self.ser.ser.writer.write_all(&[b',']).map_err(Error::io)?;
+        if !self.runs.is_empty() && !self.pairs.is_empty() {
+            self.spill()?;
+        }
+        let profile = self.ser.options.profile;
+        if self.ser.options.key_order == KeyOrder::Sorted {
+            // Sort on the unescaped keys, escaping changes the order of keys like `a` and `a!`.
+            #[cfg(feature = "metrics")]
+            let start = std::time::Instant::now();
+            self.pairs.sort_by(|a, b| profile.cmp_keys(&a.0, &b.0));
+            #[cfg(feature = "metrics")]
+            stats::sorted(start.elapsed());
+            // Flattened fields can repeat a key of the struct they are flattened into.
+            if let Some(dup) = self.pairs.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+                return Err(duplicate_key(&dup[0].0));
             }
         }
-        self.ser.ser.writer.write_all(&[b'}']).map_err(Error::io)?;
 
+        let writer = &mut self.ser.ser.writer;
+        writer.write_all(&[b'{']).map_err(Error::io)?;
+        let mut first = true;
+        let mut write_pair = |pair: &str| {
+            // not the first item so add a comma
+            if !first {
+                writer.write_all(&[b',']).map_err(Error::io)?;
+            }
+            first = false;
+            writer.write_all(pair.as_bytes()).map_err(Error::io)
+        };
+        if self.runs.is_empty() {
+            for (_, pair) in self.pairs.drain(..) {
+                write_pair(&pair)?;
+            }
+        } else {
+            spill::merge(&mut self.runs, profile, write_pair)?;
+        }
+        writer.write_all(&[b'}']).map_err(Error::io)?;
+
+        match self.variant {
+            Some((pointer_len, _)) => self.ser.close_variant(pointer_len),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "ser")]
+impl<'a, W> MapKeySorted<'a, W> {
+    /// Account for `len` more buffered bytes, failing if that is more than the options allow.
+    fn buffer(&mut self, len: usize) -> Result<()> {
+        self.buffered += len;
+        let total = self.ser.buffered.fetch_add(len, Ordering::Relaxed) + len;
+
+        let options = &self.ser.options;
+        if matches!(options.object_buffer_limit, Some(max) if self.buffered > max)
+            || matches!(options.total_buffer_limit, Some(max) if total > max)
+        {
+            return Err(Error::BufferLimit);
+        }
+        Ok(())
+    }
+
+    /// Give back everything this object buffered.
+    fn release(&mut self) {
+        self.ser
+            .buffered
+            .fetch_sub(self.buffered, Ordering::Relaxed);
+        self.buffered = 0;
+    }
+
+    /// Sort the buffered pairs and move them to a temporary file.
+    fn spill(&mut self) -> Result<()> {
+        let profile = self.ser.options.profile;
+        self.pairs.sort_by(|a, b| profile.cmp_keys(&a.0, &b.0));
+        self.runs.push(spill::Run::create(&self.pairs)?);
         self.pairs.clear();
+        self.release();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "ser")]
+impl<'a, W: Sink> MapKeySorted<'a, W> {
+    /// Like `serialize_entry` but the quoted key is copied from the cache of field names.
+    fn serialize_static_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.ser.check_string(key)?;
+        self.ser.options.visit_key(&self.ser.pointer, key);
+
+        let mut buf = vec![];
+        field_keys::write_quoted(&mut buf, key)?;
+        self.push_pair(key.to_owned(), buf, value)
+    }
+
+    /// Serialize `value` after `buf`, the quoted `key`, and add the pair to the object.
+    fn push_pair<T>(&mut self, key: String, mut buf: Vec<u8>, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        buf.push(b':');
+        value
+            .serialize(&mut self.ser.nested(&mut buf, &key))
+            .map_err(|err| {
+                let err = err.at(&key);
+                match self.variant {
+                    Some((_, variant)) => err.at(variant),
+                    None => err,
+                }
+            })?;
+
+        let pair = unsafe { String::from_utf8_unchecked(buf) };
+        self.buffer(key.len() + pair.len())?;
+        self.pairs.push((key, pair));
+
+        if self.ser.options.key_order == KeyOrder::Sorted
+            && matches!(self.ser.options.spill_threshold, Some(max) if self.pairs.len() >= max)
+        {
+            self.spill()?;
+        }
 
         Ok(())
     }
 }
 
+#[cfg(feature = "ser")]
+impl<'a, W> Drop for MapKeySorted<'a, W> {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+#[cfg(feature = "ser")]
 impl<'a, W> ser::SerializeStruct for MapKeySorted<'a, W>
 where
-    W: io::Write,
+    W: Sink,
+{
+    type Ok = ();
+    type Error = Error;
+
+    #[inline]
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.serialize_static_field(key, value)
+    }
+
+    #[inline]
+    fn end(self) -> Result<()> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+#[cfg(feature = "ser")]
+impl<'a, W> ser::SerializeStructVariant for MapKeySorted<'a, W>
+where
+    W: Sink,
 {
     type Ok = ();
     type Error = Error;
@@ -381,7 +1075,7 @@ where
     where
         T: ?Sized + Serialize,
     {
-        ser::SerializeMap::serialize_entry(self, key, value)
+        self.serialize_static_field(key, value)
     }
 
     #[inline]
@@ -390,16 +1084,20 @@ where
     }
 }
 
+#[cfg(feature = "ser")]
 pub struct CanonicalJsonFmt;
 
+#[cfg(feature = "ser")]
 impl Formatter for CanonicalJsonFmt {}
 
+#[cfg(feature = "ser")]
 #[test]
 fn check_canonical_empty() {
     let json = serde_json::json!({});
     assert_eq!(to_canonical_string(&json).unwrap(), r#"{}"#)
 }
 
+#[cfg(feature = "ser")]
 #[test]
 fn check_canonical_num() {
     let json = serde_json::json!({
@@ -409,6 +1107,7 @@ fn check_canonical_num() {
     assert_eq!(to_canonical_string(&json).unwrap(), r#"{"a":"1","b":"2"}"#)
 }
 
+#[cfg(feature = "ser")]
 #[test]
 fn check_canonical_obj() {
     let json = serde_json::json!({ "one": 1, "two": "Two" });
@@ -418,6 +1117,7 @@ fn check_canonical_obj() {
     )
 }
 
+#[cfg(feature = "ser")]
 #[test]
 fn check_canonical_sorts_keys() {
     let json = serde_json::json!({
@@ -446,6 +1146,7 @@ fn check_canonical_sorts_keys() {
     )
 }
 
+#[cfg(feature = "ser")]
 #[test]
 fn check_canonical_utf8_keys() {
     let json = serde_json::json!({
@@ -455,30 +1156,35 @@ fn check_canonical_utf8_keys() {
     assert_eq!(to_canonical_string(&json).unwrap(), r#"{"日":1,"本":2}"#)
 }
 
+#[cfg(feature = "ser")]
 #[test]
 fn check_canonical_utf8_value() {
     let json = serde_json::json!({ "a": "日本語" });
     assert_eq!(to_canonical_string(&json).unwrap(), r#"{"a":"日本語"}"#)
 }
 
+#[cfg(feature = "ser")]
 #[test]
 fn check_canonical_utf8_display() {
     let json = serde_json::json!({ "a": "\u{65E5}" });
     assert_eq!(to_canonical_string(&json).unwrap(), r#"{"a":"日"}"#)
 }
 
+#[cfg(feature = "ser")]
 #[test]
 fn check_canonical_null() {
     let json = serde_json::json!({ "a": null });
     assert_eq!(to_canonical_string(&json).unwrap(), r#"{"a":null}"#)
 }
 
+#[cfg(feature = "ser")]
 #[test]
 fn check_canonical_float_value() {
     let json = serde_json::json!({ "a": 1.01_f32 });
     assert!(to_canonical_string(&json).is_err())
 }
 
+#[cfg(feature = "ser")]
 #[test]
 // This is the most important test since sorting the keys directly from a struct is
 // the only thing that `serde_json::to_string` couldn't do (and size limits).
@@ -495,6 +1201,7 @@ fn sorts_keys_of_structs() {
     assert_eq!(to_canonical_string(&t).unwrap(), r#"{"x":1,"y":23,"z":10}"#)
 }
 
+#[cfg(feature = "ser")]
 #[test]
 fn test_float_error() {
     #[derive(serde_derive::Serialize)]
@@ -510,6 +1217,7 @@ fn test_float_error() {
     ))
 }
 
+#[cfg(feature = "ser")]
 #[test]
 fn test_write_error() {
     use std::io::{self, Error as IoError, ErrorKind, Write};
@@ -536,6 +1244,65 @@ fn test_write_error() {
     )
 }
 
+#[cfg(feature = "ser")]
+#[test]
+fn test_into_inner() {
+    let mut ser = CanonicalJson::new(Vec::new());
+    serde_json::json!({ "b": 2, "a": 1 })
+        .serialize(&mut ser)
+        .unwrap();
+    ser.writer_mut().push(b'\n');
+
+    assert_eq!(ser.into_inner(), b"{\"a\":1,\"b\":2}\n")
+}
+
+#[cfg(feature = "ser")]
+#[test]
+fn test_with_capacity() {
+    let json = serde_json::json!({ "b": 2, "a": 1 });
+    let vec = to_canonical_vec_with_capacity(1024, &json).unwrap();
+
+    assert!(vec.capacity() >= 1024);
+    assert_eq!(vec, to_canonical_vec(&json).unwrap());
+}
+
+#[cfg(feature = "ser")]
+#[test]
+fn test_into_reused_buffer() {
+    let mut buf = Vec::with_capacity(64);
+    to_canonical_into(&mut buf, &serde_json::json!({ "b": 2, "a": 1 })).unwrap();
+    assert_eq!(buf, br#"{"a":1,"b":2}"#);
+
+    to_canonical_into(&mut buf, &[1, 2]).unwrap();
+    assert_eq!(buf, b"[1,2]");
+
+    assert!(to_canonical_into(&mut buf, &[1.5]).is_err());
+    assert!(buf.is_empty());
+}
+
+#[cfg(feature = "ser")]
+#[test]
+fn test_fmt_writer() {
+    struct Event(serde_json::Value);
+
+    impl fmt::Display for Event {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            to_canonical_fmt(f, &self.0)
+                .map(|_| ())
+                .map_err(|_| fmt::Error)
+        }
+    }
+
+    let mut out = String::from("event: ");
+    let written = to_canonical_fmt(&mut out, &serde_json::json!({ "b": "本", "a": 1 })).unwrap();
+    assert_eq!(out, r#"event: {"a":1,"b":"本"}"#);
+    assert_eq!(written, out.len() - "event: ".len());
+
+    let event = Event(serde_json::json!({ "b": 2, "a": 1 }));
+    assert_eq!(event.to_string(), r#"{"a":1,"b":2}"#);
+}
+
+#[cfg(feature = "ser")]
 #[test]
 fn test_size_error() {
     #[derive(serde_derive::Serialize)]
@@ -549,3 +1316,199 @@ fn test_size_error() {
 
     assert!(matches!(to_canonical_string(&t), Err(Error::SizeLimit)))
 }
+
+#[cfg(feature = "ser")]
+#[test]
+fn test_writer_size_error() {
+    let t = vec!["a".to_string(); 65_535];
+
+    let mut buf = vec![];
+    assert!(matches!(
+        to_canonical_writer(&mut buf, &t),
+        Err(Error::SizeLimit)
+    ));
+    assert!(buf.len() <= DEFAULT_SIZE_LIMIT);
+}
+
+#[cfg(feature = "ser")]
+#[test]
+fn sorts_nested_values() {
+    #[derive(serde_derive::Serialize)]
+    struct Inner {
+        b: u8,
+        a: u8,
+    }
+
+    #[derive(serde_derive::Serialize)]
+    struct Outer {
+        list: Vec<Inner>,
+        inner: Inner,
+        #[serde(rename = "a!")]
+        bang: u8,
+        #[serde(rename = "a")]
+        plain: u8,
+    }
+
+    let outer = Outer {
+        list: vec![Inner { b: 2, a: 1 }],
+        inner: Inner { b: 2, a: 1 },
+        bang: 1,
+        plain: 0,
+    };
+    assert_eq!(
+        to_canonical_string(&outer).unwrap(),
+        r#"{"a":0,"a!":1,"inner":{"a":1,"b":2},"list":[{"a":1,"b":2}]}"#
+    );
+}
+
+#[cfg(feature = "ser")]
+#[test]
+fn flattened_fields() {
+    use std::collections::BTreeMap;
+
+    #[derive(serde_derive::Serialize)]
+    struct Unsigned {
+        age: u64,
+        transaction_id: &'static str,
+    }
+
+    #[derive(serde_derive::Serialize)]
+    struct Event {
+        #[serde(rename = "type")]
+        kind: &'static str,
+        #[serde(flatten)]
+        unsigned: Unsigned,
+        #[serde(flatten)]
+        extra: BTreeMap<&'static str, u8>,
+    }
+
+    let mut event = Event {
+        kind: "m.room.message",
+        unsigned: Unsigned {
+            age: 10,
+            transaction_id: "txn",
+        },
+        extra: vec![("depth", 3), ("b", 2)].into_iter().collect(),
+    };
+    assert_eq!(
+        to_canonical_string(&event).unwrap(),
+        r#"{"age":10,"b":2,"depth":3,"transaction_id":"txn","type":"m.room.message"}"#
+    );
+    assert_eq!(to_canonical_object(&event).unwrap().len(), 5);
+
+    event.extra.insert("age", 1);
+    assert!(matches!(
+        to_canonical_string(&event),
+        Err(Error::InvalidInput(msg)) if msg.contains("`age`")
+    ));
+    assert!(matches!(
+        Options::new().spill_threshold(Some(2)).to_string(&event),
+        Err(Error::InvalidInput(msg)) if msg.contains("`age`")
+    ));
+    assert!(matches!(
+        to_canonical_object(&event),
+        Err(Error::InvalidInput(msg)) if msg.contains("`age`")
+    ));
+}
+
+#[cfg(feature = "ser")]
+#[test]
+fn sorts_every_enum_representation() {
+    #[derive(serde_derive::Serialize)]
+    struct Payload {
+        z: u8,
+        a: u8,
+    }
+
+    #[derive(serde_derive::Serialize)]
+    enum External {
+        Unit,
+        Newtype(Payload),
+        Tuple(Payload, u8),
+        Struct { z: u8, a: Payload },
+    }
+
+    #[derive(serde_derive::Serialize)]
+    #[serde(tag = "type")]
+    enum Internal {
+        Struct { z: u8, a: u8 },
+        Newtype(Payload),
+    }
+
+    #[derive(serde_derive::Serialize)]
+    #[serde(tag = "t", content = "c")]
+    enum Adjacent {
+        Newtype(Payload),
+        Struct { z: u8, a: u8 },
+    }
+
+    #[derive(serde_derive::Serialize)]
+    #[serde(untagged)]
+    enum Untagged {
+        Struct { z: u8, a: u8 },
+    }
+
+    let cases = vec![
+        (to_canonical_string(&External::Unit), r#""Unit""#),
+        (
+            to_canonical_string(&External::Newtype(Payload { z: 1, a: 2 })),
+            r#"{"Newtype":{"a":2,"z":1}}"#,
+        ),
+        (
+            to_canonical_string(&External::Tuple(Payload { z: 1, a: 2 }, 3)),
+            r#"{"Tuple":[{"a":2,"z":1},3]}"#,
+        ),
+        (
+            to_canonical_string(&External::Struct {
+                z: 1,
+                a: Payload { z: 2, a: 3 },
+            }),
+            r#"{"Struct":{"a":{"a":3,"z":2},"z":1}}"#,
+        ),
+        (
+            to_canonical_string(&Internal::Struct { z: 1, a: 2 }),
+            r#"{"a":2,"type":"Struct","z":1}"#,
+        ),
+        (
+            to_canonical_string(&Internal::Newtype(Payload { z: 1, a: 2 })),
+            r#"{"a":2,"type":"Newtype","z":1}"#,
+        ),
+        (
+            to_canonical_string(&Adjacent::Newtype(Payload { z: 1, a: 2 })),
+            r#"{"c":{"a":2,"z":1},"t":"Newtype"}"#,
+        ),
+        (
+            to_canonical_string(&Adjacent::Struct { z: 1, a: 2 }),
+            r#"{"c":{"a":2,"z":1},"t":"Struct"}"#,
+        ),
+        (
+            to_canonical_string(&Untagged::Struct { z: 1, a: 2 }),
+            r#"{"a":2,"z":1}"#,
+        ),
+    ];
+    for (res, expected) in cases {
+        assert_eq!(res.unwrap(), expected);
+    }
+}
+
+#[cfg(feature = "ser")]
+#[test]
+fn bool_and_char_keys() {
+    use std::collections::{BTreeMap, HashMap};
+
+    let bools = vec![(true, 1), (false, 0)]
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    assert_eq!(
+        to_canonical_string(&bools).unwrap(),
+        r#"{"false":0,"true":1}"#
+    );
+
+    let chars = vec![('b', 2), ('"', 0), ('a', 1)]
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+    assert_eq!(
+        to_canonical_string(&chars).unwrap(),
+        r#"{"\"":0,"a":1,"b":2}"#
+    );
+}
@@ -0,0 +1,566 @@
+use std::fmt;
+
+use serde::{ser, serde_if_integer128, Serialize};
+
+use crate::{value::ObjectKeySerializer, Error, Result};
+
+/// The largest integer allowed in canonical JSON, `2^53 - 1`.
+pub const MAX_SAFE_INTEGER: i64 = 9_007_199_254_740_991;
+
+/// The smallest integer allowed in canonical JSON, `-(2^53 - 1)`.
+pub const MIN_SAFE_INTEGER: i64 = -MAX_SAFE_INTEGER;
+
+/// How bad a `Violation` is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The value can be serialized but other implementations may reject it. None of the checks
+    /// of this crate report warnings at the moment.
+    Warning,
+    /// The value can not be serialized as canonical JSON.
+    Error,
+}
+
+/// The kind of problem a `Violation` describes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// A floating point number.
+    Float,
+    /// An integer outside of `MIN_SAFE_INTEGER..=MAX_SAFE_INTEGER`.
+    IntegerOutOfRange,
+    /// A map key that does not serialize as a string.
+    NonStringKey,
+    /// The value does not match a JSON schema, `found` is the schema error.
+    Schema,
+    /// A key found twice in the same object when parsing, `found` is the key.
+    DuplicateKey,
+}
+
+/// A single part of a value that is not valid canonical JSON.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+    /// What is wrong with the value.
+    pub kind: ViolationKind,
+    /// Whether serialization fails because of this violation.
+    pub severity: Severity,
+    /// The JSON pointer of the offending value, empty for the root.
+    pub pointer: String,
+    /// The offending value as it was found.
+    pub found: String,
+    /// How the value could be fixed, if there is an obvious way.
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.pointer.is_empty() {
+            write!(f, "{}: ", self.pointer)?;
+        }
+        match self.kind {
+            ViolationKind::Float => write!(
+                f,
+                "float is not valid in canonical JSON found {}",
+                self.found
+            )?,
+            ViolationKind::IntegerOutOfRange => write!(
+                f,
+                "integer {} is outside of the canonical JSON range",
+                self.found
+            )?,
+            ViolationKind::NonStringKey => write!(f, "key must be a string found {}", self.found)?,
+            ViolationKind::Schema => write!(f, "{}", self.found)?,
+            ViolationKind::DuplicateKey => write!(f, "duplicate key {}", self.found)?,
+        }
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, ", {}", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/// Collect everything in `value` that is not valid canonical JSON instead of failing on the first
+/// problem.
+///
+/// Integers outside of the range the spec allows are errors, like they are for the serializer
+/// with the default `IntegerPolicy`.
+pub fn lint_value<T>(value: &T) -> Result<Vec<Violation>>
+where
+    T: ?Sized + Serialize,
+{
+    let mut linter = Linter {
+        path: vec![],
+        violations: vec![],
+    };
+    value.serialize(&mut linter)?;
+    Ok(linter.violations)
+}
+
+struct Linter {
+    path: Vec<String>,
+    violations: Vec<Violation>,
+}
+
+impl Linter {
+    fn report(&mut self, kind: ViolationKind, found: String, suggestion: Option<String>) {
+        let pointer = self
+            .path
+            .iter()
+            .map(|segment| format!("/{}", segment.replace('~', "~0").replace('/', "~1")))
+            .collect();
+        self.violations.push(Violation {
+            kind,
+            severity: Severity::Error,
+            pointer,
+            found,
+            suggestion,
+        });
+    }
+
+    fn check_integer(&mut self, value: i128) -> Result<()> {
+        if value > i128::from(MAX_SAFE_INTEGER) || value < i128::from(MIN_SAFE_INTEGER) {
+            self.report_integer(value.to_string());
+        }
+        Ok(())
+    }
+
+    fn report_integer(&mut self, found: String) {
+        self.report(
+            ViolationKind::IntegerOutOfRange,
+            found,
+            Some("encode the integer as a string".to_owned()),
+        );
+    }
+
+    fn report_float(&mut self, value: f64) {
+        let suggestion = if value.fract() == 0.0
+            && value <= MAX_SAFE_INTEGER as f64
+            && value >= MIN_SAFE_INTEGER as f64
+        {
+            format!("use the integer {}", value as i64)
+        } else {
+            "encode the number as a string".to_owned()
+        };
+        self.report(ViolationKind::Float, value.to_string(), Some(suggestion));
+    }
+
+    fn nested<T>(&mut self, segment: String, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.path.push(segment);
+        let res = value.serialize(&mut *self);
+        self.path.pop();
+        res
+    }
+}
+
+impl<'a> ser::Serializer for &'a mut Linter {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = LintCompound<'a>;
+    type SerializeTuple = LintCompound<'a>;
+    type SerializeTupleStruct = LintCompound<'a>;
+    type SerializeTupleVariant = LintCompound<'a>;
+    type SerializeMap = LintCompound<'a>;
+    type SerializeStruct = LintCompound<'a>;
+    type SerializeStructVariant = LintCompound<'a>;
+
+    fn serialize_bool(self, _value: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<()> {
+        self.check_integer(i128::from(value))
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<()> {
+        self.check_integer(i128::from(value))
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<()> {
+        self.check_integer(i128::from(value))
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<()> {
+        self.check_integer(i128::from(value))
+    }
+
+    serde_if_integer128! {
+        fn serialize_i128(self, value: i128) -> Result<()> {
+            self.check_integer(value)
+        }
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<()> {
+        self.check_integer(i128::from(value))
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<()> {
+        self.check_integer(i128::from(value))
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<()> {
+        self.check_integer(i128::from(value))
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<()> {
+        self.check_integer(i128::from(value))
+    }
+
+    serde_if_integer128! {
+        fn serialize_u128(self, value: u128) -> Result<()> {
+            if value > MAX_SAFE_INTEGER as u128 {
+                self.report_integer(value.to_string());
+            }
+            Ok(())
+        }
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<()> {
+        self.report_float(f64::from(value));
+        Ok(())
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<()> {
+        self.report_float(value);
+        Ok(())
+    }
+
+    fn serialize_char(self, _value: char) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_str(self, _value: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_bytes(self, _value: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.nested(variant.to_owned(), value)
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Ok(LintCompound::new(self, false))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Ok(LintCompound::new(self, false))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Ok(LintCompound::new(self, false))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        self.path.push(variant.to_owned());
+        Ok(LintCompound::new(self, true))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Ok(LintCompound::new(self, false))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Ok(LintCompound::new(self, false))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        self.path.push(variant.to_owned());
+        Ok(LintCompound::new(self, true))
+    }
+}
+
+struct LintCompound<'a> {
+    linter: &'a mut Linter,
+    index: usize,
+    next_key: Option<String>,
+    /// Whether the variant name of an enum was pushed onto the path.
+    variant: bool,
+}
+
+impl<'a> LintCompound<'a> {
+    fn new(linter: &'a mut Linter, variant: bool) -> Self {
+        Self {
+            linter,
+            index: 0,
+            next_key: None,
+            variant,
+        }
+    }
+
+    fn element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let res = self.linter.nested(self.index.to_string(), value);
+        self.index += 1;
+        res
+    }
+
+    fn end(self) -> Result<()> {
+        if self.variant {
+            self.linter.path.pop();
+        }
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeSeq for LintCompound<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        LintCompound::end(self)
+    }
+}
+
+impl<'a> ser::SerializeTuple for LintCompound<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        LintCompound::end(self)
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for LintCompound<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        LintCompound::end(self)
+    }
+}
+
+impl<'a> ser::SerializeTupleVariant for LintCompound<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        LintCompound::end(self)
+    }
+}
+
+impl<'a> ser::SerializeMap for LintCompound<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        match key.serialize(ObjectKeySerializer) {
+            Ok(key) => self.next_key = Some(key),
+            Err(_) => {
+                #[cfg(feature = "ser")]
+                let found = crate::to_canonical_string(key)
+                    .unwrap_or_else(|_| "a non-string key".to_owned());
+                #[cfg(not(feature = "ser"))]
+                let found = "a non-string key".to_owned();
+                self.linter.report(
+                    ViolationKind::NonStringKey,
+                    found,
+                    Some("use string keys".to_owned()),
+                );
+                self.next_key = None;
+            }
+        }
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        match self.next_key.take() {
+            Some(key) => self.linter.nested(key, value),
+            // The key was already reported, anything wrong with the value is reported at the
+            // object itself.
+            None => value.serialize(&mut *self.linter),
+        }
+    }
+
+    fn end(self) -> Result<()> {
+        LintCompound::end(self)
+    }
+}
+
+impl<'a> ser::SerializeStruct for LintCompound<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.linter.nested(key.to_owned(), value)
+    }
+
+    fn end(self) -> Result<()> {
+        LintCompound::end(self)
+    }
+}
+
+impl<'a> ser::SerializeStructVariant for LintCompound<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.linter.nested(key.to_owned(), value)
+    }
+
+    fn end(self) -> Result<()> {
+        LintCompound::end(self)
+    }
+}
+
+#[test]
+fn lint_reports_every_violation() {
+    use std::collections::BTreeMap;
+
+    #[derive(serde_derive::Serialize)]
+    struct Content {
+        scores: Vec<f64>,
+        flags: BTreeMap<(u8, u8), u8>,
+        #[serde(rename = "a/b")]
+        big: u64,
+    }
+
+    let mut flags = BTreeMap::new();
+    flags.insert((1, 2), 1);
+
+    let content = Content {
+        scores: vec![1.5, 2.5],
+        flags,
+        big: u64::MAX,
+    };
+
+    let violations = lint_value(&content).unwrap();
+    assert_eq!(
+        violations
+            .iter()
+            .map(|v| (v.kind, v.severity, v.pointer.as_str(), v.found.as_str()))
+            .collect::<Vec<_>>(),
+        vec![
+            (ViolationKind::Float, Severity::Error, "/scores/0", "1.5"),
+            (ViolationKind::Float, Severity::Error, "/scores/1", "2.5"),
+            (
+                ViolationKind::NonStringKey,
+                Severity::Error,
+                "/flags",
+                "[1,2]"
+            ),
+            (
+                ViolationKind::IntegerOutOfRange,
+                Severity::Error,
+                "/a~1b",
+                "18446744073709551615"
+            ),
+        ]
+    );
+    assert_eq!(
+        violations[0].to_string(),
+        "/scores/0: float is not valid in canonical JSON found 1.5, encode the number as a string"
+    );
+    assert_eq!(
+        lint_value(&3.0).unwrap()[0].suggestion.as_deref(),
+        Some("use the integer 3")
+    );
+    assert!(lint_value(&serde_json::json!({ "a": [1, "b", null] }))
+        .unwrap()
+        .is_empty());
+}
@@ -1,11 +1,11 @@
-use std::{fmt, io};
+use std::fmt;
 
 use serde::{
     ser::{self, Error as _, Impossible},
     serde_if_integer128, Serialize,
 };
 
-use crate::{serializer::Serializer, Error, Result};
+use crate::{error::key_must_be_a_string, serializer::Serializer, Error, Result, Sink};
 
 pub struct MapKeySerializer<'a, W: 'a> {
     pub ser: &'a mut Serializer<W>,
@@ -21,13 +21,9 @@ fn invalid_raw_value() -> Error {
     Error::custom("invalid raw value")
 }
 
-fn key_must_be_a_string() -> Error {
-    Error::custom("key must be a string")
-}
-
 impl<'a, W> ser::Serializer for MapKeySerializer<'a, W>
 where
-    W: io::Write,
+    W: Sink,
 {
     type Ok = ();
     type Error = Error;
@@ -63,8 +59,9 @@ where
     type SerializeStruct = Impossible<(), Error>;
     type SerializeStructVariant = Impossible<(), Error>;
 
-    fn serialize_bool(self, _value: bool) -> Result<()> {
-        Err(key_must_be_a_string())
+    fn serialize_bool(self, value: bool) -> Result<()> {
+        // Like serde_json, `true` and `false` keys are quoted.
+        self.ser.serialize_str(if value { "true" } else { "false" })
     }
 
     #[inline]
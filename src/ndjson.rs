@@ -0,0 +1,158 @@
+use std::{io::BufRead, marker::PhantomData, str};
+
+use serde::de::DeserializeOwned;
+
+use crate::{from_canonical_value, from_slice, is_canonical, CanonicalJsonValue, Error, Result};
+
+/// Reads newline delimited JSON, one `CanonicalJsonValue` per line. Empty lines are skipped.
+///
+/// Errors are wrapped in `Error::Line` so they point at the line they were found on, the
+/// iterator keeps going after an error so a single bad line does not end a replay.
+pub struct NdjsonReader<R> {
+    reader: R,
+    buf: Vec<u8>,
+    line: usize,
+    strict: bool,
+}
+
+impl<R: BufRead> NdjsonReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: vec![],
+            line: 0,
+            strict: false,
+        }
+    }
+
+    /// Reject lines that are valid JSON but not already canonical JSON.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// The number of the last line read, starting at 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Deserialize every line into a `T` instead of a `CanonicalJsonValue`.
+    pub fn typed<T: DeserializeOwned>(self) -> TypedNdjsonReader<R, T> {
+        TypedNdjsonReader {
+            inner: self,
+            _type: PhantomData,
+        }
+    }
+
+    fn at_line(&self, error: Error) -> Error {
+        at_line(self.line, error)
+    }
+}
+
+impl<R: BufRead> Iterator for NdjsonReader<R> {
+    type Item = Result<CanonicalJsonValue>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.buf.clear();
+            match self.reader.read_until(b'\n', &mut self.buf) {
+                Ok(0) => return None,
+                Ok(_) => self.line += 1,
+                Err(err) => return Some(Err(Error::io(err))),
+            }
+
+            if is_blank(&self.buf) {
+                continue;
+            }
+            return Some(parse_line(&self.buf, self.strict).map_err(|e| self.at_line(e)));
+        }
+    }
+}
+
+/// The iterator returned by `NdjsonReader::typed`.
+pub struct TypedNdjsonReader<R, T> {
+    inner: NdjsonReader<R>,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<R, T> Iterator for TypedNdjsonReader<R, T>
+where
+    R: BufRead,
+    T: DeserializeOwned,
+{
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let value = self.inner.next()?;
+        Some(value.and_then(|value| from_canonical_value(value).map_err(|e| self.inner.at_line(e))))
+    }
+}
+
+/// Parse a line that is not blank, with or without its line break.
+pub(crate) fn parse_line(line: &[u8], strict: bool) -> Result<CanonicalJsonValue> {
+    let line = trim_newline(line);
+    let value = from_slice(line)?;
+    // `from_slice` already made sure the line is valid UTF-8.
+    if strict && !str::from_utf8(line).map(is_canonical).unwrap_or(false) {
+        return Err(Error::InvalidInput(
+            "line is valid JSON but not canonical JSON".to_owned(),
+        ));
+    }
+    Ok(value)
+}
+
+pub(crate) fn is_blank(line: &[u8]) -> bool {
+    trim_newline(line).iter().all(u8::is_ascii_whitespace)
+}
+
+pub(crate) fn at_line(line: usize, error: Error) -> Error {
+    Error::Line {
+        line,
+        error: Box::new(error),
+    }
+}
+
+fn trim_newline(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+#[test]
+fn read_event_log() {
+    let log = b"{\"a\":1}\n\n{\"b\": 2}\r\n[1.5]\n{\"c\":\n";
+
+    let lines = NdjsonReader::new(&log[..]).collect::<Vec<_>>();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(
+        lines[1].as_ref().unwrap().get("b"),
+        Some(&CanonicalJsonValue::Integer(2))
+    );
+    assert!(matches!(
+        &lines[2],
+        Err(Error::Line { line: 4, error }) if matches!(**error, Error::InvalidInput(_))
+    ));
+    assert!(matches!(
+        &lines[3],
+        Err(Error::Line { line: 5, error }) if matches!(**error, Error::Syntax { offset: 5, .. })
+    ));
+
+    let strict = NdjsonReader::new(&b"{\"a\":1}\n{\"b\": 2}\n"[..])
+        .strict(true)
+        .collect::<Vec<_>>();
+    assert!(strict[0].is_ok());
+    assert!(matches!(&strict[1], Err(Error::Line { line: 2, .. })));
+}
+
+#[test]
+fn read_typed_lines() {
+    #[derive(Debug, PartialEq, serde_derive::Deserialize)]
+    struct Event {
+        a: u8,
+    }
+
+    let events = NdjsonReader::new(&b"{\"a\":1}\n{\"a\":2}\n"[..])
+        .typed::<Event>()
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(events, vec![Event { a: 1 }, Event { a: 2 }]);
+}
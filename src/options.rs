@@ -0,0 +1,916 @@
+#[cfg(feature = "de")]
+use std::borrow::Cow;
+use std::{cmp::Ordering, fmt, io, sync::Arc};
+
+use serde::Serialize;
+
+#[cfg(all(feature = "de", feature = "tokio"))]
+use crate::async_read::read_limited;
+#[cfg(feature = "metrics")]
+use crate::stats;
+#[cfg(not(feature = "de"))]
+use crate::to_canonical_value;
+use crate::{
+    check_pdu, jcs,
+    writer::{LimitedWriter, StagingWriter},
+    CanonicalJson, Error, Result,
+};
+#[cfg(feature = "de")]
+use crate::{
+    from_slice, read::repair_utf8, value::from_parser, CanonicalJsonValue, NumberPolicy, Parser,
+};
+
+/// The largest output `to_canonical_string` produces before failing with `Error::SizeLimit`.
+pub const DEFAULT_SIZE_LIMIT: usize = 65_535;
+
+/// The set of canonicalization rules to follow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Profile {
+    /// The Matrix spec's canonical JSON: integers only, keys sorted by code point.
+    Matrix,
+    /// RFC 8785, the JSON Canonicalization Scheme: floats are written like ECMAScript does and
+    /// keys are sorted by their UTF-16 code units. Integers must be exactly representable as
+    /// an `f64`.
+    Jcs,
+}
+
+impl Profile {
+    /// The order object keys are written in.
+    pub(crate) fn cmp_keys(self, a: &str, b: &str) -> Ordering {
+        match self {
+            Profile::Matrix => a.cmp(b),
+            Profile::Jcs => jcs::cmp_keys(a, b),
+        }
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::Matrix
+    }
+}
+
+/// The order object keys are written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyOrder {
+    /// Sorted as the profile says, which canonical JSON requires.
+    Sorted,
+    /// The order they were serialized in, like serde_json writes them. The output is compact
+    /// JSON with the same escaping and numbers but not canonical JSON, for logs and debugging.
+    /// Duplicate keys are not detected.
+    Insertion,
+}
+
+impl Default for KeyOrder {
+    fn default() -> Self {
+        Self::Sorted
+    }
+}
+
+/// What unit structs like `struct Marker;` serialize to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnitPolicy {
+    /// `null`, what serde_json does.
+    Null,
+    /// An empty object `{}`, what a Matrix payload usually wants for marker types.
+    EmptyObject,
+    /// Fail with `Error::InvalidInput`.
+    Error,
+}
+
+impl Default for UnitPolicy {
+    fn default() -> Self {
+        Self::Null
+    }
+}
+
+/// What integers outside of `MIN_SAFE_INTEGER..=MAX_SAFE_INTEGER` serialize to, the ones inside
+/// of it are written as plain integers.
+///
+/// The range is the same for every integer type and profile, a `u64` past it fails with
+/// `IntegerPolicy::Error` just like a `u128` of the same value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntegerPolicy {
+    /// Fail with `Error::IntegerOutOfRange`.
+    Error,
+    /// A string of the decimal digits like `"340282366920938463463374607431768211455"`, for
+    /// payloads that need the full range such as metadata that is not part of an event. Every
+    /// integer outside of the range is quoted, 64-bit ones included.
+    ///
+    /// This is not Matrix canonical JSON, a server reading the output gets a string where the
+    /// number was and signatures over it won't match the ones of other implementations.
+    String,
+}
+
+impl Default for IntegerPolicy {
+    fn default() -> Self {
+        Self::Error
+    }
+}
+
+/// What floats serialize to with the Matrix profile, which has none. The JCS profile always
+/// writes them as numbers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FloatPolicy {
+    /// Fail with `Error::InvalidInput`.
+    Error,
+    /// Integral floats inside of the canonical integer range like `2.0` as the integer `2`, the
+    /// others as a string of the shortest decimal that reads back as the same float, like
+    /// `"0.1"` or `"1e+300"`. NaN and the infinities still fail.
+    ///
+    /// For payloads outside of events such as analytics that carry floats but still need the
+    /// same bytes every time. This is not Matrix canonical JSON.
+    String,
+}
+
+impl Default for FloatPolicy {
+    fn default() -> Self {
+        Self::Error
+    }
+}
+
+/// The callback of `Options::size_warning`.
+#[derive(Clone)]
+struct SizeWarning {
+    threshold: usize,
+    callback: Arc<dyn Fn(usize) + Send + Sync>,
+}
+
+impl fmt::Debug for SizeWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SizeWarning")
+            .field("threshold", &self.threshold)
+            .finish()
+    }
+}
+
+/// A callback of `Options::on_key` or `Options::on_string`.
+#[derive(Clone)]
+struct Hook(Arc<dyn Fn(&str, &str) + Send + Sync>);
+
+impl fmt::Debug for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Hook")
+    }
+}
+
+/// The callback of `Options::lossy_utf8`.
+#[cfg(feature = "de")]
+#[derive(Clone)]
+struct LossyUtf8(Arc<dyn Fn(usize) + Send + Sync>);
+
+#[cfg(feature = "de")]
+impl fmt::Debug for LossyUtf8 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("LossyUtf8")
+    }
+}
+
+/// Configures how values are serialized into canonical JSON.
+#[derive(Clone, Debug)]
+pub struct Options {
+    size_limit: Option<usize>,
+    atomic: bool,
+    pub(crate) profile: Profile,
+    pub(crate) key_order: KeyOrder,
+    pub(crate) spill_threshold: Option<usize>,
+    pub(crate) object_buffer_limit: Option<usize>,
+    pub(crate) total_buffer_limit: Option<usize>,
+    pub(crate) max_string_len: Option<usize>,
+    pub(crate) unit_policy: UnitPolicy,
+    pub(crate) integer_policy: IntegerPolicy,
+    pub(crate) float_policy: FloatPolicy,
+    pdu_schema: bool,
+    size_warning: Option<SizeWarning>,
+    on_key: Option<Hook>,
+    on_string: Option<Hook>,
+    #[cfg(feature = "de")]
+    lossy_utf8: Option<LossyUtf8>,
+    #[cfg(feature = "de")]
+    number_policy: NumberPolicy,
+    #[cfg(feature = "de")]
+    collect_errors: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            size_limit: Some(DEFAULT_SIZE_LIMIT),
+            atomic: false,
+            profile: Profile::Matrix,
+            key_order: KeyOrder::Sorted,
+            spill_threshold: None,
+            object_buffer_limit: None,
+            total_buffer_limit: None,
+            max_string_len: None,
+            unit_policy: UnitPolicy::Null,
+            integer_policy: IntegerPolicy::Error,
+            float_policy: FloatPolicy::Error,
+            pdu_schema: false,
+            size_warning: None,
+            on_key: None,
+            on_string: None,
+            #[cfg(feature = "de")]
+            lossy_utf8: None,
+            #[cfg(feature = "de")]
+            number_policy: NumberPolicy::NegativeZero,
+            #[cfg(feature = "de")]
+            collect_errors: false,
+        }
+    }
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The maximum size of the output in bytes, `None` disables the check.
+    pub fn size_limit(mut self, limit: Option<usize>) -> Self {
+        self.size_limit = limit;
+        self
+    }
+
+    /// Buffer the whole output and only hand it to the writer of `to_writer` once serialization
+    /// succeeded, so an error never leaves partial output behind.
+    pub fn atomic(mut self, atomic: bool) -> Self {
+        self.atomic = atomic;
+        self
+    }
+
+    /// The canonicalization rules to follow, `Profile::Matrix` by default. The size limit applies
+    /// to every profile, JCS payloads usually want `size_limit(None)`.
+    pub fn profile(mut self, profile: Profile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// The order object keys are written in, `KeyOrder::Sorted` by default.
+    pub fn key_order(mut self, order: KeyOrder) -> Self {
+        self.key_order = order;
+        self
+    }
+
+    /// Sort the entries of objects in chunks of `threshold` that are written to temporary files
+    /// and merged at the end, so huge objects like the state of a big room don't have to be
+    /// buffered in memory. `None`, the default, never spills.
+    pub fn spill_threshold(mut self, threshold: Option<usize>) -> Self {
+        self.spill_threshold = threshold;
+        self
+    }
+
+    /// The most bytes a single object may buffer while its entries are sorted, going over fails
+    /// with `Error::BufferLimit`. Entries spilled to disk don't count.
+    pub fn object_buffer_limit(mut self, limit: Option<usize>) -> Self {
+        self.object_buffer_limit = limit;
+        self
+    }
+
+    /// The most bytes all objects being sorted at the same time may buffer together, going over
+    /// fails with `Error::BufferLimit`.
+    pub fn total_buffer_limit(mut self, limit: Option<usize>) -> Self {
+        self.total_buffer_limit = limit;
+        self
+    }
+
+    /// The longest string or object key in bytes, checked when serializing and by `from_slice`
+    /// so a single huge string fails before the overall size limit is reached.
+    pub fn max_string_len(mut self, limit: Option<usize>) -> Self {
+        self.max_string_len = limit;
+        self
+    }
+
+    /// What unit structs serialize to, `UnitPolicy::Null` by default. `()`, `None` and JSON
+    /// `null` values can not be told apart from each other and stay `null`.
+    pub fn unit_policy(mut self, policy: UnitPolicy) -> Self {
+        self.unit_policy = policy;
+        self
+    }
+
+    /// What integers outside of the canonical range serialize to, `IntegerPolicy::Error` by
+    /// default.
+    pub fn integer_policy(mut self, policy: IntegerPolicy) -> Self {
+        self.integer_policy = policy;
+        self
+    }
+
+    /// What floats serialize to with the Matrix profile, `FloatPolicy::Error` by default.
+    pub fn float_policy(mut self, policy: FloatPolicy) -> Self {
+        self.float_policy = policy;
+        self
+    }
+
+    /// Check that the output is a PDU with every required field, see `check_pdu`. Nothing is
+    /// written to the writer of `to_writer` if the check fails.
+    pub fn pdu_schema(mut self, pdu_schema: bool) -> Self {
+        self.pdu_schema = pdu_schema;
+        self
+    }
+
+    /// Call `callback` with the length of the output whenever a value serializes to at least
+    /// `threshold` bytes, like 80% of the size limit so clients can warn before a message
+    /// becomes unsendable.
+    pub fn size_warning<F>(mut self, threshold: usize, callback: F) -> Self
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        self.size_warning = Some(SizeWarning {
+            threshold,
+            callback: Arc::new(callback),
+        });
+        self
+    }
+
+    fn warn_size(&self, len: usize) {
+        if let Some(warning) = &self.size_warning {
+            if len >= warning.threshold {
+                (warning.callback)(len);
+            }
+        }
+    }
+
+    /// Call `hook` with the JSON pointer of the object and the key for every key written, for
+    /// audits like secret scanning without parsing the output again. Keys are visited in the
+    /// order they are serialized, not the canonical order.
+    pub fn on_key<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&str, &str) + Send + Sync + 'static,
+    {
+        self.on_key = Some(Hook(Arc::new(hook)));
+        self
+    }
+
+    /// Call `hook` with the JSON pointer and the value of every string written, object keys
+    /// excluded.
+    pub fn on_string<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&str, &str) + Send + Sync + 'static,
+    {
+        self.on_string = Some(Hook(Arc::new(hook)));
+        self
+    }
+
+    /// Whether the serializer has to keep track of where it is for the hooks.
+    pub(crate) fn has_hooks(&self) -> bool {
+        self.on_key.is_some() || self.on_string.is_some()
+    }
+
+    pub(crate) fn visit_key(&self, pointer: &str, key: &str) {
+        if let Some(Hook(hook)) = &self.on_key {
+            hook(pointer, key);
+        }
+    }
+
+    pub(crate) fn visit_string(&self, pointer: &str, value: &str) {
+        if let Some(Hook(hook)) = &self.on_string {
+            hook(pointer, value);
+        }
+    }
+
+    /// Replace invalid UTF-8 and escapes of lone surrogates by U+FFFD when parsing instead of
+    /// failing, for payloads bridged from systems that don't check their strings. `report` is
+    /// called with the byte offset in the input of every replacement.
+    ///
+    /// Replacing invalid bytes changes the length of the input, the offsets of syntax errors
+    /// that come after a replacement are the ones in the repaired input.
+    #[cfg(feature = "de")]
+    pub fn lossy_utf8<F>(mut self, report: F) -> Self
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        self.lossy_utf8 = Some(LossyUtf8(Arc::new(report)));
+        self
+    }
+
+    /// How parsing reads integers written differently than canonical JSON writes them, like
+    /// `-0` or `1.0`, `NumberPolicy::NegativeZero` by default. The errors name the number and
+    /// its offset in the input.
+    #[cfg(feature = "de")]
+    pub fn number_policy(mut self, policy: NumberPolicy) -> Self {
+        self.number_policy = policy;
+        self
+    }
+
+    /// Keep parsing after numbers the number policy doesn't accept and duplicate keys, and fail
+    /// with all of them in `Error::Violations` with their JSON pointers instead of the first
+    /// one, for linters and import tools. Syntax errors still stop parsing.
+    #[cfg(feature = "de")]
+    pub fn collect_errors(mut self, collect: bool) -> Self {
+        self.collect_errors = collect;
+        self
+    }
+
+    /// Parse a JSON document into a `CanonicalJsonValue`, following the string length limit and
+    /// the number policy.
+    #[cfg(feature = "de")]
+    pub fn from_slice(&self, input: &[u8]) -> Result<CanonicalJsonValue> {
+        let input = match &self.lossy_utf8 {
+            Some(LossyUtf8(report)) => repair_utf8(input, &**report),
+            None => Cow::Borrowed(input),
+        };
+        let parser = Parser::new(&input)
+            .max_string_len(self.max_string_len)
+            .allow_leading_zeros(self.number_policy == NumberPolicy::Normalize);
+        from_parser(parser, self.number_policy, self.collect_errors)
+    }
+
+    /// Read a JSON document from `reader` and parse it like `from_slice` does. The size limit
+    /// applies to the input, reading stops with `Error::SizeLimit` as soon as it is exceeded so
+    /// the buffered input never grows past it. Federation transactions hold up to 50 PDUs and
+    /// usually want a bigger limit than the default.
+    #[cfg(all(feature = "de", feature = "tokio"))]
+    pub async fn from_async_reader<R>(&self, reader: R) -> Result<CanonicalJsonValue>
+    where
+        R: tokio::io::AsyncRead + Unpin,
+    {
+        let input = read_limited(reader, self.size_limit).await?;
+        self.from_slice(&input)
+    }
+
+    /// Serialize `value` into `writer`, failing with `Error::SizeLimit` as soon as the output
+    /// grows past the size limit. Returns the number of bytes written.
+    pub fn to_writer<W, T>(&self, mut writer: W, value: &T) -> Result<usize>
+    where
+        W: io::Write,
+        T: ?Sized + Serialize,
+    {
+        if self.atomic || self.pdu_schema {
+            let buf = self.to_vec(value)?;
+            writer.write_all(&buf).map_err(Error::io)?;
+            return Ok(buf.len());
+        }
+
+        let mut ser = CanonicalJson::with_options(
+            LimitedWriter::new(StagingWriter::new(writer), self.size_limit),
+            self.clone(),
+        );
+        let res = value.serialize(&mut ser);
+
+        let writer = ser.into_inner();
+        let written = writer.written();
+        #[cfg(feature = "metrics")]
+        stats::record(&res, written);
+        res?;
+        writer.into_inner().finish().map_err(Error::io)?;
+        self.warn_size(written);
+        Ok(written)
+    }
+
+    pub fn to_vec<T>(&self, value: &T) -> Result<Vec<u8>>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.to_vec_with_capacity(128, value)
+    }
+
+    /// Like `to_vec` but the output buffer starts out with room for `capacity` bytes.
+    pub fn to_vec_with_capacity<T>(&self, capacity: usize, value: &T) -> Result<Vec<u8>>
+    where
+        T: ?Sized + Serialize,
+    {
+        let mut writer = Vec::with_capacity(capacity);
+        let mut ser = CanonicalJson::with_options(
+            LimitedWriter::new(&mut writer, self.size_limit),
+            self.clone(),
+        );
+        let res = value.serialize(&mut ser);
+        #[cfg(feature = "metrics")]
+        stats::record(&res, writer.len());
+        res?;
+
+        if self.pdu_schema {
+            // Without the parser the value is converted a second time instead.
+            #[cfg(feature = "de")]
+            check_pdu(&from_slice(&writer)?)?;
+            #[cfg(not(feature = "de"))]
+            check_pdu(&to_canonical_value(value)?)?;
+        }
+        self.warn_size(writer.len());
+        Ok(writer)
+    }
+
+    pub fn to_string<T>(&self, value: &T) -> Result<String>
+    where
+        T: ?Sized + Serialize,
+    {
+        let vec = self.to_vec(value)?;
+        Ok(
+            // serde_json does this so we can too.
+            unsafe { String::from_utf8_unchecked(vec) },
+        )
+    }
+}
+
+#[test]
+fn unlimited_size() {
+    let big = vec!["a"; 65_535];
+
+    assert!(matches!(
+        Options::new().to_string(&big),
+        Err(Error::SizeLimit)
+    ));
+    assert_eq!(
+        Options::new().size_limit(None).to_vec(&big).unwrap().len(),
+        // Each element is `"a"` plus a comma, except the last, and the brackets.
+        65_535 * 4 + 1
+    );
+}
+
+#[test]
+fn custom_size_limit() {
+    assert_eq!(
+        Options::new()
+            .size_limit(Some(7))
+            .to_string(&[1, 2, 3])
+            .unwrap(),
+        "[1,2,3]"
+    );
+    assert!(matches!(
+        Options::new().size_limit(Some(6)).to_string(&[1, 2, 3]),
+        Err(Error::SizeLimit)
+    ));
+}
+
+#[test]
+fn atomic_writer_leaves_no_partial_output() {
+    let big = vec!["a"; 65_535];
+
+    let mut buf = vec![];
+    assert!(matches!(
+        Options::new().atomic(true).to_writer(&mut buf, &big),
+        Err(Error::SizeLimit)
+    ));
+    assert!(buf.is_empty());
+
+    let written = Options::new()
+        .atomic(true)
+        .to_writer(&mut buf, &["a"])
+        .unwrap();
+    assert_eq!(buf, br#"["a"]"#);
+    assert_eq!(written, 5);
+}
+
+#[test]
+fn spills_large_objects() {
+    let map = (0..100)
+        .map(|i| (format!("key{}", i), vec![i]))
+        .collect::<std::collections::HashMap<_, _>>();
+
+    let expected = Options::new().to_string(&map).unwrap();
+    // A threshold of 1 makes more runs than are merged at once.
+    for threshold in &[7, 1] {
+        assert_eq!(
+            Options::new()
+                .spill_threshold(Some(*threshold))
+                .to_string(&map)
+                .unwrap(),
+            expected
+        );
+    }
+}
+
+#[test]
+fn buffer_limits() {
+    let json = serde_json::json!({ "a": { "b": "ccc", "d": "e" }, "f": 1 });
+
+    // Each key is buffered next to its pair. The inner object buffers `b"b":"ccc"` and
+    // `d"d":"e"`, 18 bytes, and is released before the outer object buffers
+    // `a"a":{"b":"ccc","d":"e"}` and `f"f":1`, 30 bytes.
+    for limit in &[18, 30] {
+        let res = Options::new()
+            .object_buffer_limit(Some(*limit))
+            .to_string(&json);
+        assert_eq!(res.is_ok(), *limit == 30);
+    }
+    assert!(Options::new()
+        .total_buffer_limit(Some(30))
+        .to_string(&json)
+        .is_ok());
+    assert!(matches!(
+        Options::new().total_buffer_limit(Some(29)).to_string(&json),
+        Err(Error::BufferLimit)
+    ));
+}
+
+#[cfg(feature = "de")]
+#[test]
+fn string_length_limit() {
+    let opts = Options::new().max_string_len(Some(3));
+
+    assert!(opts.to_string(&["abc"]).is_ok());
+    assert!(matches!(
+        opts.to_string(&["abcd"]),
+        Err(Error::InvalidInput(_))
+    ));
+    assert!(matches!(
+        opts.to_string(&serde_json::json!({ "abcd": 1 })),
+        Err(Error::InvalidInput(_))
+    ));
+    assert!(opts.from_slice(br#"{"abc":"abc"}"#).is_ok());
+    assert!(matches!(
+        opts.from_slice(br#"{"abcd":1}"#),
+        Err(Error::Syntax { offset: 1, .. })
+    ));
+}
+
+#[cfg(feature = "de")]
+#[test]
+fn lossy_utf8() {
+    use std::sync::Mutex;
+
+    let input = b"{\"body\":\"a\xC3\",\"k\\ud800\":1}";
+    assert!(matches!(
+        Options::new().from_slice(input),
+        Err(Error::Syntax { offset: 10, .. })
+    ));
+
+    let offsets = Arc::new(Mutex::new(vec![]));
+    let opts = Options::new().lossy_utf8({
+        let offsets = offsets.clone();
+        move |offset| offsets.lock().unwrap().push(offset)
+    });
+    let value = opts.from_slice(input).unwrap();
+    assert_eq!(
+        opts.to_string(&value).unwrap(),
+        "{\"body\":\"a\u{FFFD}\",\"k\u{FFFD}\":1}"
+    );
+    assert_eq!(*offsets.lock().unwrap(), vec![10, 15]);
+}
+
+#[test]
+fn pdu_schema_mode() {
+    let mut buf = vec![];
+    let res = Options::new()
+        .pdu_schema(true)
+        .to_writer(&mut buf, &serde_json::json!({ "type": "m.room.message" }));
+
+    assert!(matches!(res, Err(Error::Violations(v)) if v.len() == 4));
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn size_warning() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let seen = Arc::new(AtomicUsize::new(0));
+    let opts = Options::new().size_warning(7, {
+        let seen = seen.clone();
+        move |len| seen.store(len, Ordering::SeqCst)
+    });
+
+    opts.to_string(&[1, 2]).unwrap();
+    assert_eq!(seen.load(Ordering::SeqCst), 0);
+    opts.to_string(&[1, 2, 3]).unwrap();
+    assert_eq!(seen.load(Ordering::SeqCst), 7);
+    opts.to_writer(io::sink(), &[1, 2, 3, 4]).unwrap();
+    assert_eq!(seen.load(Ordering::SeqCst), 9);
+}
+
+#[test]
+fn visitor_hooks() {
+    use std::sync::Mutex;
+
+    let seen = Arc::new(Mutex::new(vec![]));
+    let opts = Options::new()
+        .on_key({
+            let seen = seen.clone();
+            move |pointer, key| {
+                seen.lock()
+                    .unwrap()
+                    .push(format!("key {} {}", pointer, key))
+            }
+        })
+        .on_string({
+            let seen = seen.clone();
+            move |pointer, value| seen.lock().unwrap().push(format!("{} {}", pointer, value))
+        });
+
+    let json = serde_json::json!({ "a/b": ["x", { "token": "secret" }], "n": 1 });
+    assert_eq!(
+        opts.to_string(&json).unwrap(),
+        r#"{"a/b":["x",{"token":"secret"}],"n":1}"#
+    );
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec![
+            "key  a/b",
+            "/a~1b/0 x",
+            "key /a~1b/1 token",
+            "/a~1b/1/token secret",
+            "key  n",
+        ]
+    );
+}
+
+#[test]
+fn unit_policies() {
+    #[derive(serde_derive::Serialize)]
+    struct Marker;
+
+    #[derive(serde_derive::Serialize)]
+    struct Content {
+        marker: Marker,
+        unit: (),
+        none: Option<u8>,
+    }
+
+    let content = Content {
+        marker: Marker,
+        unit: (),
+        none: None,
+    };
+    assert_eq!(
+        Options::new().to_string(&content).unwrap(),
+        r#"{"marker":null,"none":null,"unit":null}"#
+    );
+    assert_eq!(
+        Options::new()
+            .unit_policy(UnitPolicy::EmptyObject)
+            .to_string(&content)
+            .unwrap(),
+        r#"{"marker":{},"none":null,"unit":null}"#
+    );
+    assert!(matches!(
+        Options::new()
+            .unit_policy(UnitPolicy::Error)
+            .to_string(&content),
+        Err(Error::InvalidInput(_))
+    ));
+    assert!(Options::new()
+        .unit_policy(UnitPolicy::Error)
+        .to_string(&serde_json::json!([null, ()]))
+        .is_ok());
+}
+
+#[test]
+fn jcs_profile() {
+    // Put together from the examples in RFC 8785 section 3.2.
+    let json = serde_json::json!({
+        "numbers": [333333333.33333329, 1E30, 4.50, 2e-3, 0.000000000000000000000000001],
+        "string": "\u{20ac}$\u{f}\nA'B\"\\\\\"/",
+        "literals": [null, true, false],
+        "\u{20ac}": 1,
+        "\r": 2,
+        "\u{1f600}": 3,
+        "\u{fb33}": 4
+    });
+    let jcs = Options::new().profile(Profile::Jcs);
+
+    assert_eq!(
+        jcs.to_string(&json).unwrap(),
+        r#"{"\r":2,"literals":[null,true,false],"numbers":[333333333.3333333,1e+30,4.5,0.002,1e-27],"string":"€$\u000f\nA'B\"\\\\\"/","€":1,"😀":3,"דּ":4}"#
+    );
+    assert!(jcs.to_string(&f64::NAN).is_err());
+    assert!(jcs.to_string(&(1_u64 << 53)).is_err());
+    assert!(Options::new().to_string(&json).is_err());
+}
+
+#[test]
+fn out_of_range_integer_pointer() {
+    let jcs = Options::new().profile(Profile::Jcs);
+
+    let json = serde_json::json!({ "a": [0, { "b/c": 1_u64 << 53 }] });
+    match jcs.to_string(&json) {
+        Err(Error::IntegerOutOfRange { pointer, value }) => {
+            assert_eq!(pointer, "/a/1/b~1c");
+            assert_eq!(value, "9007199254740992");
+        }
+        other => panic!("expected an out of range integer found {:?}", other),
+    }
+    assert_eq!(
+        jcs.to_string(&(1_u64 << 53)).unwrap_err().to_string(),
+        "integer 9007199254740992 is outside of the canonical JSON range"
+    );
+}
+
+#[test]
+fn out_of_range_u64_pointer() {
+    let json = serde_json::json!({ "content": { "n": 9_007_199_254_740_992_u64 } });
+    match Options::new().to_string(&json) {
+        Err(Error::IntegerOutOfRange { pointer, value }) => {
+            assert_eq!(pointer, "/content/n");
+            assert_eq!(value, "9007199254740992");
+        }
+        other => panic!("expected an out of range integer found {:?}", other),
+    }
+}
+
+#[test]
+fn integer_policies() {
+    let max = i128::from(crate::MAX_SAFE_INTEGER);
+
+    assert_eq!(Options::new().to_string(&max).unwrap(), "9007199254740991");
+    assert_eq!(
+        Options::new().to_string(&-max).unwrap(),
+        "-9007199254740991"
+    );
+    assert!(matches!(
+        Options::new().to_string(&(max + 1)),
+        Err(Error::IntegerOutOfRange { .. })
+    ));
+    assert!(matches!(
+        Options::new().to_string(&u128::MAX),
+        Err(Error::IntegerOutOfRange { .. })
+    ));
+    // The same value fails whatever the type it has.
+    assert!(matches!(
+        Options::new().to_string(&(max as u64 + 1)),
+        Err(Error::IntegerOutOfRange { .. })
+    ));
+    assert!(matches!(
+        Options::new().to_string(&(-max as i64 - 1)),
+        Err(Error::IntegerOutOfRange { .. })
+    ));
+
+    let strings = Options::new().integer_policy(IntegerPolicy::String);
+    assert_eq!(
+        strings.to_string(&[u128::MAX, 1]).unwrap(),
+        r#"["340282366920938463463374607431768211455",1]"#
+    );
+    assert_eq!(
+        strings.to_string(&-(max + 1)).unwrap(),
+        r#""-9007199254740992""#
+    );
+}
+
+#[test]
+fn big_integers_as_strings() {
+    let big = serde_json::json!({ "id": u64::MAX, "small": 1, "negative": i64::MIN });
+
+    assert!(matches!(
+        Options::new().to_string(&big),
+        Err(Error::IntegerOutOfRange { .. })
+    ));
+    let expected = r#"{"id":"18446744073709551615","negative":"-9223372036854775808","small":1}"#;
+    let strings = Options::new().integer_policy(IntegerPolicy::String);
+    assert_eq!(strings.to_string(&big).unwrap(), expected);
+    assert_eq!(
+        strings.profile(Profile::Jcs).to_string(&big).unwrap(),
+        expected
+    );
+}
+
+#[test]
+fn floats_as_strings() {
+    let value = serde_json::json!({ "avg": 0.1, "count": 2.0, "huge": 1e300, "neg": -0.0 });
+
+    assert!(matches!(
+        Options::new().to_string(&value),
+        Err(Error::InvalidInput(_))
+    ));
+    let strings = Options::new().float_policy(FloatPolicy::String);
+    let json = strings.to_string(&value).unwrap();
+    assert_eq!(json, r#"{"avg":"0.1","count":2,"huge":"1e+300","neg":0}"#);
+    assert_eq!(strings.to_string(&value).unwrap(), json);
+    assert_eq!("1e+300".parse::<f64>().unwrap(), 1e300);
+
+    assert_eq!(strings.to_string(&1.5_f32).unwrap(), r#""1.5""#);
+    assert_eq!(strings.to_string(&0.1_f32).unwrap(), r#""0.1""#);
+    assert_eq!(strings.to_string(&-3.4e38_f32).unwrap(), r#""-3.4e+38""#);
+    assert_eq!(
+        strings.to_string(&9007199254740992.0).unwrap(),
+        r#""9007199254740992""#
+    );
+    assert!(matches!(
+        strings.to_string(&f64::NAN),
+        Err(Error::InvalidInput(_))
+    ));
+    assert_eq!(
+        strings.profile(Profile::Jcs).to_string(&value).unwrap(),
+        r#"{"avg":0.1,"count":2,"huge":1e+300,"neg":0}"#
+    );
+}
+
+#[test]
+fn insertion_order() {
+    use std::collections::BTreeMap;
+
+    #[derive(serde_derive::Serialize)]
+    struct Event {
+        r#type: &'static str,
+        // Iterates in the same order with or without `preserve_order`.
+        content: BTreeMap<&'static str, &'static str>,
+        age: u8,
+    }
+
+    let event = Event {
+        r#type: "m.room.message",
+        content: vec![("msgtype", "m.text"), ("body", "hi")]
+            .into_iter()
+            .collect(),
+        age: 2,
+    };
+    assert_eq!(
+        Options::new().to_string(&event).unwrap(),
+        r#"{"age":2,"content":{"body":"hi","msgtype":"m.text"},"type":"m.room.message"}"#
+    );
+
+    let insertion = Options::new()
+        .key_order(KeyOrder::Insertion)
+        .spill_threshold(Some(1));
+    assert_eq!(
+        insertion.to_string(&event).unwrap(),
+        r#"{"type":"m.room.message","content":{"body":"hi","msgtype":"m.text"},"age":2}"#
+    );
+    assert!(insertion.to_string(&[1.5]).is_err());
+}
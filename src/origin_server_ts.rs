@@ -0,0 +1,107 @@
+//! Conversions from points in time to the millisecond timestamps of `origin_server_ts`.
+//!
+//! The module doubles as a serde adapter for `SystemTime` fields:
+//!
+//! ```ignore
+//! #[derive(Serialize, Deserialize)]
+//! struct Pdu {
+//!     #[serde(with = "matrix_canonical_json::origin_server_ts")]
+//!     origin_server_ts: SystemTime,
+//! }
+//! ```
+
+use std::{
+    convert::TryFrom,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{de, ser, Deserialize, Deserializer, Serializer};
+
+use crate::{Error, Result, MAX_SAFE_INTEGER, MIN_SAFE_INTEGER};
+
+/// Milliseconds since the unix epoch, failing if they are outside of the canonical JSON integer
+/// range instead of wrapping.
+pub fn system_time_to_millis(time: SystemTime) -> Result<i64> {
+    let millis = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => i128::try_from(since.as_millis()).unwrap_or(i128::MAX),
+        Err(before) => -i128::try_from(before.duration().as_millis()).unwrap_or(i128::MAX),
+    };
+    millis_in_range(millis)
+}
+
+/// The `SystemTime` of a millisecond timestamp.
+pub fn millis_to_system_time(millis: i64) -> SystemTime {
+    let offset = Duration::from_millis(millis.unsigned_abs());
+    if millis < 0 {
+        UNIX_EPOCH - offset
+    } else {
+        UNIX_EPOCH + offset
+    }
+}
+
+/// Milliseconds since the unix epoch of a `chrono` date, failing if they are outside of the
+/// canonical JSON integer range.
+#[cfg(feature = "chrono")]
+pub fn chrono_to_millis<Tz: chrono::TimeZone>(time: &chrono::DateTime<Tz>) -> Result<i64> {
+    millis_in_range(i128::from(time.timestamp_millis()))
+}
+
+/// Milliseconds since the unix epoch of a `time` date, failing if they are outside of the
+/// canonical JSON integer range.
+#[cfg(feature = "time")]
+pub fn time_to_millis(time: time::OffsetDateTime) -> Result<i64> {
+    millis_in_range(time.unix_timestamp_nanos() / 1_000_000)
+}
+
+fn millis_in_range(millis: i128) -> Result<i64> {
+    if millis > i128::from(MAX_SAFE_INTEGER) || millis < i128::from(MIN_SAFE_INTEGER) {
+        return Err(Error::InvalidInput(format!(
+            "timestamp of {} milliseconds is outside of the canonical JSON range",
+            millis
+        )));
+    }
+    Ok(millis as i64)
+}
+
+pub fn serialize<S>(time: &SystemTime, serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let millis = system_time_to_millis(*time).map_err(ser::Error::custom)?;
+    serializer.serialize_i64(millis)
+}
+
+pub fn deserialize<'de, D>(deserializer: D) -> std::result::Result<SystemTime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let millis = i64::deserialize(deserializer)?;
+    millis_in_range(i128::from(millis)).map_err(de::Error::custom)?;
+    Ok(millis_to_system_time(millis))
+}
+
+#[cfg(feature = "ser")]
+#[test]
+fn timestamps() {
+    #[derive(Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize)]
+    struct Pdu {
+        #[serde(with = "crate::origin_server_ts")]
+        origin_server_ts: SystemTime,
+    }
+
+    let pdu = Pdu {
+        origin_server_ts: millis_to_system_time(1_600_000_000_123),
+    };
+    let json = crate::to_canonical_string(&pdu).unwrap();
+    assert_eq!(json, r#"{"origin_server_ts":1600000000123}"#);
+    assert_eq!(serde_json::from_str::<Pdu>(&json).unwrap(), pdu);
+
+    assert_eq!(
+        system_time_to_millis(UNIX_EPOCH - Duration::from_millis(5)).unwrap(),
+        -5
+    );
+    assert!(
+        system_time_to_millis(UNIX_EPOCH + Duration::from_millis(MAX_SAFE_INTEGER as u64 + 1))
+            .is_err()
+    );
+}
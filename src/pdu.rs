@@ -0,0 +1,283 @@
+use std::{convert::TryFrom, time::SystemTime};
+
+use crate::{
+    system_time_to_millis, CanonicalJsonObject, CanonicalJsonValue, Error, Result, Severity,
+    Violation, ViolationKind,
+};
+
+/// The top level fields every PDU needs and whether the value has the right type.
+const REQUIRED_FIELDS: &[(&str, &str, fn(&CanonicalJsonValue) -> bool)] = &[
+    ("type", "a string", is_string),
+    ("sender", "a string", is_string),
+    ("room_id", "a string", is_string),
+    ("origin_server_ts", "a positive integer", is_timestamp),
+    ("content", "an object", is_object),
+];
+
+fn is_string(value: &CanonicalJsonValue) -> bool {
+    value.as_str().is_some()
+}
+
+fn is_timestamp(value: &CanonicalJsonValue) -> bool {
+    matches!(value.as_i64(), Some(ts) if ts >= 0)
+}
+
+fn is_object(value: &CanonicalJsonValue) -> bool {
+    value.as_object().is_some()
+}
+
+/// Check that `pdu` has every field the spec requires of a PDU with the right type, all
+/// problems are returned in `Error::Violations`.
+pub fn check_pdu(pdu: &CanonicalJsonValue) -> Result<()> {
+    let object = match pdu.as_object() {
+        Some(object) => object,
+        None => {
+            return Err(Error::Violations(vec![schema_violation(
+                "",
+                "a PDU must be an object",
+            )]))
+        }
+    };
+
+    let mut violations = vec![];
+    for (field, expected, check) in REQUIRED_FIELDS {
+        match object.get(*field) {
+            Some(value) if check(value) => {}
+            Some(_) => violations.push(schema_violation(
+                field,
+                &format!("`{}` must be {}", field, expected),
+            )),
+            None => violations.push(schema_violation(
+                field,
+                &format!("missing required field `{}`", field),
+            )),
+        }
+    }
+    if matches!(object.get("state_key"), Some(key) if !is_string(key)) {
+        violations.push(schema_violation(
+            "state_key",
+            "`state_key` must be a string",
+        ));
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Violations(violations))
+    }
+}
+
+fn schema_violation(field: &str, msg: &str) -> Violation {
+    Violation {
+        kind: ViolationKind::Schema,
+        severity: Severity::Error,
+        pointer: if field.is_empty() {
+            String::new()
+        } else {
+            format!("/{}", field)
+        },
+        found: msg.to_owned(),
+        suggestion: None,
+    }
+}
+
+/// Builds the fields of an event that make up a PDU, the output passes `check_pdu`.
+#[derive(Clone, Debug)]
+pub struct EventBuilder {
+    event_type: String,
+    sender: String,
+    room_id: String,
+    content: CanonicalJsonObject,
+    state_key: Option<String>,
+    origin_server_ts: Option<i64>,
+}
+
+impl EventBuilder {
+    pub fn new<T, S, R>(event_type: T, sender: S, room_id: R) -> Self
+    where
+        T: Into<String>,
+        S: Into<String>,
+        R: Into<String>,
+    {
+        Self {
+            event_type: event_type.into(),
+            sender: sender.into(),
+            room_id: room_id.into(),
+            content: CanonicalJsonObject::new(),
+            state_key: None,
+            origin_server_ts: None,
+        }
+    }
+
+    /// The content of the event, `to_canonical_object` turns any `Serialize` type into one.
+    pub fn content(mut self, content: CanonicalJsonObject) -> Self {
+        self.content = content;
+        self
+    }
+
+    /// Makes the event a state event.
+    pub fn state_key<K: Into<String>>(mut self, state_key: K) -> Self {
+        self.state_key = Some(state_key.into());
+        self
+    }
+
+    /// The timestamp in milliseconds since the unix epoch, the current time if it is not set.
+    pub fn origin_server_ts(mut self, ts: i64) -> Self {
+        self.origin_server_ts = Some(ts);
+        self
+    }
+
+    pub fn build(self) -> Result<CanonicalJsonObject> {
+        let ts = match self.origin_server_ts {
+            Some(ts) => ts,
+            None => system_time_to_millis(SystemTime::now())?,
+        };
+
+        let mut event = CanonicalJsonObject::new();
+        event.insert("type".into(), self.event_type.into());
+        event.insert("sender".into(), self.sender.into());
+        event.insert("room_id".into(), self.room_id.into());
+        event.insert("origin_server_ts".into(), ts.into());
+        event.insert("content".into(), self.content.into());
+        if let Some(state_key) = self.state_key {
+            event.insert("state_key".into(), state_key.into());
+        }
+
+        let event = CanonicalJsonValue::Object(event);
+        check_pdu(&event)?;
+        match event {
+            CanonicalJsonValue::Object(event) => Ok(event),
+            _ => unreachable!("the event was built as an object"),
+        }
+    }
+}
+
+/// Set `unsigned.age` of `event`, the milliseconds since it was sent, creating `unsigned` if
+/// it has none. `unsigned` is left out of hashes and signatures so this never invalidates them.
+///
+/// Fails with `Error::IntegerOutOfRange` if `age` is outside of the canonical range.
+pub fn insert_age(event: &mut CanonicalJsonObject, age: i64) -> Result<()> {
+    insert_unsigned(event, "age", age)
+}
+
+/// Set `unsigned.age_ts` of `event`, the timestamp `age` is relative to, like `insert_age`.
+pub fn insert_age_ts(event: &mut CanonicalJsonObject, age_ts: i64) -> Result<()> {
+    insert_unsigned(event, "age_ts", age_ts)
+}
+
+/// Remove `unsigned.age` and `unsigned.age_ts` from `event` and `unsigned` too if nothing else
+/// is left in it, everything else in `unsigned` is kept.
+pub fn strip_age(event: &mut CanonicalJsonObject) {
+    if let Some(CanonicalJsonValue::Object(unsigned)) = event.get_mut("unsigned") {
+        unsigned.remove("age");
+        unsigned.remove("age_ts");
+        if unsigned.is_empty() {
+            event.remove("unsigned");
+        }
+    }
+}
+
+fn insert_unsigned(event: &mut CanonicalJsonObject, field: &str, value: i64) -> Result<()> {
+    let value = CanonicalJsonValue::try_from(value)?;
+    let unsigned = event
+        .entry("unsigned".to_owned())
+        .or_insert_with(|| CanonicalJsonObject::new().into());
+    match unsigned {
+        CanonicalJsonValue::Object(unsigned) => {
+            unsigned.insert(field.to_owned(), value);
+            Ok(())
+        }
+        _ => Err(Error::InvalidInput(
+            "`unsigned` of the event is not an object".to_owned(),
+        )),
+    }
+}
+
+#[cfg(feature = "ser")]
+#[test]
+fn builds_events() {
+    let content = crate::to_canonical_object(&serde_json::json!({ "membership": "join" })).unwrap();
+    let event = EventBuilder::new("m.room.member", "@alice:example.org", "!room:example.org")
+        .content(content)
+        .state_key("@alice:example.org")
+        .origin_server_ts(1_600_000_000_000)
+        .build()
+        .unwrap();
+
+    assert_eq!(
+        crate::to_canonical_string(&event).unwrap(),
+        r#"{"content":{"membership":"join"},"origin_server_ts":1600000000000,"room_id":"!room:example.org","sender":"@alice:example.org","state_key":"@alice:example.org","type":"m.room.member"}"#
+    );
+    assert!(EventBuilder::new("m.room.message", "@a:b", "!c:d")
+        .origin_server_ts(-1)
+        .build()
+        .is_err());
+}
+
+#[test]
+fn checks_required_fields() {
+    let pdu = crate::to_canonical_value(&serde_json::json!({
+        "type": "m.room.message",
+        "sender": "@alice:example.org",
+        "room_id": "!room:example.org",
+        "origin_server_ts": 1_600_000_000_000_u64,
+        "content": { "body": "hi" }
+    }))
+    .unwrap();
+    assert!(check_pdu(&pdu).is_ok());
+
+    let bad = crate::to_canonical_value(&serde_json::json!({
+        "type": "m.room.member",
+        "sender": "@alice:example.org",
+        "origin_server_ts": -1,
+        "content": "nope",
+        "state_key": 1
+    }))
+    .unwrap();
+    match check_pdu(&bad) {
+        Err(Error::Violations(violations)) => assert_eq!(
+            violations
+                .iter()
+                .map(|v| v.pointer.as_str())
+                .collect::<Vec<_>>(),
+            vec!["/room_id", "/origin_server_ts", "/content", "/state_key"]
+        ),
+        other => panic!("expected violations found {:?}", other),
+    }
+}
+
+#[cfg(feature = "ser")]
+#[test]
+fn age_fields() {
+    let mut event = EventBuilder::new("m.room.message", "@alice:example.org", "!room:example.org")
+        .origin_server_ts(1_600_000_000_000)
+        .build()
+        .unwrap();
+    let sent = event.clone();
+
+    insert_age(&mut event, 45).unwrap();
+    insert_age_ts(&mut event, 1_600_000_000_045).unwrap();
+    assert_eq!(
+        crate::to_canonical_string(&event["unsigned"]).unwrap(),
+        r#"{"age":45,"age_ts":1600000000045}"#
+    );
+    strip_age(&mut event);
+    assert_eq!(event, sent);
+    assert!(matches!(
+        insert_age(&mut event, i64::MAX),
+        Err(Error::IntegerOutOfRange { .. })
+    ));
+    assert_eq!(event, sent);
+
+    let mut txn_id = CanonicalJsonObject::new();
+    txn_id.insert("transaction_id".to_owned(), "m1".into());
+    event.insert("unsigned".to_owned(), txn_id.clone().into());
+    insert_age(&mut event, 7).unwrap();
+    strip_age(&mut event);
+    assert_eq!(event["unsigned"], CanonicalJsonValue::Object(txn_id));
+
+    event.insert("unsigned".to_owned(), 1.into());
+    assert!(insert_age(&mut event, 7).is_err());
+    strip_age(&mut event);
+    assert_eq!(event["unsigned"], CanonicalJsonValue::Integer(1));
+}
@@ -0,0 +1,169 @@
+use crate::{
+    check_declared_hashes, check_pdu, from_slice, CanonicalJsonObject, CanonicalJsonValue, Error,
+    Event, HashCheck, Parser, Result,
+};
+
+/// The outcome of checking one PDU of a `/backfill` or `/state` response.
+#[derive(Debug)]
+pub struct CheckedPdu {
+    /// The JSON pointer of the PDU in the response, like `/pdus/3`.
+    pub pointer: String,
+    /// The PDU and whether it has to be redacted, or why it can't be used at all.
+    pub result: Result<(CanonicalJsonObject, HashCheck)>,
+}
+
+/// Check every PDU of a `/backfill` or `/state` response on its own, so the good ones can be
+/// accepted while others are dropped. The PDUs of `pdus` and of `auth_chain`, if there is one,
+/// are checked in the order they appear.
+///
+/// A PDU is used if it is canonical JSON, has the fields of a PDU, has the `event_id` field
+/// exactly when `room_version` is 1 or 2, the versions that send it, and its content hash is
+/// checked with `check_declared_hashes`. Signatures are not checked.
+///
+/// Fails as a whole only if `body` is not a JSON object with a `pdus` array.
+pub fn check_pdu_response(body: &[u8], room_version: &str) -> Result<Vec<CheckedPdu>> {
+    let mut parser = Parser::new(body);
+    if parser.next_event()? != Some(Event::ObjectStart) {
+        return Err(Error::InvalidInput(
+            "the response is not an object".to_owned(),
+        ));
+    }
+
+    let mut checked = vec![];
+    let mut has_pdus = false;
+    while let Some(Event::Key(key)) = parser.next_event()? {
+        let list = match &*key.unescape() {
+            "pdus" => "pdus",
+            "auth_chain" => "auth_chain",
+            _ => "",
+        };
+        match parser.next_event()? {
+            Some(Event::ArrayStart) if !list.is_empty() => {
+                has_pdus |= list == "pdus";
+                check_list(&mut parser, body, list, room_version, &mut checked)?;
+            }
+            Some(Event::ObjectStart) | Some(Event::ArrayStart) => skip_to_depth(&mut parser, 1)?,
+            _ => {}
+        }
+    }
+    parser.end()?;
+
+    if !has_pdus {
+        return Err(Error::InvalidInput(
+            "the response has no `pdus` array".to_owned(),
+        ));
+    }
+    Ok(checked)
+}
+
+/// Check the elements of the array `list` the parser just entered.
+fn check_list(
+    parser: &mut Parser<'_>,
+    body: &[u8],
+    list: &str,
+    room_version: &str,
+    checked: &mut Vec<CheckedPdu>,
+) -> Result<()> {
+    for idx in 0.. {
+        let start = parser.offset();
+        match parser.next_event()? {
+            Some(Event::ArrayEnd) => break,
+            Some(Event::ObjectStart) | Some(Event::ArrayStart) => skip_to_depth(parser, 2)?,
+            _ => {}
+        }
+        // The element after the comma that separates it from the one before.
+        let raw = &body[start..parser.offset()];
+        let raw = match raw
+            .iter()
+            .position(|b| !matches!(b, b',' | b' ' | b'\t' | b'\n' | b'\r'))
+        {
+            Some(pos) => &raw[pos..],
+            None => raw,
+        };
+
+        checked.push(CheckedPdu {
+            pointer: format!("/{}/{}", list, idx),
+            result: check_one(raw, room_version),
+        });
+    }
+    Ok(())
+}
+
+fn check_one(raw: &[u8], room_version: &str) -> Result<(CanonicalJsonObject, HashCheck)> {
+    let pdu = from_slice(raw)?;
+    check_pdu(&pdu)?;
+    let pdu = match pdu {
+        CanonicalJsonValue::Object(pdu) => pdu,
+        _ => unreachable!("`check_pdu` only accepts objects"),
+    };
+
+    let sends_event_id = matches!(room_version, "1" | "2");
+    let has_event_id = if sends_event_id {
+        matches!(pdu.get("event_id"), Some(CanonicalJsonValue::String(_)))
+    } else {
+        !pdu.contains_key("event_id")
+    };
+    if !has_event_id {
+        return Err(Error::InvalidInput(format!(
+            "PDUs of room version {} {} a string `event_id`",
+            room_version,
+            if sends_event_id { "need" } else { "can't have" }
+        )));
+    }
+
+    let hashes = check_declared_hashes(&pdu)?;
+    Ok((pdu, hashes))
+}
+
+/// Read events until the container that was just opened is closed again.
+fn skip_to_depth(parser: &mut Parser<'_>, depth: usize) -> Result<()> {
+    while parser.depth() > depth {
+        parser.next_event()?;
+    }
+    Ok(())
+}
+
+#[test]
+fn pdu_responses() {
+    use crate::{content_hash, to_canonical_string};
+
+    let mut good = crate::to_canonical_object(&serde_json::json!({
+        "content": { "body": "hi" },
+        "origin_server_ts": 1_600_000_000_000_u64,
+        "room_id": "!r:example.org",
+        "sender": "@alice:example.org",
+        "type": "m.room.message"
+    }))
+    .unwrap();
+    let unhashed = to_canonical_string(&good).unwrap();
+    let mut hashes = CanonicalJsonObject::new();
+    hashes.insert("sha256".to_owned(), content_hash(&good).unwrap().into());
+    good.insert("hashes".to_owned(), hashes.into());
+    let good_json = to_canonical_string(&good).unwrap();
+
+    let body = format!(
+        r#"{{"origin": "example.org", "pdus": [{}, {} ,[1.5], {{"event_id": "$a", "x": {{}}}}], "auth_chain": [{}], "extra": {{"pdus": 1}}}}"#,
+        good_json, unhashed, good_json
+    );
+    let checked = check_pdu_response(body.as_bytes(), "6").unwrap();
+    assert_eq!(
+        checked
+            .iter()
+            .map(|c| c.pointer.as_str())
+            .collect::<Vec<_>>(),
+        vec!["/pdus/0", "/pdus/1", "/pdus/2", "/pdus/3", "/auth_chain/0"]
+    );
+    assert!(matches!(&checked[0].result, Ok((pdu, HashCheck::Valid)) if *pdu == good));
+    assert!(matches!(checked[1].result, Ok((_, HashCheck::Redact))));
+    assert!(checked[2].result.is_err());
+    assert!(matches!(checked[3].result, Err(Error::Violations(_))));
+    assert!(matches!(checked[4].result, Ok((_, HashCheck::Valid))));
+
+    // Version 1 rooms send the event ID with every PDU.
+    let checked = check_pdu_response(body.as_bytes(), "1").unwrap();
+    assert!(matches!(checked[0].result, Err(Error::InvalidInput(_))));
+
+    assert!(check_pdu_response(br#"{"auth_chain": []}"#, "6").is_err());
+    assert!(check_pdu_response(b"[]", "6").is_err());
+    assert!(check_pdu_response(br#"{"pdus": [}"#, "6").is_err());
+}
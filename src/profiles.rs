@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{Error, Options, Result};
+
+/// Named sets of `Options` picked per call, so the rules of an MSC can be tried out under a
+/// name like `"org.example.msc1234"` next to the canonical ones without forking the serializer.
+///
+/// A profile is a whole `Options`: key order, how floats and large integers are written, the
+/// `on_string` hook for escaping rules and the size limit. Share one registry between threads
+/// with an `Arc`, or a `RwLock` to keep registering.
+#[derive(Clone, Debug, Default)]
+pub struct ProfileRegistry {
+    profiles: HashMap<String, Options>,
+}
+
+impl ProfileRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `options` as `name`, returning the options registered as it before.
+    pub fn register<N>(&mut self, name: N, options: Options) -> Option<Options>
+    where
+        N: Into<String>,
+    {
+        self.profiles.insert(name.into(), options)
+    }
+
+    pub fn unregister(&mut self, name: &str) -> Option<Options> {
+        self.profiles.remove(name)
+    }
+
+    /// The options registered as `name`, failing with `Error::InvalidInput` for unknown names.
+    pub fn get(&self, name: &str) -> Result<&Options> {
+        self.profiles
+            .get(name)
+            .ok_or_else(|| Error::InvalidInput(format!("no profile named {:?}", name)))
+    }
+
+    /// The names of every registered profile, in no particular order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    /// Serialize `value` with the profile `name`.
+    pub fn to_vec<T>(&self, name: &str, value: &T) -> Result<Vec<u8>>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.get(name)?.to_vec(value)
+    }
+
+    /// Serialize `value` with the profile `name`.
+    pub fn to_string<T>(&self, name: &str, value: &T) -> Result<String>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.get(name)?.to_string(value)
+    }
+}
+
+#[test]
+fn named_profiles() {
+    use crate::{KeyOrder, Profile};
+
+    let value = serde_json::json!({ "b": 1.5, "a": "x" });
+
+    let mut registry = ProfileRegistry::new();
+    assert!(registry
+        .register("jcs", Options::new().profile(Profile::Jcs))
+        .is_none());
+    registry.register(
+        "org.example.unsorted",
+        Options::new()
+            .profile(Profile::Jcs)
+            .key_order(KeyOrder::Insertion)
+            .size_limit(Some(10)),
+    );
+    registry.register("matrix", Options::new());
+
+    assert_eq!(
+        registry.to_string("jcs", &value).unwrap(),
+        r#"{"a":"x","b":1.5}"#
+    );
+    assert!(matches!(
+        registry.to_string("org.example.unsorted", &value),
+        Err(Error::SizeLimit)
+    ));
+    assert!(matches!(
+        registry.to_vec("matrix", &value),
+        Err(Error::InvalidInput(_))
+    ));
+    assert!(matches!(
+        registry.to_vec("missing", &value),
+        Err(Error::InvalidInput(msg)) if msg == r#"no profile named "missing""#
+    ));
+
+    let mut names = registry.names().collect::<Vec<_>>();
+    names.sort_unstable();
+    assert_eq!(names, vec!["jcs", "matrix", "org.example.unsorted"]);
+
+    assert!(registry.unregister("jcs").is_some());
+    assert!(registry.get("jcs").is_err());
+}
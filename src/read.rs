@@ -0,0 +1,1034 @@
+use std::{borrow::Cow, mem, str, sync::Arc};
+
+use crate::{Error, KeyInterner, Result};
+
+/// The deepest nesting of arrays and objects the parser accepts.
+const RECURSION_LIMIT: usize = 128;
+
+/// A token of a JSON document, produced by `Parser`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event<'a> {
+    ObjectStart,
+    ObjectEnd,
+    ArrayStart,
+    ArrayEnd,
+    Key(Str<'a>),
+    String(Str<'a>),
+    /// The number exactly as it appears in the input.
+    Number(&'a str),
+    Bool(bool),
+    Null,
+}
+
+impl<'a> Event<'a> {
+    /// The value of a `Number` that is an integer fitting in an `i64`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Event::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// A JSON string as it appears in the input, without the surrounding quotes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Str<'a> {
+    raw: &'a str,
+    escaped: bool,
+    canonical: bool,
+}
+
+impl<'a> Str<'a> {
+    /// The string exactly as it appears in the input, escape sequences included.
+    pub fn raw(&self) -> &'a str {
+        self.raw
+    }
+
+    /// Whether every escape sequence is the one canonical JSON would use.
+    pub fn is_canonical(&self) -> bool {
+        self.canonical
+    }
+
+    /// The string with all escape sequences replaced, the parser already made sure they are
+    /// valid.
+    pub fn unescape(&self) -> Cow<'a, str> {
+        if !self.escaped {
+            return Cow::Borrowed(self.raw);
+        }
+
+        let bytes = self.raw.as_bytes();
+        let mut out = String::with_capacity(bytes.len());
+        let mut start = 0;
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] != b'\\' {
+                i += 1;
+                continue;
+            }
+
+            out.push_str(&self.raw[start..i]);
+            let (ch, len) = match bytes[i + 1] {
+                b'"' => ('"', 2),
+                b'\\' => ('\\', 2),
+                b'/' => ('/', 2),
+                b'b' => ('\x08', 2),
+                b'f' => ('\x0C', 2),
+                b'n' => ('\n', 2),
+                b'r' => ('\r', 2),
+                b't' => ('\t', 2),
+                _ => {
+                    let cp = hex4(&bytes[i + 2..i + 6]);
+                    if (0xD800..0xDC00).contains(&cp) {
+                        let low = hex4(&bytes[i + 8..i + 12]);
+                        let cp = 0x10000 + ((cp - 0xD800) << 10) + (low - 0xDC00);
+                        (char::from_u32(cp).unwrap_or('\u{FFFD}'), 12)
+                    } else {
+                        (char::from_u32(cp).unwrap_or('\u{FFFD}'), 6)
+                    }
+                }
+            };
+            out.push(ch);
+            i += len;
+            start = i;
+        }
+        out.push_str(&self.raw[start..]);
+
+        Cow::Owned(out)
+    }
+}
+
+fn hex_digit(byte: u8) -> Option<u32> {
+    match byte {
+        b'0'..=b'9' => Some(u32::from(byte - b'0')),
+        b'a'..=b'f' => Some(u32::from(byte - b'a' + 10)),
+        b'A'..=b'F' => Some(u32::from(byte - b'A' + 10)),
+        _ => None,
+    }
+}
+
+/// Decode four hex digits that were already validated.
+fn hex4(digits: &[u8]) -> u32 {
+    digits
+        .iter()
+        .fold(0, |n, &d| (n << 4) | hex_digit(d).unwrap_or(0))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Container {
+    Object,
+    Array,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Expect {
+    /// A value, at the top level, after a `:` or after a `,` in an array.
+    Value,
+    /// The first key of an object or its `}`.
+    FirstKey,
+    /// A key after a `,`.
+    Key,
+    /// The `:` after a key.
+    Colon,
+    /// The first value of an array or its `]`.
+    FirstValue,
+    /// A `,` or the end of the innermost container.
+    CommaOrEnd,
+    /// The top level value is complete.
+    Done,
+}
+
+/// How far an unfinished string at the end of the input was checked, so a `PushParser` can
+/// continue there once more of it arrived instead of going over it again.
+#[derive(Clone, Copy, Debug)]
+struct PartialStr {
+    /// The offset of the opening quote.
+    quote: usize,
+    /// The offset of the first byte not checked yet, never inside an escape.
+    pos: usize,
+    escaped: bool,
+    canonical: bool,
+}
+
+/// A pull parser yielding the `Event`s of a single JSON document without building a tree.
+///
+/// The parser checks the JSON grammar but not the canonical JSON rules, it does remember whether
+/// it skipped any whitespace and each `Str` whether its escapes are canonical.
+///
+/// As an `Iterator` the parser also checks that nothing follows the document and stops after
+/// the first error.
+pub struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+    stack: Vec<Container>,
+    expect: Expect,
+    whitespace: bool,
+    finished: bool,
+    max_string_len: Option<usize>,
+    leading_zeros: bool,
+    /// Set when the last error was the input ending, which more input could have avoided.
+    incomplete: bool,
+    /// The string the input ended in, or where to continue the string at `quote`.
+    partial: Option<PartialStr>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(input: &'a [u8]) -> Self {
+        Self {
+            input,
+            pos: 0,
+            stack: vec![],
+            expect: Expect::Value,
+            whitespace: false,
+            finished: false,
+            max_string_len: None,
+            leading_zeros: false,
+            incomplete: false,
+            partial: None,
+        }
+    }
+
+    /// Fail as soon as a string or key is longer than `limit` bytes as it appears in the input,
+    /// escapes included.
+    pub fn max_string_len(mut self, limit: Option<usize>) -> Self {
+        self.max_string_len = limit;
+        self
+    }
+
+    /// Accept numbers with leading zeros like `007`, which JSON does not allow, for
+    /// `NumberPolicy::Normalize`.
+    pub(crate) fn allow_leading_zeros(mut self, allow: bool) -> Self {
+        self.leading_zeros = allow;
+        self
+    }
+
+    /// Whether any whitespace between tokens was skipped so far.
+    pub fn saw_whitespace(&self) -> bool {
+        self.whitespace
+    }
+
+    /// The number of bytes of the input consumed so far.
+    pub fn offset(&self) -> usize {
+        self.pos
+    }
+
+    /// How many arrays and objects are open, a key of the top level object is at depth 1.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Check that nothing but whitespace follows the document.
+    pub fn end(&mut self) -> Result<()> {
+        self.skip_whitespace();
+        if self.pos < self.input.len() {
+            Err(self.error("trailing characters"))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The next event of the document or `None` once the top level value is complete.
+    pub fn next_event(&mut self) -> Result<Option<Event<'a>>> {
+        loop {
+            self.skip_whitespace();
+            match self.expect {
+                Expect::Done => return Ok(None),
+                Expect::Value => return self.parse_value().map(Some),
+                Expect::FirstValue => {
+                    if self.peek() == Some(b']') {
+                        self.pos += 1;
+                        return Ok(Some(self.close()));
+                    }
+                    return self.parse_value().map(Some);
+                }
+                Expect::FirstKey => {
+                    if self.peek() == Some(b'}') {
+                        self.pos += 1;
+                        return Ok(Some(self.close()));
+                    }
+                    return self.parse_key().map(Some);
+                }
+                Expect::Key => return self.parse_key().map(Some),
+                Expect::Colon => match self.peek() {
+                    Some(b':') => {
+                        self.pos += 1;
+                        self.expect = Expect::Value;
+                    }
+                    Some(_) => return Err(self.error("expected `:`")),
+                    None => return Err(self.eof("EOF while parsing an object")),
+                },
+                Expect::CommaOrEnd => {
+                    let container = *self.stack.last().expect("a container is open");
+                    match (self.peek(), container) {
+                        (Some(b','), Container::Object) => {
+                            self.pos += 1;
+                            self.expect = Expect::Key;
+                        }
+                        (Some(b','), Container::Array) => {
+                            self.pos += 1;
+                            self.expect = Expect::Value;
+                        }
+                        (Some(b'}'), Container::Object) | (Some(b']'), Container::Array) => {
+                            self.pos += 1;
+                            return Ok(Some(self.close()));
+                        }
+                        (Some(_), Container::Object) => {
+                            return Err(self.error("expected `,` or `}`"))
+                        }
+                        (Some(_), Container::Array) => {
+                            return Err(self.error("expected `,` or `]`"))
+                        }
+                        (None, _) => return Err(self.eof("unexpected end of input")),
+                    }
+                }
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn error(&self, msg: &str) -> Error {
+        self.error_at(self.pos, msg)
+    }
+
+    fn error_at(&self, offset: usize, msg: &str) -> Error {
+        Error::Syntax {
+            msg: msg.to_owned(),
+            offset,
+        }
+    }
+
+    /// An error caused by the input ending.
+    fn eof(&mut self, msg: &str) -> Error {
+        self.incomplete = true;
+        self.error(msg)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\n') | Some(b'\t') | Some(b'\r') = self.peek() {
+            self.pos += 1;
+            self.whitespace = true;
+        }
+    }
+
+    fn value_done(&mut self) {
+        self.expect = if self.stack.is_empty() {
+            Expect::Done
+        } else {
+            Expect::CommaOrEnd
+        };
+    }
+
+    fn open(&mut self, container: Container) -> Result<Event<'a>> {
+        if self.stack.len() >= RECURSION_LIMIT {
+            return Err(self.error("recursion limit exceeded"));
+        }
+        self.pos += 1;
+        self.stack.push(container);
+        Ok(match container {
+            Container::Object => {
+                self.expect = Expect::FirstKey;
+                Event::ObjectStart
+            }
+            Container::Array => {
+                self.expect = Expect::FirstValue;
+                Event::ArrayStart
+            }
+        })
+    }
+
+    fn close(&mut self) -> Event<'a> {
+        let container = self.stack.pop();
+        self.value_done();
+        match container {
+            Some(Container::Object) => Event::ObjectEnd,
+            _ => Event::ArrayEnd,
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Event<'a>> {
+        match self.peek() {
+            Some(b'{') => self.open(Container::Object),
+            Some(b'[') => self.open(Container::Array),
+            Some(b'"') => {
+                let s = self.parse_str()?;
+                self.value_done();
+                Ok(Event::String(s))
+            }
+            Some(b'-') | Some(b'0'..=b'9') => self.parse_number(),
+            Some(b't') => self.parse_ident(b"true", Event::Bool(true)),
+            Some(b'f') => self.parse_ident(b"false", Event::Bool(false)),
+            Some(b'n') => self.parse_ident(b"null", Event::Null),
+            Some(_) => Err(self.error("expected value")),
+            None => Err(self.eof("EOF while parsing a value")),
+        }
+    }
+
+    fn parse_key(&mut self) -> Result<Event<'a>> {
+        match self.peek() {
+            Some(b'"') => {
+                let s = self.parse_str()?;
+                self.expect = Expect::Colon;
+                Ok(Event::Key(s))
+            }
+            Some(_) => Err(self.error("key must be a string")),
+            None => Err(self.eof("EOF while parsing an object")),
+        }
+    }
+
+    fn parse_ident(&mut self, ident: &[u8], event: Event<'a>) -> Result<Event<'a>> {
+        if self.input[self.pos..].starts_with(ident) {
+            self.pos += ident.len();
+            self.value_done();
+            Ok(event)
+        } else if ident.starts_with(&self.input[self.pos..]) {
+            Err(self.eof("expected value"))
+        } else {
+            Err(self.error("expected value"))
+        }
+    }
+
+    fn eat_digits(&mut self) -> Result<()> {
+        match self.peek() {
+            Some(b'0'..=b'9') => {}
+            Some(_) => return Err(self.error("invalid number")),
+            None => return Err(self.eof("invalid number")),
+        }
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        Ok(())
+    }
+
+    fn parse_number(&mut self) -> Result<Event<'a>> {
+        let start = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        match self.peek() {
+            Some(b'0') => {
+                self.pos += 1;
+                if let Some(b'0'..=b'9') = self.peek() {
+                    let offset = self.pos;
+                    self.eat_digits()?;
+                    if !self.leading_zeros {
+                        let number = String::from_utf8_lossy(&self.input[start..self.pos]);
+                        let msg = format!("number `{}` has leading zeros", number);
+                        return Err(self.error_at(offset, &msg));
+                    }
+                }
+            }
+            _ => self.eat_digits()?,
+        }
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            self.eat_digits()?;
+        }
+        if let Some(b'e') | Some(b'E') = self.peek() {
+            self.pos += 1;
+            if let Some(b'+') | Some(b'-') = self.peek() {
+                self.pos += 1;
+            }
+            self.eat_digits()?;
+        }
+        self.value_done();
+
+        let input = self.input;
+        // A number is made up of ASCII only.
+        let raw = unsafe { str::from_utf8_unchecked(&input[start..self.pos]) };
+        Ok(Event::Number(raw))
+    }
+
+    fn parse_hex4(&mut self) -> Result<(u32, bool)> {
+        let mut cp = 0;
+        let mut lowercase = true;
+        for _ in 0..4 {
+            let byte = match self.peek() {
+                Some(byte) => byte,
+                None => return Err(self.eof("EOF while parsing a string")),
+            };
+            match hex_digit(byte) {
+                Some(digit) => cp = (cp << 4) | digit,
+                None => return Err(self.error("invalid escape")),
+            }
+            lowercase &= !byte.is_ascii_uppercase();
+            self.pos += 1;
+        }
+        Ok((cp, lowercase))
+    }
+
+    /// Parse a string starting at its opening quote.
+    fn parse_str(&mut self) -> Result<Str<'a>> {
+        let quote = self.pos;
+        let mut scan = match self.partial.take() {
+            Some(partial) if partial.quote == quote => partial,
+            _ => PartialStr {
+                quote,
+                pos: quote + 1,
+                escaped: false,
+                canonical: true,
+            },
+        };
+        self.pos = scan.pos;
+        if let Err(err) = self.scan_str(&mut scan) {
+            if self.incomplete {
+                self.partial = Some(scan);
+            }
+            return Err(err);
+        }
+
+        let start = quote + 1;
+        let input = self.input;
+        let raw = str::from_utf8(&input[start..self.pos])
+            .map_err(|e| self.error_at(start + e.valid_up_to(), "invalid UTF-8 in string"))?;
+        // The closing quote.
+        self.pos += 1;
+
+        Ok(Str {
+            raw,
+            escaped: scan.escaped,
+            canonical: scan.canonical,
+        })
+    }
+
+    /// Check the string `scan` is in up to its closing quote, keeping `scan.pos` at the start
+    /// of the last character or escape so it can be picked up there if the input ends first.
+    fn scan_str(&mut self, scan: &mut PartialStr) -> Result<()> {
+        let start = scan.quote + 1;
+        loop {
+            scan.pos = self.pos;
+            if matches!(self.max_string_len, Some(max) if self.pos - start > max) {
+                return Err(self.error_at(start - 1, "string is longer than the maximum length"));
+            }
+            match self.peek() {
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    let escape_start = self.pos;
+                    scan.escaped = true;
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(b'"') | Some(b'\\') | Some(b'b') | Some(b'f') | Some(b'n')
+                        | Some(b'r') | Some(b't') => self.pos += 1,
+                        Some(b'/') => {
+                            scan.canonical = false;
+                            self.pos += 1;
+                        }
+                        Some(b'u') => {
+                            self.pos += 1;
+                            let (cp, lowercase) = self.parse_hex4()?;
+                            if (0xD800..0xDC00).contains(&cp) {
+                                if b"\\u".starts_with(&self.input[self.pos..]) {
+                                    return Err(self.eof("EOF while parsing a string"));
+                                }
+                                if !self.input[self.pos..].starts_with(b"\\u") {
+                                    return Err(
+                                        self.error_at(escape_start, "lone leading surrogate")
+                                    );
+                                }
+                                self.pos += 2;
+                                let (low, _) = self.parse_hex4()?;
+                                if !(0xDC00..0xE000).contains(&low) {
+                                    return Err(
+                                        self.error_at(escape_start, "lone leading surrogate")
+                                    );
+                                }
+                                // Only control characters are escaped this way.
+                                scan.canonical = false;
+                            } else if (0xDC00..0xE000).contains(&cp) {
+                                return Err(self.error_at(escape_start, "lone trailing surrogate"));
+                            } else if cp >= 0x20
+                                || matches!(cp, 0x08 | 0x09 | 0x0A | 0x0C | 0x0D)
+                                || !lowercase
+                            {
+                                scan.canonical = false;
+                            }
+                        }
+                        Some(_) => return Err(self.error("invalid escape")),
+                        None => return Err(self.eof("EOF while parsing a string")),
+                    }
+                }
+                Some(byte) if byte < 0x20 => {
+                    return Err(self.error("control character found while parsing a string"))
+                }
+                Some(_) => self.pos += 1,
+                None => return Err(self.eof("EOF while parsing a string")),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for Parser<'a> {
+    type Item = Result<Event<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        match self.next_event() {
+            Ok(Some(event)) => Some(Ok(event)),
+            Ok(None) => {
+                self.finished = true;
+                self.end().err().map(Err)
+            }
+            Err(err) => {
+                self.finished = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// An `Event` that owns its strings, yielded by `PushParser`. Keys and strings are unescaped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OwnedEvent {
+    ObjectStart,
+    ObjectEnd,
+    ArrayStart,
+    ArrayEnd,
+    /// Shared with the other occurrences of the key if the `PushParser` interns keys.
+    Key(Arc<str>),
+    String(String),
+    /// The number exactly as it appears in the input.
+    Number(String),
+    Bool(bool),
+    Null,
+}
+
+impl From<Event<'_>> for OwnedEvent {
+    fn from(event: Event<'_>) -> Self {
+        match event {
+            Event::ObjectStart => OwnedEvent::ObjectStart,
+            Event::ObjectEnd => OwnedEvent::ObjectEnd,
+            Event::ArrayStart => OwnedEvent::ArrayStart,
+            Event::ArrayEnd => OwnedEvent::ArrayEnd,
+            Event::Key(key) => OwnedEvent::Key(Arc::from(&*key.unescape())),
+            Event::String(s) => OwnedEvent::String(s.unescape().into_owned()),
+            Event::Number(n) => OwnedEvent::Number(n.to_owned()),
+            Event::Bool(b) => OwnedEvent::Bool(b),
+            Event::Null => OwnedEvent::Null,
+        }
+    }
+}
+
+/// A parser the input is pushed into in chunks as it arrives, for checking a body while it is
+/// streamed through instead of after it was put back together. A token split across chunks is
+/// kept until the rest of it is fed. A string is checked where the previous chunk left off,
+/// other tokens are short enough to be scanned again from their start.
+///
+/// Error offsets count from the start of the first chunk. The parser should not be used after
+/// it returned an error.
+#[derive(Debug)]
+pub struct PushParser {
+    /// Input that was fed but is not part of a yielded event yet.
+    buf: Vec<u8>,
+    /// The bytes consumed before `buf`.
+    offset: usize,
+    stack: Vec<Container>,
+    expect: Expect,
+    max_string_len: Option<usize>,
+    /// How far the string `buf` ends in was checked.
+    partial: Option<PartialStr>,
+    keys: Option<KeyInterner>,
+}
+
+impl Default for PushParser {
+    fn default() -> Self {
+        Self {
+            buf: vec![],
+            offset: 0,
+            stack: vec![],
+            expect: Expect::Value,
+            max_string_len: None,
+            partial: None,
+            keys: None,
+        }
+    }
+}
+
+impl PushParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fail as soon as a string or key is longer than `limit` bytes, see
+    /// `Parser::max_string_len`.
+    pub fn max_string_len(mut self, limit: Option<usize>) -> Self {
+        self.max_string_len = limit;
+        self
+    }
+
+    /// Look up every key in `interner` before yielding it, so a key repeated across a bulk import
+    /// is only allocated once. Pass the interner from `into_interner` on to the parser of the
+    /// next document to share keys between documents.
+    pub fn intern_keys(mut self, interner: KeyInterner) -> Self {
+        self.keys = Some(interner);
+        self
+    }
+
+    /// The interner given to `intern_keys`, with every key seen so far.
+    pub fn into_interner(self) -> Option<KeyInterner> {
+        self.keys
+    }
+
+    /// Parse `chunk`, the next part of the input, returning the events completed by it.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Vec<OwnedEvent>> {
+        self.buf.extend_from_slice(chunk);
+        self.parse(false)
+    }
+
+    /// Signal the end of the input, returning the events that were still waiting for more of
+    /// it. Fails if the document is not complete or something other than whitespace follows it.
+    pub fn finish(&mut self) -> Result<Vec<OwnedEvent>> {
+        self.parse(true)
+    }
+
+    fn parse(&mut self, last: bool) -> Result<Vec<OwnedEvent>> {
+        let mut parser = Parser::new(&self.buf).max_string_len(self.max_string_len);
+        parser.stack = mem::take(&mut self.stack);
+        parser.expect = self.expect;
+        parser.partial = self.partial.take();
+
+        let mut events = vec![];
+        let mut consumed = 0;
+        let res = loop {
+            // A failed `next_event` may have moved past a `:` or `,`, the stack is only changed
+            // by events that were yielded.
+            let expect = parser.expect;
+            match parser.next_event() {
+                // More digits may follow in the next chunk.
+                Ok(Some(Event::Number(_))) if !last && parser.pos == parser.input.len() => {
+                    parser.expect = expect;
+                    break Ok(());
+                }
+                Ok(Some(event)) => {
+                    events.push(match (event, &mut self.keys) {
+                        (Event::Key(key), Some(keys)) => OwnedEvent::Key(keys.intern_str(&key)),
+                        (event, _) => event.into(),
+                    });
+                    consumed = parser.pos;
+                }
+                Ok(None) => {
+                    let res = parser.end();
+                    consumed = parser.pos;
+                    break res;
+                }
+                Err(_) if !last && parser.incomplete => {
+                    parser.expect = expect;
+                    // Offsets in what is left of the buffer once the consumed bytes are gone.
+                    self.partial = parser.partial.map(|partial| PartialStr {
+                        quote: partial.quote - consumed,
+                        pos: partial.pos - consumed,
+                        ..partial
+                    });
+                    break Ok(());
+                }
+                Err(err) => break Err(err),
+            }
+        };
+        self.stack = parser.stack;
+        self.expect = parser.expect;
+
+        let offset = self.offset;
+        self.buf.drain(..consumed);
+        self.offset += consumed;
+        match res {
+            Ok(()) => Ok(events),
+            Err(Error::Syntax { msg, offset: at }) => Err(Error::Syntax {
+                msg,
+                offset: offset + at,
+            }),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Replace invalid UTF-8 and escapes of lone surrogates in `input` by U+FFFD, calling `report`
+/// with the offset in `input` of every replacement. Everything else is left for the parser to
+/// check, `input` is borrowed when nothing had to be replaced.
+pub(crate) fn repair_utf8<'a>(input: &'a [u8], report: &dyn Fn(usize)) -> Cow<'a, [u8]> {
+    // The start, end and replacement of every range that has to be replaced, in order.
+    let mut fixes: Vec<(usize, usize, &[u8])> = vec![];
+    let mut start = 0;
+    loop {
+        let (valid, invalid) = match str::from_utf8(&input[start..]) {
+            Ok(_) => (input.len(), None),
+            Err(err) => {
+                let valid = start + err.valid_up_to();
+                let len = err.error_len().unwrap_or(input.len() - valid);
+                (valid, Some(valid + len))
+            }
+        };
+        // Escapes are ASCII, a valid one never spans invalid UTF-8.
+        lone_surrogates(&input[..valid], start, &mut fixes);
+        match invalid {
+            Some(end) => {
+                fixes.push((valid, end, "\u{FFFD}".as_bytes()));
+                start = end;
+            }
+            None => break,
+        }
+    }
+
+    if fixes.is_empty() {
+        return Cow::Borrowed(input);
+    }
+    let mut out = Vec::with_capacity(input.len() + 2 * fixes.len());
+    let mut copied = 0;
+    for (start, end, replacement) in fixes {
+        report(start);
+        out.extend_from_slice(&input[copied..start]);
+        out.extend_from_slice(replacement);
+        copied = end;
+    }
+    out.extend_from_slice(&input[copied..]);
+    Cow::Owned(out)
+}
+
+/// Add the `\uXXXX` escapes of lone surrogates in `input` from `start` on to `fixes`.
+fn lone_surrogates(input: &[u8], start: usize, fixes: &mut Vec<(usize, usize, &[u8])>) {
+    let mut i = start;
+    while i < input.len() {
+        if input[i] != b'\\' {
+            i += 1;
+            continue;
+        }
+        match escaped_code_unit(input, i) {
+            Some(high) if (0xD800..0xDC00).contains(&high) => match escaped_code_unit(input, i + 6)
+            {
+                Some(low) if (0xDC00..0xE000).contains(&low) => i += 12,
+                _ => {
+                    fixes.push((i, i + 6, b"\\ufffd"));
+                    i += 6;
+                }
+            },
+            Some(low) if (0xDC00..0xE000).contains(&low) => {
+                fixes.push((i, i + 6, b"\\ufffd"));
+                i += 6;
+            }
+            // Any other escape, the character after the backslash is never the start of one.
+            _ => i += 2,
+        }
+    }
+}
+
+/// The code unit of the `\uXXXX` escape at `i`, if there is one.
+fn escaped_code_unit(input: &[u8], i: usize) -> Option<u32> {
+    let escape = input.get(i..i + 6)?;
+    if !escape.starts_with(b"\\u") || !escape[2..].iter().all(|&d| hex_digit(d).is_some()) {
+        return None;
+    }
+    Some(hex4(&escape[2..]))
+}
+
+#[cfg(test)]
+fn events(json: &str) -> Result<Vec<Event<'_>>> {
+    let mut parser = Parser::new(json.as_bytes());
+    let mut events = vec![];
+    while let Some(event) = parser.next_event()? {
+        events.push(event);
+    }
+    parser.end()?;
+    Ok(events)
+}
+
+#[test]
+fn parse_events() {
+    let events = events(r#" {"a": [1, -2.5e3, true, null], "b": {}} "#).unwrap();
+
+    assert!(matches!(
+        events.as_slice(),
+        [
+            Event::ObjectStart,
+            Event::Key(a),
+            Event::ArrayStart,
+            Event::Number("1"),
+            Event::Number("-2.5e3"),
+            Event::Bool(true),
+            Event::Null,
+            Event::ArrayEnd,
+            Event::Key(b),
+            Event::ObjectStart,
+            Event::ObjectEnd,
+            Event::ObjectEnd,
+        ] if a.unescape() == "a" && b.unescape() == "b"
+    ));
+}
+
+#[test]
+fn parse_escapes() {
+    let events = events(r#"["\u0041\n😀", "\u001f", "\/"]"#).unwrap();
+
+    match events.as_slice() {
+        [Event::ArrayStart, Event::String(a), Event::String(b), Event::String(c), Event::ArrayEnd] =>
+        {
+            assert_eq!(a.unescape(), "A\n😀");
+            assert!(!a.is_canonical());
+            assert_eq!(b.unescape(), "\u{1f}");
+            assert!(b.is_canonical());
+            assert_eq!(c.unescape(), "/");
+            assert!(!c.is_canonical());
+        }
+        _ => panic!("unexpected events {:?}", events),
+    }
+}
+
+#[test]
+fn parse_errors() {
+    for (json, offset) in &[
+        (r#"{"a" 1}"#, 5),
+        ("[1,]", 3),
+        ("[01]", 2),
+        (r#""\ud800""#, 1),
+        ("{} {}", 3),
+        ("", 0),
+    ] {
+        match events(json) {
+            Err(Error::Syntax { offset: found, .. }) => assert_eq!(found, *offset, "{}", json),
+            res => panic!("expected a syntax error for {} found {:?}", json, res),
+        }
+    }
+}
+
+#[test]
+fn string_length_limit() {
+    let mut parser = Parser::new(br#"["abc","abcd"]"#).max_string_len(Some(3));
+    assert_eq!(parser.next_event().unwrap(), Some(Event::ArrayStart));
+    assert!(matches!(parser.next_event(), Ok(Some(Event::String(_)))));
+    assert!(matches!(
+        parser.next_event(),
+        Err(Error::Syntax { offset: 7, .. })
+    ));
+}
+
+#[test]
+fn extract_top_level_fields() {
+    let json =
+        br#"{"content":{"type":"nested","room_id":"!no"},"room_id":"!a:b","type":"m.room.create"}"#;
+
+    let mut parser = Parser::new(json);
+    let mut fields = vec![];
+    while let Some(event) = parser.next() {
+        match event.unwrap() {
+            Event::Key(key)
+                if parser.depth() == 1 && (key.raw() == "type" || key.raw() == "room_id") =>
+            {
+                match parser.next() {
+                    Some(Ok(Event::String(value))) => fields.push((key.raw(), value.raw())),
+                    other => panic!("expected a string found {:?}", other),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    assert_eq!(fields, vec![("room_id", "!a:b"), ("type", "m.room.create")]);
+    assert!(matches!(
+        Parser::new(b"{} 1").last(),
+        Some(Err(Error::Syntax { offset: 3, .. }))
+    ));
+    assert_eq!(Event::Number("-12").as_i64(), Some(-12));
+    assert_eq!(Event::Number("1.5").as_i64(), None);
+}
+
+#[test]
+fn push_chunks() {
+    let json = br#" {"a": [12, true, "\ud83d\ude00"], "b": null} "#;
+    let expected = events(str::from_utf8(json).unwrap())
+        .unwrap()
+        .into_iter()
+        .map(OwnedEvent::from)
+        .collect::<Vec<_>>();
+
+    for size in 1..json.len() {
+        let mut parser = PushParser::new();
+        let mut events = vec![];
+        for chunk in json.chunks(size) {
+            events.extend(parser.feed(chunk).unwrap());
+        }
+        events.extend(parser.finish().unwrap());
+        assert_eq!(events, expected, "chunks of {}", size);
+    }
+
+    let mut parser = PushParser::new();
+    assert_eq!(parser.feed(b"12").unwrap(), vec![]);
+    assert_eq!(parser.feed(b"3").unwrap(), vec![]);
+    assert_eq!(
+        parser.finish().unwrap(),
+        vec![OwnedEvent::Number("123".to_owned())]
+    );
+
+    let mut parser = PushParser::new();
+    assert_eq!(parser.feed(b"[1,").unwrap().len(), 2);
+    assert!(matches!(
+        parser.feed(b"]"),
+        Err(Error::Syntax { offset: 3, .. })
+    ));
+    let mut parser = PushParser::new();
+    assert_eq!(parser.feed(b"{\"a\":tr").unwrap().len(), 2);
+    assert!(parser.finish().is_err());
+    let mut parser = PushParser::new();
+    parser.feed(b"{}").unwrap();
+    assert!(matches!(
+        parser.feed(b" x"),
+        Err(Error::Syntax { offset: 3, .. })
+    ));
+}
+
+#[test]
+fn push_long_string() {
+    // Scanning the string again for every byte would take billions of steps.
+    let long = "a\\u00e9".repeat(20_000);
+    let json = format!(r#"{{"k": "{}"}}"#, long);
+
+    let mut parser = PushParser::new();
+    let mut events = vec![];
+    for byte in json.as_bytes() {
+        events.extend(parser.feed(&[*byte]).unwrap());
+    }
+    events.extend(parser.finish().unwrap());
+    assert_eq!(
+        events,
+        vec![
+            OwnedEvent::ObjectStart,
+            OwnedEvent::Key("k".into()),
+            OwnedEvent::String("a\u{e9}".repeat(20_000)),
+            OwnedEvent::ObjectEnd,
+        ]
+    );
+
+    let mut parser = PushParser::new().max_string_len(Some(10));
+    let res = json
+        .as_bytes()
+        .iter()
+        .map(|byte| parser.feed(&[*byte]))
+        .find(Result::is_err);
+    assert!(matches!(res, Some(Err(Error::Syntax { offset: 6, .. }))));
+
+    let mut parser = PushParser::new();
+    parser.feed(b"[\"ab\\u00").unwrap();
+    assert!(matches!(
+        parser.feed(b"zz\"]"),
+        Err(Error::Syntax { offset: 8, .. })
+    ));
+}
+
+#[test]
+fn repair_invalid_utf8() {
+    let offsets = std::cell::RefCell::new(vec![]);
+    let report = |offset| offsets.borrow_mut().push(offset);
+
+    assert!(matches!(
+        repair_utf8(br#"["\ud83d\ude00", "\\ud800"]"#, &report),
+        Cow::Borrowed(_)
+    ));
+    assert_eq!(
+        repair_utf8(
+            b"[\"a\xFFb\", \"\\ud800\\ud800\\udc00\", \"\\udc00\xE2\x82",
+            &report
+        ),
+        "[\"a\u{FFFD}b\", \"\\ufffd\\ud800\\udc00\", \"\\ufffd\u{FFFD}".as_bytes()
+    );
+    assert_eq!(*offsets.borrow(), vec![3, 9, 31, 37]);
+}
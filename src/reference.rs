@@ -0,0 +1,85 @@
+//! Differential tests against the Python `canonicaljson` package, the implementation Synapse
+//! and most of the Matrix ecosystem sign with. Run them with
+//! `cargo test --features reference-tests`, `python3 -m pip install canonicaljson` first.
+//! `CANONICALJSON_PYTHON` picks another interpreter.
+
+use std::{
+    env,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use serde_json::{json, Value};
+
+use crate::{to_canonical_cow, to_canonical_string};
+
+const SCRIPT: &str = "import canonicaljson, json, sys
+sys.stdout.buffer.write(canonicaljson.encode_canonical_json(json.load(sys.stdin)))";
+
+/// What the reference implementation makes of `input`.
+fn reference(input: &str) -> Vec<u8> {
+    let python = env::var("CANONICALJSON_PYTHON").unwrap_or_else(|_| "python3".to_owned());
+    let mut child = Command::new(python)
+        .args(&["-c", SCRIPT])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .expect("failed to run the Python interpreter");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "canonicaljson failed on {}", input);
+    output.stdout
+}
+
+fn corpus() -> Vec<Value> {
+    vec![
+        json!({}),
+        json!([]),
+        json!({ "one": 1, "two": "Two" }),
+        json!({ "b": "2", "a": "1" }),
+        json!({ "auth": { "success": true, "mxid": "@john.doe:example.com", "profile": {
+            "display_name": "John Doe",
+            "three_pids": [
+                { "medium": "email", "address": "john.doe@example.org" },
+                { "medium": "msisdn", "address": "123456789" }
+            ]
+        } } }),
+        json!({ "a": "日本語" }),
+        json!({ "本": 2, "日": 1 }),
+        json!({ "a": "\u{1d11e}", "\u{1f600}": "\u{7f}\u{80}" }),
+        json!({ "a": null }),
+        json!({ "control": "\u{0}\u{1}\u{8}\t\n\u{b}\u{c}\r\u{1f}", "quote": "\"\\/" }),
+        json!({ "keys": { "": 0, "a": 1, "A": 2, "aa": 3, "a\u{0}": 4 } }),
+        json!([-9007199254740991_i64, -1, 0, 9007199254740991_i64]),
+        json!({
+            "content": { "body": "hello", "msgtype": "m.text" },
+            "depth": 12,
+            "origin_server_ts": 1_600_000_000_000_u64,
+            "prev_events": [["$a:example.org", { "sha256": "abc" }]],
+            "room_id": "!room:example.org",
+            "sender": "@alice:example.org",
+            "type": "m.room.message",
+            "unsigned": { "age_ts": 1_600_000_000_000_u64 }
+        }),
+    ]
+}
+
+#[test]
+fn matches_python_canonicaljson() {
+    for value in corpus() {
+        let expected = reference(&value.to_string());
+        let expected = String::from_utf8(expected).unwrap();
+
+        assert_eq!(to_canonical_string(&value).unwrap(), expected);
+        // Pretty printed input also goes through the transcoder's parser.
+        let pretty = serde_json::to_string_pretty(&value).unwrap();
+        assert_eq!(to_canonical_cow(&pretty).unwrap(), expected);
+    }
+}
@@ -0,0 +1,40 @@
+use http::{
+    header::{CONTENT_LENGTH, CONTENT_TYPE},
+    request::Builder,
+    HeaderValue, Request,
+};
+use serde::Serialize;
+
+use crate::{to_canonical_vec, Error, Result};
+
+/// Finish `builder` with `value` as the canonical JSON body, setting the `Content-Type` and
+/// `Content-Length` headers to match it.
+///
+/// The body can be anything a `Vec<u8>` converts into, so a hyper client can use the request as
+/// it is with `canonical_request::<_, hyper::Body>(builder, &value)`.
+pub fn canonical_request<T, B>(builder: Builder, value: &T) -> Result<Request<B>>
+where
+    T: ?Sized + Serialize,
+    B: From<Vec<u8>>,
+{
+    let body = to_canonical_vec(value)?;
+    builder
+        .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+        .header(CONTENT_LENGTH, body.len())
+        .body(B::from(body))
+        .map_err(|e| Error::Custom(e.to_string()))
+}
+
+#[test]
+fn request_body() {
+    let builder = Request::put("https://example.org/_matrix/federation/v1/send/1");
+    let request =
+        canonical_request::<_, Vec<u8>>(builder, &serde_json::json!({ "b": 2, "a": 1 })).unwrap();
+
+    assert_eq!(request.body(), br#"{"a":1,"b":2}"#);
+    assert_eq!(request.headers()[CONTENT_TYPE], "application/json");
+    assert_eq!(request.headers()[CONTENT_LENGTH], "13");
+
+    assert!(canonical_request::<_, Vec<u8>>(Request::post("/"), &1.5).is_err());
+    assert!(canonical_request::<_, Vec<u8>>(Request::post("not a uri"), &1).is_err());
+}
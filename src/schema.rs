@@ -0,0 +1,69 @@
+use jsonschema::JSONSchema;
+use serde::Serialize;
+
+use crate::{to_canonical_string, Error, Result, Severity, Violation, ViolationKind};
+
+/// Validate `value` against the JSON `schema` and only then serialize it as canonical JSON.
+///
+/// Every schema error is returned in `Error::Violations` with the pointer of the value it is
+/// about, so an invalid event never makes it as far as being signed.
+pub fn canonicalize_validated<T>(schema: &serde_json::Value, value: &T) -> Result<String>
+where
+    T: ?Sized + Serialize,
+{
+    let compiled = JSONSchema::compile(schema)
+        .map_err(|e| Error::InvalidInput(format!("invalid JSON schema: {}", e)))?;
+    let instance = serde_json::to_value(value).map_err(|e| Error::Custom(e.to_string()))?;
+
+    if let Err(errors) = compiled.validate(&instance) {
+        return Err(Error::Violations(
+            errors
+                .map(|err| Violation {
+                    kind: ViolationKind::Schema,
+                    severity: Severity::Error,
+                    pointer: err.instance_path.to_string(),
+                    found: err.to_string(),
+                    suggestion: None,
+                })
+                .collect(),
+        ));
+    }
+
+    to_canonical_string(value)
+}
+
+#[test]
+fn validates_before_canonicalizing() {
+    let schema = serde_json::json!({
+        "type": "object",
+        "properties": {
+            "membership": { "enum": ["join", "leave"] },
+            "displayname": { "type": "string" }
+        },
+        "required": ["membership"]
+    });
+
+    assert_eq!(
+        canonicalize_validated(
+            &schema,
+            &serde_json::json!({ "membership": "join", "displayname": "alice" })
+        )
+        .unwrap(),
+        r#"{"displayname":"alice","membership":"join"}"#
+    );
+
+    match canonicalize_validated(
+        &schema,
+        &serde_json::json!({ "membership": "knock", "displayname": 1 }),
+    ) {
+        Err(Error::Violations(violations)) => {
+            let mut pointers = violations
+                .iter()
+                .map(|v| v.pointer.as_str())
+                .collect::<Vec<_>>();
+            pointers.sort();
+            assert_eq!(pointers, vec!["/displayname", "/membership"]);
+        }
+        other => panic!("expected schema violations found {:?}", other),
+    }
+}
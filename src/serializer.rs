@@ -1,11 +1,8 @@
 use std::{fmt, io};
 
-use serde::{
-    ser::{self, Error as _},
-    serde_if_integer128, Serialize,
-};
+use serde::{ser, serde_if_integer128, Serialize};
 
-use crate::{formatter::Formatter, CanonicalJsonFmt, Error, Result};
+use crate::{formatter::Formatter, CanonicalJsonFmt, Error, Result, Sink};
 
 // We only use our own error type; no need for From conversions provided by the
 // standard library's try! macro. This reduces lines of LLVM IR by 4%.
@@ -29,7 +26,7 @@ pub struct Serializer<W> {
 
 impl<W> Serializer<W>
 where
-    W: io::Write,
+    W: Sink,
 {
     /// Creates a new JSON visitor whose output will be written to the writer
     /// specified.
@@ -48,9 +45,17 @@ where
     }
 }
 
+impl Serializer<Vec<u8>> {
+    /// Creates a new JSON visitor writing into a buffer with room for `capacity` bytes.
+    #[inline]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Serializer::new(Vec::with_capacity(capacity))
+    }
+}
+
 impl<'a, W> ser::Serializer for &'a mut Serializer<W>
 where
-    W: io::Write,
+    W: Sink,
 {
     type Ok = ();
     type Error = Error;
@@ -432,7 +437,7 @@ where
 
         impl<'ser, W> Write for Adapter<'ser, W>
         where
-            W: io::Write,
+            W: Sink,
         {
             fn write_str(&mut self, s: &str) -> fmt::Result {
                 debug_assert!(self.error.is_none());
@@ -485,7 +490,7 @@ pub enum Compound<'a, W: 'a> {
 
 impl<'a, W> ser::SerializeSeq for Compound<'a, W>
 where
-    W: io::Write,
+    W: Sink,
 {
     type Ok = ();
     type Error = Error;
@@ -539,7 +544,7 @@ where
 
 impl<'a, W> ser::SerializeTuple for Compound<'a, W>
 where
-    W: io::Write,
+    W: Sink,
 {
     type Ok = ();
     type Error = Error;
@@ -560,7 +565,7 @@ where
 
 impl<'a, W> ser::SerializeTupleStruct for Compound<'a, W>
 where
-    W: io::Write,
+    W: Sink,
 {
     type Ok = ();
     type Error = Error;
@@ -581,7 +586,7 @@ where
 
 impl<'a, W> ser::SerializeTupleVariant for Compound<'a, W>
 where
-    W: io::Write,
+    W: Sink,
 {
     type Ok = ();
     type Error = Error;
@@ -619,7 +624,7 @@ where
 
 impl<'a, W> ser::SerializeMap for Compound<'a, W>
 where
-    W: io::Write,
+    W: Sink,
 {
     type Ok = ();
     type Error = Error;
@@ -700,7 +705,7 @@ where
 
 impl<'a, W> ser::SerializeStruct for Compound<'a, W>
 where
-    W: io::Write,
+    W: Sink,
 {
     type Ok = ();
     type Error = Error;
@@ -747,7 +752,7 @@ where
 
 impl<'a, W> ser::SerializeStructVariant for Compound<'a, W>
 where
-    W: io::Write,
+    W: Sink,
 {
     type Ok = ();
     type Error = Error;
@@ -795,11 +800,11 @@ fn format_escaped_str<W>(
     value: &str,
 ) -> Result<()>
 where
-    W: ?Sized + io::Write,
+    W: ?Sized + Sink,
 {
-    tri!(formatter.begin_string(writer).map_err(Error::custom));
+    tri!(formatter.begin_string(writer).map_err(Error::io));
     tri!(format_escaped_str_contents(writer, formatter, value));
-    tri!(formatter.end_string(writer).map_err(Error::custom));
+    tri!(formatter.end_string(writer).map_err(Error::io));
     Ok(())
 }
 
@@ -809,7 +814,7 @@ fn format_escaped_str_contents<W>(
     value: &str,
 ) -> Result<()>
 where
-    W: ?Sized + io::Write,
+    W: ?Sized + Sink,
 {
     let bytes = value.as_bytes();
 
@@ -824,13 +829,13 @@ where
         if start < i {
             tri!(formatter
                 .write_string_fragment(writer, &value[start..i])
-                .map_err(Error::custom));
+                .map_err(Error::io));
         }
 
         let char_escape = CharEscape::from_escape_table(escape, byte);
         tri!(formatter
             .write_char_escape(writer, char_escape)
-            .map_err(Error::custom));
+            .map_err(Error::io));
 
         start = i + 1;
     }
@@ -838,7 +843,7 @@ where
     if start != bytes.len() {
         tri!(formatter
             .write_string_fragment(writer, &value[start..])
-            .map_err(Error::custom));
+            .map_err(Error::io));
     }
 
     Ok(())
@@ -0,0 +1,35 @@
+use std::io;
+
+/// Where the serializer writes its output, every writer the crate accepts goes through this one
+/// trait so there is a single serializer implementation.
+///
+/// Every `io::Write` is a `Sink`, including `Vec<u8>` and the adapter `to_canonical_fmt` puts
+/// around a `fmt::Write`. The trait is public so it can bound the public serializers but lives
+/// in a private module, so other crates can neither name nor implement it.
+pub trait Sink {
+    /// Write all of `buf` or fail.
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()>;
+}
+
+impl<W: ?Sized + io::Write> Sink for W {
+    #[inline]
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        io::Write::write_all(self, buf)
+    }
+}
+
+#[test]
+fn custom_sink() {
+    struct Counter(usize);
+
+    impl Sink for Counter {
+        fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+            self.0 += buf.len();
+            Ok(())
+        }
+    }
+
+    let mut ser = crate::CanonicalJson::new(Counter(0));
+    serde::Serialize::serialize(&serde_json::json!({ "b": [1, 2], "a": "x" }), &mut ser).unwrap();
+    assert_eq!(ser.into_inner().0, r#"{"a":"x","b":[1,2]}"#.len());
+}
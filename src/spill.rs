@@ -0,0 +1,188 @@
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::PathBuf,
+    process,
+    sync::atomic::{self, AtomicUsize},
+};
+
+use crate::{error::duplicate_key, Error, Profile, Result};
+
+static NEXT_RUN: AtomicUsize = AtomicUsize::new(0);
+
+/// The most runs merged at once, and so the most temporary files open at once.
+pub(crate) const MAX_FAN_IN: usize = 64;
+
+/// A sorted chunk of the entries of an object, written to a temporary file that is removed once
+/// the run is dropped. The file is only open while it is written or merged.
+pub(crate) struct Run {
+    path: PathBuf,
+}
+
+impl Run {
+    /// Write `entries`, which must already be sorted, to a new temporary file.
+    pub(crate) fn create(entries: &[(String, String)]) -> Result<Self> {
+        Run::write_with(|writer| {
+            for (key, pair) in entries {
+                write_entry(writer, key, pair)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn write_with<F>(write: F) -> Result<Self>
+    where
+        F: FnOnce(&mut BufWriter<File>) -> Result<()>,
+    {
+        let path = env::temp_dir().join(format!(
+            "canonical-json-{}-{}.run",
+            process::id(),
+            NEXT_RUN.fetch_add(1, atomic::Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(Error::io)?;
+        // Created before writing so the file is removed if writing fails.
+        let run = Run { path };
+
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)?;
+        writer.flush().map_err(Error::io)?;
+        Ok(run)
+    }
+
+    fn open(&self) -> io::Result<BufReader<File>> {
+        File::open(&self.path).map(BufReader::new)
+    }
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn write_entry<W: Write>(writer: &mut W, key: &str, pair: &str) -> Result<()> {
+    write_string(writer, key)
+        .and_then(|_| write_string(writer, pair))
+        .map_err(Error::io)
+}
+
+fn write_string<W: Write>(writer: &mut W, s: &str) -> io::Result<()> {
+    writer.write_all(&(s.len() as u64).to_le_bytes())?;
+    writer.write_all(s.as_bytes())
+}
+
+fn next_entry<R: Read>(reader: &mut R) -> Result<Option<(String, String)>> {
+    let key = match read_string(reader) {
+        Ok(key) => key,
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(Error::io(err)),
+    };
+    let pair = read_string(reader).map_err(Error::io)?;
+    Ok(Some((key, pair)))
+}
+
+fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
+    let mut len = [0; 8];
+    reader.read_exact(&mut len)?;
+    let mut buf = vec![0; u64::from_le_bytes(len) as usize];
+    reader.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// The next entry of a run, ordered backwards so the `BinaryHeap` pops the smallest key first.
+struct Head {
+    key: String,
+    pair: String,
+    run: usize,
+    profile: Profile,
+}
+
+impl Ord for Head {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .profile
+            .cmp_keys(&other.key, &self.key)
+            .then_with(|| other.run.cmp(&self.run))
+    }
+}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+/// Merge the sorted `runs`, handing each `"key":value` pair to `emit` in order. Fails if a key
+/// shows up twice.
+///
+/// Runs are merged `MAX_FAN_IN` at a time into bigger runs until few enough are left to merge
+/// into `emit`, so a huge object never has more than `MAX_FAN_IN` files open.
+pub(crate) fn merge<F>(runs: &mut Vec<Run>, profile: Profile, mut emit: F) -> Result<()>
+where
+    F: FnMut(&str) -> Result<()>,
+{
+    while runs.len() > MAX_FAN_IN {
+        let merged = runs.drain(..MAX_FAN_IN).collect::<Vec<_>>();
+        let run = Run::write_with(|writer| {
+            merge_runs(&merged, profile, |key, pair| write_entry(writer, key, pair))
+        })?;
+        runs.push(run);
+    }
+    merge_runs(runs, profile, |_, pair| emit(pair))
+}
+
+fn merge_runs<F>(runs: &[Run], profile: Profile, mut emit: F) -> Result<()>
+where
+    F: FnMut(&str, &str) -> Result<()>,
+{
+    let mut readers = runs
+        .iter()
+        .map(Run::open)
+        .collect::<io::Result<Vec<_>>>()
+        .map_err(Error::io)?;
+    let mut heads = BinaryHeap::with_capacity(readers.len());
+    for (run, reader) in readers.iter_mut().enumerate() {
+        if let Some((key, pair)) = next_entry(reader)? {
+            heads.push(Head {
+                key,
+                pair,
+                run,
+                profile,
+            });
+        }
+    }
+    // The key emitted last, equal keys of different runs end up next to each other.
+    let mut last: Option<String> = None;
+
+    while let Some(Head { key, pair, run, .. }) = heads.pop() {
+        if last.as_ref() == Some(&key) {
+            return Err(duplicate_key(&key));
+        }
+        emit(&key, &pair)?;
+        if let Some((next_key, next_pair)) = next_entry(&mut readers[run])? {
+            heads.push(Head {
+                key: next_key,
+                pair: next_pair,
+                run,
+                profile,
+            });
+        }
+        last = Some(key);
+    }
+    Ok(())
+}
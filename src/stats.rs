@@ -0,0 +1,25 @@
+//! Canonicalization cost recorded through the `metrics` facade, whatever recorder the
+//! application installed receives them.
+
+use std::time::Duration;
+
+use metrics::{counter, histogram};
+
+use crate::{Error, Result};
+
+/// Record a finished serialization that wrote `len` bytes.
+pub(crate) fn record(res: &Result<()>, len: usize) {
+    match res {
+        Ok(()) => {
+            counter!("canonical_json.canonicalizations", 1);
+            counter!("canonical_json.bytes_emitted", len as u64);
+        }
+        Err(Error::SizeLimit) => counter!("canonical_json.size_limit_rejections", 1),
+        Err(_) => counter!("canonical_json.errors", 1),
+    }
+}
+
+/// Record the time it took to sort the keys of one object.
+pub(crate) fn sorted(elapsed: Duration) {
+    histogram!("canonical_json.sort_time", elapsed);
+}
@@ -0,0 +1,633 @@
+use std::{borrow::Cow, collections::HashSet};
+
+#[cfg(feature = "ser")]
+use serde::ser::Serializer as _;
+
+#[cfg(feature = "ser")]
+use crate::{
+    error::duplicate_key, Serializer, Severity, Violation, ViolationKind, DEFAULT_SIZE_LIMIT,
+};
+use crate::{
+    read::{Event, Parser},
+    Error, Result, MAX_SAFE_INTEGER, MIN_SAFE_INTEGER,
+};
+
+/// Whether `json` is exactly what this crate produces for the value it holds: no whitespace
+/// between tokens, object keys sorted without duplicates, only integers inside of the canonical
+/// range and the minimal string escapes.
+pub fn is_canonical(json: &str) -> bool {
+    check_canonical(json).unwrap_or(false)
+}
+
+/// Whether every object in `json` has its keys in canonical order without duplicates, a cheaper
+/// check than `is_canonical` that ignores whitespace, numbers and escapes. Invalid JSON is never
+/// sorted.
+pub fn verify_sorted(json: &str) -> bool {
+    let mut parser = Parser::new(json.as_bytes());
+    // The last key of every open container, arrays and empty objects have none.
+    let mut last_keys: Vec<Option<Cow<'_, str>>> = vec![];
+    loop {
+        match parser.next_event() {
+            Ok(Some(Event::ObjectStart)) | Ok(Some(Event::ArrayStart)) => last_keys.push(None),
+            Ok(Some(Event::ObjectEnd)) | Ok(Some(Event::ArrayEnd)) => {
+                last_keys.pop();
+            }
+            Ok(Some(Event::Key(key))) => {
+                let key = key.unescape();
+                let last = last_keys
+                    .last_mut()
+                    .expect("the parser only yields keys inside objects");
+                if matches!(last, Some(last) if *last >= key) {
+                    return false;
+                }
+                *last = Some(key);
+            }
+            Ok(Some(_)) => {}
+            Ok(None) => return parser.end().is_ok(),
+            Err(_) => return false,
+        }
+    }
+}
+
+/// Canonicalize the JSON text `json`, borrowing it unchanged when it already is canonical so
+/// nothing is allocated or serialized.
+///
+/// The text of a `serde_json::value::RawValue` can be passed in through `RawValue::get`.
+#[cfg(feature = "ser")]
+pub fn to_canonical_cow(json: &str) -> Result<Cow<'_, str>> {
+    if json.len() <= DEFAULT_SIZE_LIMIT && check_canonical(json)? {
+        return Ok(Cow::Borrowed(json));
+    }
+
+    transcode(json, &mut None).map(Cow::Owned)
+}
+
+/// What canonicalizing a JSON text changed, returned by `to_canonical_with_report`. Every
+/// location is a JSON pointer.
+#[cfg(feature = "ser")]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CanonicalizationReport {
+    /// Whitespace between tokens was removed.
+    pub whitespace_removed: bool,
+    /// Objects whose keys were not in canonical order.
+    pub keys_reordered: Vec<String>,
+    /// Strings, or the keys at these locations, that were escaped differently.
+    pub escapes_normalized: Vec<String>,
+    /// Numbers written differently, like `-0`.
+    pub numbers_normalized: Vec<String>,
+}
+
+/// Like `to_canonical_cow` but also reports what had to change to make `json` canonical.
+///
+/// Instead of stopping at the first float every one of them is listed in
+/// `Error::Violations`.
+#[cfg(feature = "ser")]
+pub fn to_canonical_with_report(json: &str) -> Result<(String, CanonicalizationReport)> {
+    let mut reporter = Some(Reporter::default());
+    let out = transcode(json, &mut reporter)?;
+
+    let reporter = reporter.expect("the reporter is never taken");
+    if !reporter.floats.is_empty() {
+        return Err(Error::Violations(reporter.floats));
+    }
+    Ok((out, reporter.report))
+}
+
+#[cfg(feature = "ser")]
+#[derive(Default)]
+struct Reporter {
+    path: Vec<String>,
+    report: CanonicalizationReport,
+    floats: Vec<Violation>,
+}
+
+#[cfg(feature = "ser")]
+impl Reporter {
+    fn pointer(&self) -> String {
+        self.path
+            .iter()
+            .map(|segment| format!("/{}", segment.replace('~', "~0").replace('/', "~1")))
+            .collect()
+    }
+}
+
+#[cfg(feature = "ser")]
+fn transcode(json: &str, report: &mut Option<Reporter>) -> Result<String> {
+    let mut parser = Parser::new(json.as_bytes());
+    let mut out = Vec::with_capacity(json.len());
+    let event = next(&mut parser)?;
+    write_value(&mut parser, event, &mut out, report)?;
+    parser.end()?;
+
+    if let Some(reporter) = report {
+        reporter.report.whitespace_removed = parser.saw_whitespace();
+    }
+    if out.len() > DEFAULT_SIZE_LIMIT {
+        return Err(Error::SizeLimit);
+    }
+    Ok(
+        // The parser made sure every string is valid UTF-8.
+        unsafe { String::from_utf8_unchecked(out) },
+    )
+}
+
+fn check_canonical(json: &str) -> Result<bool> {
+    Ok(Validator::new().violation(json)?.is_none())
+}
+
+/// A configurable `is_canonical`. Each rule canonical JSON adds to the JSON grammar can be
+/// turned off, so one validator checks for exactly canonical JSON and another for input that
+/// canonicalizes without losing anything, like `Validator::new().sorted_keys(false)
+/// .minimal_escapes(false).no_whitespace(false)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Validator {
+    sorted_keys: bool,
+    unique_keys: bool,
+    integers_only: bool,
+    minimal_escapes: bool,
+    no_whitespace: bool,
+}
+
+impl Default for Validator {
+    fn default() -> Self {
+        Self {
+            sorted_keys: true,
+            unique_keys: true,
+            integers_only: true,
+            minimal_escapes: true,
+            no_whitespace: true,
+        }
+    }
+}
+
+impl Validator {
+    /// A validator checking every rule, like `is_canonical`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Object keys in canonical order, the same key twice in a row only counts as out of order
+    /// when `unique_keys` is checked.
+    pub fn sorted_keys(mut self, check: bool) -> Self {
+        self.sorted_keys = check;
+        self
+    }
+
+    /// No key twice in the same object.
+    pub fn unique_keys(mut self, check: bool) -> Self {
+        self.unique_keys = check;
+        self
+    }
+
+    /// Numbers are integers inside of `MIN_SAFE_INTEGER..=MAX_SAFE_INTEGER` without a fraction
+    /// or exponent, and not `-0`.
+    pub fn integers_only(mut self, check: bool) -> Self {
+        self.integers_only = check;
+        self
+    }
+
+    /// Strings and keys only escape what canonical JSON escapes, the way it escapes them.
+    pub fn minimal_escapes(mut self, check: bool) -> Self {
+        self.minimal_escapes = check;
+        self
+    }
+
+    /// No whitespace between tokens or around the document.
+    pub fn no_whitespace(mut self, check: bool) -> Self {
+        self.no_whitespace = check;
+        self
+    }
+
+    /// Whether `json` is valid JSON following every checked rule.
+    pub fn is_valid(&self, json: &str) -> bool {
+        matches!(self.violation(json), Ok(None))
+    }
+
+    /// Check `json`, failing with `Error::Syntax` if it is not JSON and `Error::InvalidInput`
+    /// naming the first rule it breaks and where.
+    pub fn validate(&self, json: &str) -> Result<()> {
+        match self.violation(json)? {
+            Some(msg) => Err(Error::InvalidInput(msg)),
+            None => Ok(()),
+        }
+    }
+
+    /// The first rule `json` breaks, the error is the one of the parser.
+    fn violation(&self, json: &str) -> Result<Option<String>> {
+        let mut parser = Parser::new(json.as_bytes());
+        let mut keys: Vec<ObjectKeys<'_>> = vec![];
+
+        loop {
+            let before = parser.offset();
+            let event = parser.next_event()?;
+            if self.no_whitespace && parser.saw_whitespace() {
+                return Ok(Some(format!("whitespace after byte {}", before)));
+            }
+
+            match event {
+                None => break,
+                Some(Event::ObjectStart) => keys.push(ObjectKeys::default()),
+                Some(Event::ObjectEnd) => {
+                    keys.pop();
+                }
+                Some(Event::Key(key)) => {
+                    let start = parser.offset() - key.raw().len() - 2;
+                    if self.minimal_escapes && !key.is_canonical() {
+                        return Ok(Some(format!(
+                            "key at byte {} is not escaped minimally",
+                            start
+                        )));
+                    }
+                    let key = key.unescape();
+                    let object = keys.last_mut().expect("keys are only found in objects");
+                    let duplicate = if self.sorted_keys {
+                        match &object.last {
+                            Some(prev) if *prev > key => {
+                                return Ok(Some(format!(
+                                    "key {:?} at byte {} is not in canonical order",
+                                    key, start
+                                )))
+                            }
+                            Some(prev) => *prev == key,
+                            None => false,
+                        }
+                    } else {
+                        self.unique_keys && !object.seen.insert(key.clone())
+                    };
+                    if self.unique_keys && duplicate {
+                        return Ok(Some(format!("duplicate key {:?} at byte {}", key, start)));
+                    }
+                    object.last = Some(key);
+                }
+                Some(Event::String(s)) if self.minimal_escapes && !s.is_canonical() => {
+                    let start = parser.offset() - s.raw().len() - 2;
+                    return Ok(Some(format!(
+                        "string at byte {} is not escaped minimally",
+                        start
+                    )));
+                }
+                Some(Event::Number(n)) if self.integers_only && (!is_integer(n) || n == "-0") => {
+                    return Ok(Some(format!(
+                        "`{}` at byte {} is not a canonical JSON integer",
+                        n,
+                        parser.offset() - n.len()
+                    )))
+                }
+                _ => {}
+            }
+        }
+        let before = parser.offset();
+        parser.end()?;
+
+        if self.no_whitespace && parser.saw_whitespace() {
+            return Ok(Some(format!("whitespace after byte {}", before)));
+        }
+        Ok(None)
+    }
+}
+
+/// The keys `Validator` saw in an open object.
+#[derive(Default)]
+struct ObjectKeys<'a> {
+    last: Option<Cow<'a, str>>,
+    /// Every key, only kept when the keys don't have to be sorted.
+    seen: HashSet<Cow<'a, str>>,
+}
+
+/// Whether `number` is written with a fraction or an exponent.
+fn is_float(number: &str) -> bool {
+    number.contains(&['.', 'e', 'E'][..])
+}
+
+/// Whether `number` is an integer canonical JSON allows, one inside of
+/// `MIN_SAFE_INTEGER..=MAX_SAFE_INTEGER` written without a fraction or exponent.
+fn is_integer(number: &str) -> bool {
+    match number.parse::<i64>() {
+        Ok(int) => (MIN_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&int),
+        Err(_) => false,
+    }
+}
+
+/// The next event inside a value, the parser is only done once the top level value is complete.
+#[cfg(feature = "ser")]
+fn next<'a>(parser: &mut Parser<'a>) -> Result<Event<'a>> {
+    Ok(parser
+        .next_event()?
+        .expect("the parser yields a whole value before it is done"))
+}
+
+#[cfg(feature = "ser")]
+fn write_str(out: &mut Vec<u8>, value: &str) -> Result<()> {
+    Serializer::new(out).serialize_str(value)
+}
+
+#[cfg(feature = "ser")]
+fn write_value<'a>(
+    parser: &mut Parser<'a>,
+    event: Event<'a>,
+    out: &mut Vec<u8>,
+    report: &mut Option<Reporter>,
+) -> Result<()> {
+    match event {
+        Event::Null => out.extend_from_slice(b"null"),
+        Event::Bool(true) => out.extend_from_slice(b"true"),
+        Event::Bool(false) => out.extend_from_slice(b"false"),
+        Event::Number(n) => {
+            if !is_integer(n) {
+                let kind = if is_float(n) {
+                    ViolationKind::Float
+                } else {
+                    ViolationKind::IntegerOutOfRange
+                };
+                let reporter = match report {
+                    Some(reporter) => reporter,
+                    None if kind == ViolationKind::Float => {
+                        return Err(Error::InvalidInput(format!(
+                            "{} is not valid in canonical JSON, only integers are",
+                            n
+                        )))
+                    }
+                    None => {
+                        return Err(Error::IntegerOutOfRange {
+                            pointer: String::new(),
+                            value: n.to_owned(),
+                        })
+                    }
+                };
+                reporter.floats.push(Violation {
+                    kind,
+                    severity: Severity::Error,
+                    pointer: reporter.pointer(),
+                    found: n.to_owned(),
+                    suggestion: None,
+                });
+            }
+            if n == "-0" {
+                if let Some(reporter) = report {
+                    let pointer = reporter.pointer();
+                    reporter.report.numbers_normalized.push(pointer);
+                }
+            }
+            out.extend_from_slice(if n == "-0" { "0" } else { n }.as_bytes());
+        }
+        Event::String(s) => {
+            if !s.is_canonical() {
+                if let Some(reporter) = report {
+                    let pointer = reporter.pointer();
+                    reporter.report.escapes_normalized.push(pointer);
+                }
+            }
+            write_str(out, &s.unescape())?
+        }
+        Event::ArrayStart => {
+            out.push(b'[');
+            let mut idx = 0;
+            loop {
+                match next(parser)? {
+                    Event::ArrayEnd => break,
+                    event => {
+                        if idx != 0 {
+                            out.push(b',');
+                        }
+                        if let Some(reporter) = report {
+                            reporter.path.push(idx.to_string());
+                        }
+                        write_value(parser, event, out, report)?;
+                        if let Some(reporter) = report {
+                            reporter.path.pop();
+                        }
+                        idx += 1;
+                    }
+                }
+            }
+            out.push(b']');
+        }
+        Event::ObjectStart => {
+            let mut entries = vec![];
+            loop {
+                match next(parser)? {
+                    Event::ObjectEnd => break,
+                    Event::Key(key) => {
+                        let event = next(parser)?;
+                        let mut value = vec![];
+                        if let Some(reporter) = report {
+                            reporter.path.push(key.unescape().into_owned());
+                            if !key.is_canonical() {
+                                let pointer = reporter.pointer();
+                                reporter.report.escapes_normalized.push(pointer);
+                            }
+                        }
+                        write_value(parser, event, &mut value, report)?;
+                        if let Some(reporter) = report {
+                            reporter.path.pop();
+                        }
+                        entries.push((key.unescape(), value));
+                    }
+                    _ => unreachable!("objects only hold keys followed by values"),
+                }
+            }
+
+            if let Some(reporter) = report {
+                if entries.windows(2).any(|pair| pair[0].0 > pair[1].0) {
+                    let pointer = reporter.pointer();
+                    reporter.report.keys_reordered.push(pointer);
+                }
+            }
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            if let Some(pair) = entries.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+                return Err(duplicate_key(&pair[0].0));
+            }
+
+            out.push(b'{');
+            for (idx, (key, value)) in entries.iter().enumerate() {
+                if idx != 0 {
+                    out.push(b',');
+                }
+                write_str(out, key)?;
+                out.push(b':');
+                out.extend_from_slice(value);
+            }
+            out.push(b'}');
+        }
+        Event::ObjectEnd | Event::ArrayEnd | Event::Key(_) => {
+            unreachable!("the parser only yields these after a value started")
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "ser")]
+#[test]
+fn borrows_canonical_input() {
+    let json = r#"{"a":[1,-2,"\n\u001f"],"b":{"日":null,"本":true}}"#;
+
+    assert!(is_canonical(json));
+    assert!(matches!(to_canonical_cow(json), Ok(Cow::Borrowed(s)) if s == json));
+}
+
+#[cfg(feature = "ser")]
+#[test]
+fn integers_outside_of_the_range() {
+    for json in &["[9007199254740991]", "[-9007199254740991]"] {
+        assert!(is_canonical(json), "{}", json);
+        assert!(matches!(to_canonical_cow(json), Ok(Cow::Borrowed(_))));
+    }
+    for json in &[
+        "[9007199254740992]",
+        "[-9007199254740992]",
+        "[9223372036854775808]",
+        "[-99999999999999999999]",
+    ] {
+        assert!(!is_canonical(json), "{}", json);
+        assert!(
+            matches!(to_canonical_cow(json), Err(Error::IntegerOutOfRange { .. })),
+            "{}",
+            json
+        );
+    }
+}
+
+#[cfg(feature = "ser")]
+#[test]
+fn canonicalizes_other_input() {
+    let json = r#" { "b" : { "本": true, "日": null }, "a": [ 1, -0, "A\/" ] } "#;
+
+    assert!(!is_canonical(json));
+    assert!(matches!(
+        to_canonical_cow(json),
+        Ok(Cow::Owned(s)) if s == r#"{"a":[1,0,"A/"],"b":{"日":null,"本":true}}"#
+    ));
+}
+
+#[cfg(feature = "ser")]
+#[test]
+fn rejects_invalid_input() {
+    assert!(matches!(
+        to_canonical_cow(r#"{"a":1.5}"#),
+        Err(Error::InvalidInput(_))
+    ));
+    assert!(matches!(
+        to_canonical_cow(r#"{"a":1,"a":2}"#),
+        Err(Error::InvalidInput(_))
+    ));
+    assert!(matches!(
+        to_canonical_cow(r#"{"a":1"#),
+        Err(Error::Syntax { .. })
+    ));
+}
+
+#[cfg(feature = "ser")]
+#[test]
+fn reports_changes() {
+    let json = r#" { "b" : { "本": true, "日": -0 }, "a": [ 1, "A\/" ], "\u0063": 1 } "#;
+
+    let (out, report) = to_canonical_with_report(json).unwrap();
+    assert_eq!(out, r#"{"a":[1,"A/"],"b":{"日":0,"本":true},"c":1}"#);
+    assert_eq!(
+        report,
+        CanonicalizationReport {
+            whitespace_removed: true,
+            keys_reordered: vec!["/b".to_owned(), "".to_owned()],
+            escapes_normalized: vec!["/a/1".to_owned(), "/c".to_owned()],
+            numbers_normalized: vec!["/b/日".to_owned()],
+        }
+    );
+
+    match to_canonical_with_report(r#"{"a":1.5,"b":[2e3]}"#) {
+        Err(Error::Violations(floats)) => assert_eq!(
+            floats
+                .iter()
+                .map(|v| v.pointer.as_str())
+                .collect::<Vec<_>>(),
+            vec!["/a", "/b/0"]
+        ),
+        other => panic!("expected float violations found {:?}", other),
+    }
+}
+
+#[test]
+fn verifies_key_order() {
+    assert!(verify_sorted(
+        r#"{"a":1,"b":{"c":[{"e":1,"f":2}],"d":1.5}}"#
+    ));
+    assert!(verify_sorted(r#" { "a" : [ ] , "b" : "\u0063" } "#));
+    assert!(verify_sorted("[]"));
+
+    assert!(!verify_sorted(r#"{"b":1,"a":2}"#));
+    assert!(!verify_sorted(r#"{"a":[{"d":1,"c":2}]}"#));
+    assert!(!verify_sorted(r#"{"a":1,"a":2}"#));
+    // `\u0062` is `b`.
+    assert!(!verify_sorted(r#"{"c":1,"\u0062":2}"#));
+    assert!(!verify_sorted(r#"{"a":1"#));
+    assert!(!verify_sorted(r#"{"a":1} {}"#));
+}
+
+#[test]
+fn configurable_rules() {
+    let canonical = r#"{"a":[1,"\n"],"b":{}}"#;
+    let input = r#"{ "b": {}, "a": [1, "\u000a"] }"#;
+
+    let all = Validator::new();
+    assert!(all.is_valid(canonical));
+    assert!(!all.is_valid(input));
+    assert!(!all.is_valid("{"));
+    assert!(matches!(
+        all.validate(input),
+        Err(Error::InvalidInput(msg)) if msg == "whitespace after byte 1"
+    ));
+
+    let accepting = Validator::new()
+        .sorted_keys(false)
+        .minimal_escapes(false)
+        .no_whitespace(false);
+    assert!(accepting.is_valid(input));
+    assert!(accepting.is_valid(canonical));
+    assert!(matches!(
+        accepting.validate(r#"{"b":1,"a":2,"b":3}"#),
+        Err(Error::InvalidInput(msg)) if msg == r#"duplicate key "b" at byte 13"#
+    ));
+    assert!(matches!(
+        accepting.validate("[1, -0]"),
+        Err(Error::InvalidInput(msg)) if msg == "`-0` at byte 4 is not a canonical JSON integer"
+    ));
+    assert!(matches!(
+        accepting.validate("[1"),
+        Err(Error::Syntax { .. })
+    ));
+
+    let messages = |validator: Validator, json: &str| match validator.validate(json) {
+        Err(Error::InvalidInput(msg)) => msg,
+        res => panic!("{} validated as {:?}", json, res),
+    };
+    assert_eq!(
+        messages(all, r#"{"b":1,"a":2}"#),
+        r#"key "a" at byte 7 is not in canonical order"#
+    );
+    assert_eq!(
+        messages(all, r#"{"a":1,"a":2}"#),
+        r#"duplicate key "a" at byte 7"#
+    );
+    assert!(all.unique_keys(false).is_valid(r#"{"a":1,"a":2}"#));
+    assert_eq!(
+        messages(all, r#"["\/"]"#),
+        "string at byte 1 is not escaped minimally"
+    );
+    assert_eq!(
+        messages(all, "[1.5]"),
+        "`1.5` at byte 1 is not a canonical JSON integer"
+    );
+    assert!(all.integers_only(false).is_valid("[1.5,-0]"));
+    assert!(all.is_valid("[9007199254740991,-9007199254740991]"));
+    assert_eq!(
+        messages(all, "[9007199254740992]"),
+        "`9007199254740992` at byte 1 is not a canonical JSON integer"
+    );
+    assert_eq!(
+        messages(accepting, "[1,-9223372036854775809]"),
+        "`-9223372036854775809` at byte 3 is not a canonical JSON integer"
+    );
+    assert!(all
+        .integers_only(false)
+        .is_valid("[9007199254740992,99999999999999999999]"));
+    assert_eq!(messages(all, "[] "), "whitespace after byte 2");
+}
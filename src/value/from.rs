@@ -0,0 +1,150 @@
+use std::convert::TryFrom;
+
+use crate::{
+    value::{CanonicalJsonObject, CanonicalJsonValue},
+    Error, MAX_SAFE_INTEGER, MIN_SAFE_INTEGER,
+};
+
+macro_rules! from_integer {
+    ($($ty:ty)*) => {
+        $(
+            impl From<$ty> for CanonicalJsonValue {
+                fn from(value: $ty) -> Self {
+                    CanonicalJsonValue::Integer(i64::from(value))
+                }
+            }
+        )*
+    };
+}
+
+from_integer! { i8 i16 i32 u8 u16 u32 }
+
+// These can hold integers outside of the canonical range, the conversion is checked instead of
+// wrapping.
+macro_rules! try_from_integer {
+    ($($ty:ty)*) => {
+        $(
+            impl TryFrom<$ty> for CanonicalJsonValue {
+                type Error = Error;
+
+                fn try_from(value: $ty) -> Result<Self, Error> {
+                    match i64::try_from(value) {
+                        Ok(int) if (MIN_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&int) => {
+                            Ok(CanonicalJsonValue::Integer(int))
+                        }
+                        _ => Err(Error::IntegerOutOfRange {
+                            pointer: String::new(),
+                            value: value.to_string(),
+                        }),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+try_from_integer! { i64 u64 usize isize i128 u128 }
+
+impl From<bool> for CanonicalJsonValue {
+    fn from(value: bool) -> Self {
+        CanonicalJsonValue::Bool(value)
+    }
+}
+
+impl From<String> for CanonicalJsonValue {
+    fn from(value: String) -> Self {
+        CanonicalJsonValue::String(value)
+    }
+}
+
+impl From<&str> for CanonicalJsonValue {
+    fn from(value: &str) -> Self {
+        CanonicalJsonValue::String(value.to_owned())
+    }
+}
+
+impl From<Vec<CanonicalJsonValue>> for CanonicalJsonValue {
+    fn from(value: Vec<CanonicalJsonValue>) -> Self {
+        CanonicalJsonValue::Array(value)
+    }
+}
+
+impl From<CanonicalJsonObject> for CanonicalJsonValue {
+    fn from(value: CanonicalJsonObject) -> Self {
+        CanonicalJsonValue::Object(value)
+    }
+}
+
+/// The entries are sorted, the order of the `IndexMap` is not kept.
+#[cfg(feature = "indexmap")]
+impl<S> From<indexmap::IndexMap<String, CanonicalJsonValue, S>> for CanonicalJsonValue {
+    fn from(value: indexmap::IndexMap<String, CanonicalJsonValue, S>) -> Self {
+        CanonicalJsonValue::Object(value.into_iter().collect())
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl From<uuid::Uuid> for CanonicalJsonValue {
+    fn from(value: uuid::Uuid) -> Self {
+        CanonicalJsonValue::String(value.to_string())
+    }
+}
+
+#[cfg(feature = "url")]
+impl From<url::Url> for CanonicalJsonValue {
+    fn from(value: url::Url) -> Self {
+        CanonicalJsonValue::String(String::from(value))
+    }
+}
+
+#[cfg(feature = "mime")]
+impl From<mime::Mime> for CanonicalJsonValue {
+    fn from(value: mime::Mime) -> Self {
+        CanonicalJsonValue::String(value.to_string())
+    }
+}
+
+impl<T: Into<CanonicalJsonValue>> From<Option<T>> for CanonicalJsonValue {
+    fn from(value: Option<T>) -> Self {
+        match value {
+            Some(value) => value.into(),
+            None => CanonicalJsonValue::Null,
+        }
+    }
+}
+
+#[cfg(all(feature = "indexmap", feature = "ser"))]
+#[test]
+fn index_maps() {
+    use indexmap::IndexMap;
+
+    use crate::{to_canonical_object, to_canonical_string};
+
+    #[derive(serde_derive::Serialize)]
+    struct Content {
+        fields: IndexMap<String, IndexMap<&'static str, u32>>,
+    }
+
+    let mut inner = IndexMap::new();
+    inner.insert("z", 1);
+    inner.insert("y", 2);
+    let mut fields = IndexMap::new();
+    fields.insert("b".to_owned(), inner.clone());
+    fields.insert("a".to_owned(), IndexMap::new());
+    let content = Content { fields };
+    assert_eq!(
+        to_canonical_string(&content).unwrap(),
+        r#"{"fields":{"a":{},"b":{"y":2,"z":1}}}"#
+    );
+
+    let object = to_canonical_object(&inner).unwrap();
+    assert_eq!(object.keys().collect::<Vec<_>>(), vec!["y", "z"]);
+    let values = inner
+        .into_iter()
+        .map(|(key, value)| (key.to_owned(), CanonicalJsonValue::from(value)))
+        .collect::<IndexMap<_, _>>();
+    assert_eq!(
+        CanonicalJsonValue::from(values),
+        CanonicalJsonValue::Object(object)
+    );
+}
@@ -0,0 +1,64 @@
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{
+        ArrayValidation, InstanceType, NumberValidation, ObjectValidation, Schema, SchemaObject,
+    },
+    JsonSchema,
+};
+
+use super::CanonicalJsonValue;
+use crate::{MAX_SAFE_INTEGER, MIN_SAFE_INTEGER};
+
+/// Any JSON value except floats, with integers limited to the range canonical JSON allows.
+/// `CanonicalJsonObject` is a `BTreeMap` and gets its schema from schemars.
+impl JsonSchema for CanonicalJsonValue {
+    fn schema_name() -> String {
+        "CanonicalJsonValue".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let value = gen.subschema_for::<CanonicalJsonValue>();
+        SchemaObject {
+            instance_type: Some(
+                vec![
+                    InstanceType::Null,
+                    InstanceType::Boolean,
+                    InstanceType::Integer,
+                    InstanceType::String,
+                    InstanceType::Array,
+                    InstanceType::Object,
+                ]
+                .into(),
+            ),
+            number: Some(Box::new(NumberValidation {
+                minimum: Some(MIN_SAFE_INTEGER as f64),
+                maximum: Some(MAX_SAFE_INTEGER as f64),
+                ..Default::default()
+            })),
+            array: Some(Box::new(ArrayValidation {
+                items: Some(value.clone().into()),
+                ..Default::default()
+            })),
+            object: Some(Box::new(ObjectValidation {
+                additional_properties: Some(Box::new(value)),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+#[test]
+fn value_schema() {
+    let schema = serde_json::to_value(schemars::schema_for!(CanonicalJsonValue)).unwrap();
+
+    assert_eq!(schema["title"], "CanonicalJsonValue");
+    assert_eq!(schema["maximum"], MAX_SAFE_INTEGER as f64);
+    assert_eq!(schema["minimum"], MIN_SAFE_INTEGER as f64);
+    assert_eq!(schema["items"]["$ref"], "#/definitions/CanonicalJsonValue");
+    assert!(!schema["type"]
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::json!("number")));
+}
@@ -0,0 +1,384 @@
+use std::collections::{btree_map::Entry, BTreeMap};
+
+use serde::{de::DeserializeOwned, Serialize, Serializer};
+
+use crate::{Error, Result};
+
+mod de;
+mod from;
+#[cfg(feature = "schemars")]
+mod json_schema;
+#[cfg(feature = "de")]
+mod parse;
+mod ser;
+
+#[cfg(feature = "de")]
+pub(crate) use self::parse::from_parser;
+#[cfg(feature = "de")]
+pub use self::parse::{from_slice, from_slice_with_trailing, NumberPolicy};
+
+pub(crate) use self::ser::ObjectKeySerializer;
+
+/// A JSON object holding only values that are valid in canonical JSON, the keys are kept in
+/// canonical order.
+pub type CanonicalJsonObject = BTreeMap<String, CanonicalJsonValue>;
+
+/// Any value that can be represented in canonical JSON.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CanonicalJsonValue {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    String(String),
+    Array(Vec<CanonicalJsonValue>),
+    Object(CanonicalJsonObject),
+}
+
+impl CanonicalJsonValue {
+    pub fn is_null(&self) -> bool {
+        matches!(self, CanonicalJsonValue::Null)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            CanonicalJsonValue::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            CanonicalJsonValue::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            CanonicalJsonValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<CanonicalJsonValue>> {
+        match self {
+            CanonicalJsonValue::Array(array) => Some(array),
+            _ => None,
+        }
+    }
+
+    pub fn as_array_mut(&mut self) -> Option<&mut Vec<CanonicalJsonValue>> {
+        match self {
+            CanonicalJsonValue::Array(array) => Some(array),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&CanonicalJsonObject> {
+        match self {
+            CanonicalJsonValue::Object(object) => Some(object),
+            _ => None,
+        }
+    }
+
+    pub fn as_object_mut(&mut self) -> Option<&mut CanonicalJsonObject> {
+        match self {
+            CanonicalJsonValue::Object(object) => Some(object),
+            _ => None,
+        }
+    }
+
+    /// The value of `key` if this is an object that holds it.
+    pub fn get(&self, key: &str) -> Option<&CanonicalJsonValue> {
+        self.as_object()?.get(key)
+    }
+
+    /// The value of `key` if this is an object that holds it.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut CanonicalJsonValue> {
+        self.as_object_mut()?.get_mut(key)
+    }
+
+    /// Insert `value` at `key` returning the previous value, fails if this is not an object.
+    ///
+    /// Integers that may be outside of the canonical range, like `i64` or `u64`, have to be
+    /// converted with `TryFrom` first.
+    pub fn insert<K, V>(&mut self, key: K, value: V) -> Result<Option<CanonicalJsonValue>>
+    where
+        K: Into<String>,
+        V: Into<CanonicalJsonValue>,
+    {
+        Ok(self.object_mut()?.insert(key.into(), value.into()))
+    }
+
+    /// Remove `key` if this is an object that holds it.
+    pub fn remove(&mut self, key: &str) -> Option<CanonicalJsonValue> {
+        self.as_object_mut()?.remove(key)
+    }
+
+    /// The entry of `key` for in place modification, fails if this is not an object.
+    pub fn entry<K>(&mut self, key: K) -> Result<Entry<'_, String, CanonicalJsonValue>>
+    where
+        K: Into<String>,
+    {
+        Ok(self.object_mut()?.entry(key.into()))
+    }
+
+    fn object_mut(&mut self) -> Result<&mut CanonicalJsonObject> {
+        self.as_object_mut()
+            .ok_or_else(|| Error::InvalidInput("expected a JSON object".to_owned()))
+    }
+}
+
+impl Serialize for CanonicalJsonValue {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            CanonicalJsonValue::Null => serializer.serialize_unit(),
+            CanonicalJsonValue::Bool(b) => serializer.serialize_bool(*b),
+            CanonicalJsonValue::Integer(i) => serializer.serialize_i64(*i),
+            CanonicalJsonValue::String(s) => serializer.serialize_str(s),
+            CanonicalJsonValue::Array(vec) => vec.serialize(serializer),
+            CanonicalJsonValue::Object(map) => map.serialize(serializer),
+        }
+    }
+}
+
+/// Convert `value` into a `CanonicalJsonValue`, failing for anything canonical JSON can't
+/// represent.
+pub fn to_canonical_value<T>(value: &T) -> Result<CanonicalJsonValue>
+where
+    T: ?Sized + Serialize,
+{
+    value.serialize(ser::Serializer)
+}
+
+/// Convert `value` into a `CanonicalJsonObject` so it can be modified, for example to add
+/// `unsigned` or `signatures`, before it is serialized.
+pub fn to_canonical_object<T>(value: &T) -> Result<CanonicalJsonObject>
+where
+    T: ?Sized + Serialize,
+{
+    match to_canonical_value(value)? {
+        CanonicalJsonValue::Object(object) => Ok(object),
+        _ => Err(Error::InvalidInput(
+            "expected a value that serializes to a JSON object".to_owned(),
+        )),
+    }
+}
+
+/// Deserialize a `T` from a `CanonicalJsonValue`, the inverse of `to_canonical_value`.
+pub fn from_canonical_value<T>(value: CanonicalJsonValue) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    T::deserialize(value)
+}
+
+#[cfg(feature = "ser")]
+#[test]
+fn struct_to_object() {
+    #[derive(serde_derive::Serialize)]
+    struct Content {
+        body: String,
+        msgtype: &'static str,
+    }
+
+    #[derive(serde_derive::Serialize)]
+    struct Event {
+        sender: &'static str,
+        content: Content,
+        origin_server_ts: u64,
+        state_key: Option<String>,
+    }
+
+    let event = Event {
+        sender: "@alice:example.org",
+        content: Content {
+            body: "hi".to_owned(),
+            msgtype: "m.text",
+        },
+        origin_server_ts: 1_000,
+        state_key: None,
+    };
+
+    let mut object = to_canonical_object(&event).unwrap();
+    assert_eq!(
+        object["origin_server_ts"],
+        CanonicalJsonValue::Integer(1_000)
+    );
+
+    object.insert(
+        "unsigned".to_owned(),
+        CanonicalJsonValue::Object(BTreeMap::new()),
+    );
+    assert_eq!(
+        crate::to_canonical_string(&object).unwrap(),
+        r#"{"content":{"body":"hi","msgtype":"m.text"},"origin_server_ts":1000,"sender":"@alice:example.org","state_key":null,"unsigned":{}}"#
+    );
+}
+
+#[test]
+fn invalid_objects() {
+    assert!(matches!(
+        to_canonical_object(&serde_json::json!({ "a": 1.5 })),
+        Err(Error::InvalidInput(_))
+    ));
+    assert!(matches!(
+        to_canonical_object(&[1, 2]),
+        Err(Error::InvalidInput(_))
+    ));
+    assert!(matches!(
+        to_canonical_value(&u64::MAX),
+        Err(Error::IntegerOutOfRange { .. })
+    ));
+}
+
+#[test]
+fn out_of_range_integers() {
+    use crate::{MAX_SAFE_INTEGER, MIN_SAFE_INTEGER};
+
+    assert_eq!(
+        to_canonical_value(&MAX_SAFE_INTEGER).unwrap(),
+        CanonicalJsonValue::Integer(MAX_SAFE_INTEGER)
+    );
+    assert_eq!(
+        to_canonical_value(&MIN_SAFE_INTEGER).unwrap(),
+        CanonicalJsonValue::Integer(MIN_SAFE_INTEGER)
+    );
+    for value in &[MAX_SAFE_INTEGER + 1, MIN_SAFE_INTEGER - 1, i64::MAX] {
+        assert!(matches!(
+            to_canonical_value(value),
+            Err(Error::IntegerOutOfRange { pointer, .. }) if pointer.is_empty()
+        ));
+    }
+    assert!(matches!(
+        to_canonical_value(&(MAX_SAFE_INTEGER as u128 + 1)),
+        Err(Error::IntegerOutOfRange { .. })
+    ));
+
+    let mut map = BTreeMap::new();
+    map.insert("ts", vec![1, MAX_SAFE_INTEGER + 1]);
+    assert!(matches!(
+        to_canonical_object(&map),
+        Err(Error::IntegerOutOfRange { pointer, value })
+            if pointer == "/ts/1" && value == "9007199254740992"
+    ));
+}
+
+#[cfg(feature = "ser")]
+#[test]
+fn mutate_value() {
+    use std::convert::TryFrom;
+
+    let mut event = to_canonical_value(&serde_json::json!({
+        "content": { "body": "hi" },
+        "type": "m.room.message"
+    }))
+    .unwrap();
+
+    event
+        .insert(
+            "origin_server_ts",
+            CanonicalJsonValue::try_from(1_600_000_000_000_u64).unwrap(),
+        )
+        .unwrap();
+    event
+        .entry("unsigned")
+        .unwrap()
+        .or_insert_with(|| CanonicalJsonValue::Object(BTreeMap::new()))
+        .insert("age", 45)
+        .unwrap();
+    event
+        .get_mut("content")
+        .unwrap()
+        .insert("msgtype", "m.text")
+        .unwrap();
+    assert_eq!(
+        event.remove("type"),
+        Some(CanonicalJsonValue::from("m.room.message"))
+    );
+
+    assert_eq!(
+        event.get("unsigned").and_then(|u| u.get("age")),
+        Some(&45.into())
+    );
+    assert!(CanonicalJsonValue::try_from(u64::MAX).is_err());
+    assert!(matches!(
+        CanonicalJsonValue::try_from(u64::MAX >> 1),
+        Err(Error::IntegerOutOfRange { .. })
+    ));
+    assert!(CanonicalJsonValue::try_from(crate::MIN_SAFE_INTEGER - 1).is_err());
+    assert_eq!(
+        CanonicalJsonValue::try_from(crate::MAX_SAFE_INTEGER).unwrap(),
+        CanonicalJsonValue::Integer(crate::MAX_SAFE_INTEGER)
+    );
+    assert!(CanonicalJsonValue::Null.insert("a", true).is_err());
+    assert_eq!(
+        crate::to_canonical_string(&event).unwrap(),
+        r#"{"content":{"body":"hi","msgtype":"m.text"},"origin_server_ts":1600000000000,"unsigned":{"age":45}}"#
+    );
+}
+
+#[test]
+fn value_round_trip() {
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize)]
+    enum Membership {
+        Join,
+        Ban { reason: String },
+        Knock(Option<String>),
+    }
+
+    #[derive(Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize)]
+    struct Member {
+        membership: Membership,
+        levels: HashMap<u64, bool>,
+        pair: (u8, String),
+        avatar_url: Option<String>,
+    }
+
+    let members = vec![
+        Member {
+            membership: Membership::Join,
+            levels: vec![(50, true)].into_iter().collect(),
+            pair: (1, "a".to_owned()),
+            avatar_url: None,
+        },
+        Member {
+            membership: Membership::Ban {
+                reason: "spam".to_owned(),
+            },
+            levels: HashMap::new(),
+            pair: (2, "b".to_owned()),
+            avatar_url: Some("mxc://example.org/abc".to_owned()),
+        },
+        Member {
+            membership: Membership::Knock(None),
+            levels: HashMap::new(),
+            pair: (3, "c".to_owned()),
+            avatar_url: None,
+        },
+    ];
+
+    let value = to_canonical_value(&members).unwrap();
+    assert_eq!(from_canonical_value::<Vec<Member>>(value).unwrap(), members);
+}
+
+#[test]
+fn value_type_mismatch() {
+    #[derive(Debug, serde_derive::Deserialize)]
+    struct Event {
+        #[allow(dead_code)]
+        origin_server_ts: u64,
+    }
+
+    let value = to_canonical_value(&serde_json::json!({ "origin_server_ts": "now" })).unwrap();
+    assert!(matches!(
+        from_canonical_value::<Event>(value),
+        Err(Error::Custom(_))
+    ));
+}
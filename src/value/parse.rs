@@ -0,0 +1,437 @@
+use std::{borrow::Cow, collections::btree_map::Entry};
+
+use crate::{
+    error::duplicate_key,
+    read::{Event, Parser},
+    CanonicalJsonObject, CanonicalJsonValue, Error, Result, Severity, Violation, ViolationKind,
+    MAX_SAFE_INTEGER, MIN_SAFE_INTEGER,
+};
+
+/// How numbers are read that are integers but not written the way canonical JSON writes them,
+/// see `Options::number_policy`. Numbers that are not integers always fail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NumberPolicy {
+    /// Only integers written exactly like canonical JSON writes them.
+    Strict,
+    /// Also `-0`, read as `0`. What `from_slice` does.
+    NegativeZero,
+    /// Any number whose value is an integer however it is written, like `-0`, `007`, `1.0`,
+    /// `1e3` or `12.5e1`. Leading zeros are not valid JSON but accepted too.
+    Normalize,
+}
+
+impl Default for NumberPolicy {
+    fn default() -> Self {
+        Self::NegativeZero
+    }
+}
+
+/// Parse a JSON document into a `CanonicalJsonValue`, only whitespace may follow it.
+pub fn from_slice(input: &[u8]) -> Result<CanonicalJsonValue> {
+    from_parser(Parser::new(input), NumberPolicy::NegativeZero, false)
+}
+
+/// Parse the whole document of an already configured `parser`. With `collect` numbers that
+/// can't be read and duplicate keys don't stop parsing, they are all returned in
+/// `Error::Violations` once the document is read.
+pub(crate) fn from_parser(
+    mut parser: Parser<'_>,
+    numbers: NumberPolicy,
+    collect: bool,
+) -> Result<CanonicalJsonValue> {
+    let mut cx = Context {
+        numbers,
+        path: vec![],
+        violations: if collect { Some(vec![]) } else { None },
+    };
+    let value = parse(&mut parser, &mut cx)?;
+    parser.end()?;
+
+    match cx.violations {
+        Some(violations) if !violations.is_empty() => Err(Error::Violations(violations)),
+        _ => Ok(value),
+    }
+}
+
+/// Parse the first JSON document in `input`, anything after it is left alone.
+///
+/// Returns the value and the number of bytes consumed so the caller can continue with the next
+/// document at `&input[consumed..]`.
+pub fn from_slice_with_trailing(input: &[u8]) -> Result<(CanonicalJsonValue, usize)> {
+    let mut parser = Parser::new(input);
+    let mut cx = Context {
+        numbers: NumberPolicy::NegativeZero,
+        path: vec![],
+        violations: None,
+    };
+    let value = parse(&mut parser, &mut cx)?;
+    Ok((value, parser.offset()))
+}
+
+/// How a document is parsed and, when errors are collected, what is wrong with it so far.
+struct Context {
+    numbers: NumberPolicy,
+    /// The path to the current value, only kept when errors are collected.
+    path: Vec<String>,
+    violations: Option<Vec<Violation>>,
+}
+
+impl Context {
+    /// Fail with `error`, or record it and carry on when errors are collected.
+    fn report(
+        &mut self,
+        error: Error,
+        kind: ViolationKind,
+        found: String,
+        suggestion: Option<String>,
+    ) -> Result<()> {
+        let violations = match &mut self.violations {
+            Some(violations) => violations,
+            None => return Err(error),
+        };
+        violations.push(Violation {
+            kind,
+            severity: Severity::Error,
+            pointer: self
+                .path
+                .iter()
+                .map(|segment| format!("/{}", segment.replace('~', "~0").replace('/', "~1")))
+                .collect(),
+            found,
+            suggestion,
+        });
+        Ok(())
+    }
+
+    fn enter<F: FnOnce() -> String>(&mut self, segment: F) {
+        if self.violations.is_some() {
+            self.path.push(segment());
+        }
+    }
+
+    fn leave(&mut self) {
+        self.path.pop();
+    }
+}
+
+fn parse(parser: &mut Parser<'_>, cx: &mut Context) -> Result<CanonicalJsonValue> {
+    let event = next(parser)?;
+    parse_value(parser, event, cx)
+}
+
+fn next<'a>(parser: &mut Parser<'a>) -> Result<Event<'a>> {
+    Ok(parser
+        .next_event()?
+        .expect("the parser yields a whole value before it is done"))
+}
+
+fn parse_value<'a>(
+    parser: &mut Parser<'a>,
+    event: Event<'a>,
+    cx: &mut Context,
+) -> Result<CanonicalJsonValue> {
+    Ok(match event {
+        Event::Null => CanonicalJsonValue::Null,
+        Event::Bool(b) => CanonicalJsonValue::Bool(b),
+        Event::Number(n) => match read_integer(n, cx.numbers) {
+            Ok(int) => CanonicalJsonValue::Integer(int),
+            Err(bad) => {
+                let offset = parser.offset() - n.len();
+                let error = Error::InvalidInput(format!("`{}` at byte {} {}", n, offset, bad.why));
+                cx.report(error, bad.kind, n.to_owned(), Some(bad.suggestion))?;
+                // Never returned, parsing fails once the document is read.
+                CanonicalJsonValue::Null
+            }
+        },
+        Event::String(s) => CanonicalJsonValue::String(s.unescape().into_owned()),
+        Event::ArrayStart => {
+            let mut array = vec![];
+            loop {
+                match next(parser)? {
+                    Event::ArrayEnd => break,
+                    event => {
+                        let idx = array.len();
+                        cx.enter(|| idx.to_string());
+                        array.push(parse_value(parser, event, cx)?);
+                        cx.leave();
+                    }
+                }
+            }
+            CanonicalJsonValue::Array(array)
+        }
+        Event::ObjectStart => {
+            let mut object = CanonicalJsonObject::new();
+            loop {
+                match next(parser)? {
+                    Event::ObjectEnd => break,
+                    Event::Key(key) => {
+                        let key = key.unescape().into_owned();
+                        let event = next(parser)?;
+                        cx.enter(|| key.clone());
+                        let value = parse_value(parser, event, cx)?;
+                        match object.entry(key) {
+                            Entry::Vacant(entry) => {
+                                entry.insert(value);
+                            }
+                            // The value of the first one is kept.
+                            Entry::Occupied(entry) => {
+                                let key = entry.key().clone();
+                                cx.report(
+                                    duplicate_key(&key),
+                                    ViolationKind::DuplicateKey,
+                                    key,
+                                    None,
+                                )?;
+                            }
+                        }
+                        cx.leave();
+                    }
+                    _ => unreachable!("objects only hold keys followed by values"),
+                }
+            }
+            CanonicalJsonValue::Object(object)
+        }
+        Event::ObjectEnd | Event::ArrayEnd | Event::Key(_) => {
+            unreachable!("the parser only yields these after a value started")
+        }
+    })
+}
+
+/// Why a number can't be read as an integer.
+struct BadNumber {
+    kind: ViolationKind,
+    why: String,
+    suggestion: String,
+}
+
+/// Read the integer `number` as `policy` says.
+fn read_integer(number: &str, policy: NumberPolicy) -> std::result::Result<i64, BadNumber> {
+    let out_of_range = || BadNumber {
+        kind: ViolationKind::IntegerOutOfRange,
+        why: OUT_OF_RANGE.to_owned(),
+        suggestion: "encode the integer as a string".to_owned(),
+    };
+
+    let plain = !number.contains(&['.', 'e', 'E'][..]);
+    let digits = if plain && (number == "0" || !number.trim_start_matches('-').starts_with('0')) {
+        Cow::Borrowed(number)
+    } else {
+        let digits = match normalize_integer(number) {
+            Ok(digits) => digits,
+            Err(why) if why == OUT_OF_RANGE => return Err(out_of_range()),
+            Err(why) => {
+                return Err(BadNumber {
+                    kind: ViolationKind::Float,
+                    why: why.to_owned(),
+                    suggestion: "encode the number as a string".to_owned(),
+                })
+            }
+        };
+        let why = match policy {
+            NumberPolicy::Normalize => None,
+            _ if !plain => Some(format!(
+                "is the integer {} written with a fraction or an exponent",
+                digits
+            )),
+            NumberPolicy::Strict => Some(format!("is written as `{}` in canonical JSON", digits)),
+            NumberPolicy::NegativeZero => None,
+        };
+        if let Some(why) = why {
+            return Err(BadNumber {
+                kind: ViolationKind::Float,
+                why,
+                suggestion: format!("use the integer {}", digits),
+            });
+        }
+        Cow::Owned(digits)
+    };
+    match digits.parse() {
+        Ok(int) if (MIN_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&int) => Ok(int),
+        _ => Err(out_of_range()),
+    }
+}
+
+const NOT_INTEGER: &str = "is not an integer, canonical JSON has no floats";
+const OUT_OF_RANGE: &str = "is out of the range of canonical JSON integers";
+
+/// The digits of the integer `number` is however it is written, like `12` for `1.2e1` or
+/// `0012.0`. Fails with the reason when it is not an integer.
+fn normalize_integer(number: &str) -> std::result::Result<String, &'static str> {
+    let (sign, rest) = match number.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", number),
+    };
+    let (mantissa, exp) = match rest.find(&['e', 'E'][..]) {
+        Some(pos) => (&rest[..pos], &rest[pos + 1..]),
+        None => (rest, "0"),
+    };
+    let (int, frac) = match mantissa.find('.') {
+        Some(pos) => (&mantissa[..pos], &mantissa[pos + 1..]),
+        None => (mantissa, ""),
+    };
+
+    let digits = format!("{}{}", int, frac);
+    let significant = digits.trim_start_matches('0');
+    if significant.is_empty() {
+        return Ok("0".to_owned());
+    }
+    let exp = match exp.parse::<i32>() {
+        Ok(exp) => i64::from(exp),
+        Err(_) if exp.starts_with('-') => return Err(NOT_INTEGER),
+        Err(_) => return Err(OUT_OF_RANGE),
+    };
+    // How many digits of `significant` come before the decimal point.
+    let point = int.len() as i64 - (digits.len() - significant.len()) as i64 + exp;
+    let significant = significant.trim_end_matches('0');
+    if significant.len() as i64 > point {
+        return Err(NOT_INTEGER);
+    }
+    if point > 19 {
+        return Err(OUT_OF_RANGE);
+    }
+    Ok(format!(
+        "{}{}{}",
+        sign,
+        significant,
+        "0".repeat((point - significant.len() as i64) as usize)
+    ))
+}
+
+#[cfg(feature = "ser")]
+#[test]
+fn parse_concatenated_documents() {
+    let journal = b"{\"a\":1}{\"b\":[-0,\"\\n\"]}\n{\"c\":null}\n";
+
+    let mut rest = &journal[..];
+    let mut values = vec![];
+    while !rest.iter().all(u8::is_ascii_whitespace) {
+        let (value, consumed) = from_slice_with_trailing(rest).unwrap();
+        values.push(crate::to_canonical_string(&value).unwrap());
+        rest = &rest[consumed..];
+    }
+
+    assert_eq!(
+        values,
+        vec![r#"{"a":1}"#, r#"{"b":[0,"\n"]}"#, r#"{"c":null}"#]
+    );
+    assert!(matches!(
+        from_slice(b"{\"a\":1}{}"),
+        Err(Error::Syntax { offset: 7, .. })
+    ));
+    assert!(matches!(from_slice(b"[1.5]"), Err(Error::InvalidInput(_))));
+    assert!(matches!(
+        from_slice(br#"{"a":1,"a":2}"#),
+        Err(Error::InvalidInput(_))
+    ));
+}
+
+#[cfg(feature = "ser")]
+#[test]
+fn number_policies() {
+    use crate::Options;
+
+    let read = |policy, json: &str| {
+        Options::new()
+            .number_policy(policy)
+            .from_slice(json.as_bytes())
+    };
+    let error = |policy, json: &str| match read(policy, json) {
+        Err(Error::InvalidInput(msg)) | Err(Error::Syntax { msg, .. }) => msg,
+        res => panic!("{} read as {:?}", json, res),
+    };
+
+    assert_eq!(from_slice(b"[-0]").unwrap(), from_slice(b"[0]").unwrap());
+    assert_eq!(
+        error(NumberPolicy::Strict, "[1, -0]"),
+        "`-0` at byte 4 is written as `0` in canonical JSON"
+    );
+    assert_eq!(
+        error(NumberPolicy::NegativeZero, "[1.0]"),
+        "`1.0` at byte 1 is the integer 1 written with a fraction or an exponent"
+    );
+    assert_eq!(
+        error(NumberPolicy::NegativeZero, r#"{"a": 1.5e-1}"#),
+        "`1.5e-1` at byte 6 is not an integer, canonical JSON has no floats"
+    );
+    assert_eq!(
+        error(NumberPolicy::Strict, "[007]"),
+        "number `007` has leading zeros"
+    );
+
+    let normalized = read(
+        NumberPolicy::Normalize,
+        "[-0, 007, -0.0e5, 1.0, 1e3, 12.5E+1, 1200e-2, 0.000, 9007199254740991.0]",
+    )
+    .unwrap();
+    assert_eq!(
+        crate::to_canonical_string(&normalized).unwrap(),
+        "[0,7,0,1,1000,125,12,0,9007199254740991]"
+    );
+    assert_eq!(
+        error(NumberPolicy::Normalize, "[1.05e1]"),
+        "`1.05e1` at byte 1 is not an integer, canonical JSON has no floats"
+    );
+    assert_eq!(
+        error(NumberPolicy::Normalize, "[1e400]"),
+        "`1e400` at byte 1 is out of the range of canonical JSON integers"
+    );
+    assert_eq!(
+        error(NumberPolicy::Strict, "[-9007199254740992]"),
+        "`-9007199254740992` at byte 1 is out of the range of canonical JSON integers"
+    );
+    assert!(read(NumberPolicy::Normalize, "[9.007199254740992e15]").is_err());
+    assert!(read(NumberPolicy::Normalize, "[1e-99999999999]").is_err());
+}
+
+#[cfg(feature = "ser")]
+#[test]
+fn collects_errors() {
+    use crate::Options;
+
+    let json = br#"{"a": [1, 1.5, {"b": 2, "b": 3}], "c/d": -0, "e": 99999999999999999999, "f": 9007199254740992}"#;
+    assert!(matches!(
+        Options::new().from_slice(json),
+        Err(Error::InvalidInput(msg)) if msg == "`1.5` at byte 10 is not an integer, canonical JSON has no floats"
+    ));
+
+    let violations = match Options::new()
+        .number_policy(NumberPolicy::Strict)
+        .collect_errors(true)
+        .from_slice(json)
+    {
+        Err(Error::Violations(violations)) => violations,
+        res => panic!("{:?}", res),
+    };
+    assert_eq!(
+        violations
+            .iter()
+            .map(|v| (v.kind, v.pointer.as_str(), v.found.as_str()))
+            .collect::<Vec<_>>(),
+        vec![
+            (ViolationKind::Float, "/a/1", "1.5"),
+            (ViolationKind::DuplicateKey, "/a/2/b", "b"),
+            (ViolationKind::Float, "/c~1d", "-0"),
+            (
+                ViolationKind::IntegerOutOfRange,
+                "/e",
+                "99999999999999999999"
+            ),
+            (ViolationKind::IntegerOutOfRange, "/f", "9007199254740992"),
+        ]
+    );
+    assert_eq!(
+        violations[2].suggestion.as_deref(),
+        Some("use the integer 0")
+    );
+
+    let collecting = Options::new().collect_errors(true);
+    assert_eq!(
+        collecting.from_slice(br#"{"a": [1, -0]}"#).unwrap(),
+        from_slice(br#"{"a": [1, 0]}"#).unwrap()
+    );
+    // Syntax errors still stop parsing.
+    assert!(matches!(
+        collecting.from_slice(b"[1.5, }"),
+        Err(Error::Syntax { .. })
+    ));
+}
@@ -0,0 +1,616 @@
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    convert::TryFrom,
+    fmt,
+};
+
+use serde::{
+    ser::{self, Impossible},
+    serde_if_integer128, Serialize,
+};
+
+use crate::{
+    error::{duplicate_key, key_must_be_a_string},
+    value::{CanonicalJsonObject, CanonicalJsonValue},
+    Error, Result, MAX_SAFE_INTEGER, MIN_SAFE_INTEGER,
+};
+
+/// `value` as an `Integer`, failing like the serializer does for values outside of the range
+/// canonical JSON allows.
+fn integer<T>(value: T) -> Result<CanonicalJsonValue>
+where
+    T: Copy + fmt::Display,
+    i64: TryFrom<T>,
+{
+    match i64::try_from(value) {
+        Ok(int) if (MIN_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&int) => {
+            Ok(CanonicalJsonValue::Integer(int))
+        }
+        _ => Err(Error::IntegerOutOfRange {
+            pointer: String::new(),
+            value: value.to_string(),
+        }),
+    }
+}
+
+/// Insert an entry of an object, flattened fields can repeat a key of the struct they are
+/// flattened into.
+fn insert_unique(
+    map: &mut CanonicalJsonObject,
+    key: String,
+    value: CanonicalJsonValue,
+) -> Result<()> {
+    match map.entry(key) {
+        Entry::Vacant(entry) => {
+            entry.insert(value);
+            Ok(())
+        }
+        Entry::Occupied(entry) => Err(duplicate_key(entry.key())),
+    }
+}
+
+/// Serializes any `Serialize` type into a `CanonicalJsonValue`.
+pub(crate) struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = CanonicalJsonValue;
+    type Error = Error;
+
+    type SerializeSeq = SerializeVec;
+    type SerializeTuple = SerializeVec;
+    type SerializeTupleStruct = SerializeVec;
+    type SerializeTupleVariant = SerializeTupleVariant;
+    type SerializeMap = SerializeMap;
+    type SerializeStruct = SerializeMap;
+    type SerializeStructVariant = SerializeStructVariant;
+
+    #[inline]
+    fn serialize_bool(self, value: bool) -> Result<CanonicalJsonValue> {
+        Ok(CanonicalJsonValue::Bool(value))
+    }
+
+    #[inline]
+    fn serialize_i8(self, value: i8) -> Result<CanonicalJsonValue> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    #[inline]
+    fn serialize_i16(self, value: i16) -> Result<CanonicalJsonValue> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    #[inline]
+    fn serialize_i32(self, value: i32) -> Result<CanonicalJsonValue> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    #[inline]
+    fn serialize_i64(self, value: i64) -> Result<CanonicalJsonValue> {
+        integer(value)
+    }
+
+    serde_if_integer128! {
+        fn serialize_i128(self, value: i128) -> Result<CanonicalJsonValue> {
+            integer(value)
+        }
+    }
+
+    #[inline]
+    fn serialize_u8(self, value: u8) -> Result<CanonicalJsonValue> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    #[inline]
+    fn serialize_u16(self, value: u16) -> Result<CanonicalJsonValue> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    #[inline]
+    fn serialize_u32(self, value: u32) -> Result<CanonicalJsonValue> {
+        self.serialize_i64(i64::from(value))
+    }
+
+    #[inline]
+    fn serialize_u64(self, value: u64) -> Result<CanonicalJsonValue> {
+        integer(value)
+    }
+
+    serde_if_integer128! {
+        fn serialize_u128(self, value: u128) -> Result<CanonicalJsonValue> {
+            integer(value)
+        }
+    }
+
+    #[inline]
+    fn serialize_f32(self, value: f32) -> Result<CanonicalJsonValue> {
+        Err(Error::InvalidInput(format!(
+            "f32 is not valid in canonical JSON found {}",
+            value
+        )))
+    }
+
+    #[inline]
+    fn serialize_f64(self, value: f64) -> Result<CanonicalJsonValue> {
+        Err(Error::InvalidInput(format!(
+            "f64 is not valid in canonical JSON found {}",
+            value
+        )))
+    }
+
+    #[inline]
+    fn serialize_char(self, value: char) -> Result<CanonicalJsonValue> {
+        Ok(CanonicalJsonValue::String(value.to_string()))
+    }
+
+    #[inline]
+    fn serialize_str(self, value: &str) -> Result<CanonicalJsonValue> {
+        Ok(CanonicalJsonValue::String(value.to_owned()))
+    }
+
+    #[inline]
+    fn serialize_bytes(self, value: &[u8]) -> Result<CanonicalJsonValue> {
+        Ok(CanonicalJsonValue::Array(
+            value
+                .iter()
+                .map(|&byte| CanonicalJsonValue::Integer(i64::from(byte)))
+                .collect(),
+        ))
+    }
+
+    #[inline]
+    fn serialize_unit(self) -> Result<CanonicalJsonValue> {
+        Ok(CanonicalJsonValue::Null)
+    }
+
+    #[inline]
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<CanonicalJsonValue> {
+        self.serialize_unit()
+    }
+
+    #[inline]
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<CanonicalJsonValue> {
+        self.serialize_str(variant)
+    }
+
+    #[inline]
+    fn serialize_newtype_struct<T>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<CanonicalJsonValue>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<CanonicalJsonValue>
+    where
+        T: ?Sized + Serialize,
+    {
+        let mut object = BTreeMap::new();
+        let value = value.serialize(Serializer).map_err(|err| err.at(variant))?;
+        object.insert(variant.to_owned(), value);
+        Ok(CanonicalJsonValue::Object(object))
+    }
+
+    #[inline]
+    fn serialize_none(self) -> Result<CanonicalJsonValue> {
+        self.serialize_unit()
+    }
+
+    #[inline]
+    fn serialize_some<T>(self, value: &T) -> Result<CanonicalJsonValue>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Ok(SerializeVec {
+            vec: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Ok(SerializeTupleVariant {
+            name: variant.to_owned(),
+            vec: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Ok(SerializeMap {
+            map: BTreeMap::new(),
+            next_key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Ok(SerializeStructVariant {
+            name: variant.to_owned(),
+            map: BTreeMap::new(),
+        })
+    }
+}
+
+pub(crate) struct SerializeVec {
+    vec: Vec<CanonicalJsonValue>,
+}
+
+pub(crate) struct SerializeTupleVariant {
+    name: String,
+    vec: Vec<CanonicalJsonValue>,
+}
+
+pub(crate) struct SerializeMap {
+    map: CanonicalJsonObject,
+    next_key: Option<String>,
+}
+
+pub(crate) struct SerializeStructVariant {
+    name: String,
+    map: CanonicalJsonObject,
+}
+
+impl ser::SerializeSeq for SerializeVec {
+    type Ok = CanonicalJsonValue;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let index = self.vec.len();
+        let value = value
+            .serialize(Serializer)
+            .map_err(|err| err.at(&index.to_string()))?;
+        self.vec.push(value);
+        Ok(())
+    }
+
+    fn end(self) -> Result<CanonicalJsonValue> {
+        Ok(CanonicalJsonValue::Array(self.vec))
+    }
+}
+
+impl ser::SerializeTuple for SerializeVec {
+    type Ok = CanonicalJsonValue;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<CanonicalJsonValue> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeVec {
+    type Ok = CanonicalJsonValue;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<CanonicalJsonValue> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeTupleVariant {
+    type Ok = CanonicalJsonValue;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let index = self.vec.len();
+        let value = value
+            .serialize(Serializer)
+            .map_err(|err| err.at(&index.to_string()).at(&self.name))?;
+        self.vec.push(value);
+        Ok(())
+    }
+
+    fn end(self) -> Result<CanonicalJsonValue> {
+        let mut object = BTreeMap::new();
+        object.insert(self.name, CanonicalJsonValue::Array(self.vec));
+        Ok(CanonicalJsonValue::Object(object))
+    }
+}
+
+impl ser::SerializeMap for SerializeMap {
+    type Ok = CanonicalJsonValue;
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.next_key = Some(key.serialize(ObjectKeySerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let key = self
+            .next_key
+            .take()
+            .expect("serialize_value called before serialize_key");
+        let value = value.serialize(Serializer).map_err(|err| err.at(&key))?;
+        insert_unique(&mut self.map, key, value)
+    }
+
+    fn end(self) -> Result<CanonicalJsonValue> {
+        Ok(CanonicalJsonValue::Object(self.map))
+    }
+}
+
+impl ser::SerializeStruct for SerializeMap {
+    type Ok = CanonicalJsonValue;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeMap::serialize_entry(self, key, value)
+    }
+
+    fn end(self) -> Result<CanonicalJsonValue> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeStructVariant {
+    type Ok = CanonicalJsonValue;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let value = value
+            .serialize(Serializer)
+            .map_err(|err| err.at(key).at(&self.name))?;
+        insert_unique(&mut self.map, key.to_owned(), value)
+    }
+
+    fn end(self) -> Result<CanonicalJsonValue> {
+        let mut object = BTreeMap::new();
+        object.insert(self.name, CanonicalJsonValue::Object(self.map));
+        Ok(CanonicalJsonValue::Object(object))
+    }
+}
+
+/// Serializes map keys into the `String` keys of a `CanonicalJsonObject`, following the same
+/// rules as `MapKeySerializer`.
+pub(crate) struct ObjectKeySerializer;
+
+impl ser::Serializer for ObjectKeySerializer {
+    type Ok = String;
+    type Error = Error;
+
+    type SerializeSeq = Impossible<String, Error>;
+    type SerializeTuple = Impossible<String, Error>;
+    type SerializeTupleStruct = Impossible<String, Error>;
+    type SerializeTupleVariant = Impossible<String, Error>;
+    type SerializeMap = Impossible<String, Error>;
+    type SerializeStruct = Impossible<String, Error>;
+    type SerializeStructVariant = Impossible<String, Error>;
+
+    #[inline]
+    fn serialize_str(self, value: &str) -> Result<String> {
+        Ok(value.to_owned())
+    }
+
+    #[inline]
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<String> {
+        Ok(variant.to_owned())
+    }
+
+    #[inline]
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<String>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_bool(self, value: bool) -> Result<String> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<String> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<String> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<String> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<String> {
+        Ok(value.to_string())
+    }
+
+    serde_if_integer128! {
+        fn serialize_i128(self, value: i128) -> Result<String> {
+            Ok(value.to_string())
+        }
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<String> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<String> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<String> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<String> {
+        Ok(value.to_string())
+    }
+
+    serde_if_integer128! {
+        fn serialize_u128(self, value: u128) -> Result<String> {
+            Ok(value.to_string())
+        }
+    }
+
+    fn serialize_f32(self, _value: f32) -> Result<String> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_f64(self, _value: f64) -> Result<String> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_char(self, value: char) -> Result<String> {
+        Ok(value.to_string())
+    }
+
+    fn serialize_bytes(self, _value: &[u8]) -> Result<String> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_unit(self) -> Result<String> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<String> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<String>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_none(self) -> Result<String> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_some<T>(self, _value: &T) -> Result<String>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(key_must_be_a_string())
+    }
+}
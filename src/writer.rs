@@ -0,0 +1,213 @@
+use std::{fmt, io, str};
+
+use crate::Error;
+
+/// A writer that counts the bytes written through it and fails once more than `limit` bytes
+/// would be written.
+///
+/// The failing write returns an `io::Error` wrapping `Error::SizeLimit`, `Error::io` turns it
+/// back into `Error::SizeLimit`. Nothing of the write that crosses the limit reaches the inner
+/// writer.
+pub struct LimitedWriter<W> {
+    writer: W,
+    written: usize,
+    limit: Option<usize>,
+}
+
+impl<W> LimitedWriter<W> {
+    /// Wrap `writer`, a `limit` of `None` only counts the bytes written.
+    pub fn new(writer: W, limit: Option<usize>) -> Self {
+        Self {
+            writer,
+            written: 0,
+            limit,
+        }
+    }
+
+    /// The number of bytes written so far.
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: io::Write> io::Write for LimitedWriter<W> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(limit) = self.limit {
+            if self.written + buf.len() > limit {
+                return Err(io::Error::new(io::ErrorKind::Other, Error::SizeLimit));
+            }
+        }
+        let n = self.writer.write(buf)?;
+        self.written += n;
+        Ok(n)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// How many bytes `StagingWriter` collects before handing them on.
+const STAGING_CAPACITY: usize = 8 * 1024;
+
+/// Collects the many small writes of the serializer, like `{` or `":`, and hands them to the
+/// inner writer in chunks of up to 8 KiB.
+///
+/// Nothing is written on drop, `finish` writes what is left so the output of a failed
+/// serialization stops at the last full chunk.
+pub(crate) struct StagingWriter<W> {
+    writer: W,
+    buf: Vec<u8>,
+}
+
+impl<W: io::Write> StagingWriter<W> {
+    pub(crate) fn new(writer: W) -> Self {
+        Self {
+            writer,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Write the staged bytes and return the inner writer.
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        self.write_staged()?;
+        Ok(self.writer)
+    }
+
+    fn write_staged(&mut self) -> io::Result<()> {
+        self.writer.write_all(&self.buf)?;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+impl<W: io::Write> io::Write for StagingWriter<W> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.buf.len() + buf.len() > STAGING_CAPACITY {
+            self.write_staged()?;
+        }
+        if buf.len() >= STAGING_CAPACITY {
+            self.writer.write_all(buf)?;
+        } else {
+            if self.buf.capacity() == 0 {
+                self.buf.reserve(STAGING_CAPACITY);
+            }
+            self.buf.extend_from_slice(buf);
+        }
+        Ok(buf.len())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_staged()?;
+        self.writer.flush()
+    }
+}
+
+/// Adapts a `fmt::Write` sink to `io::Write`.
+///
+/// The serializer only ever writes whole UTF-8 sequences so each write is valid UTF-8 on its own.
+pub(crate) struct FmtWriter<W> {
+    writer: W,
+}
+
+impl<W> FmtWriter<W> {
+    pub(crate) fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: fmt::Write> io::Write for FmtWriter<W> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let s = str::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.writer
+            .write_str(s)
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "fmt::Write sink failed"))?;
+        Ok(buf.len())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn limited_writer_mid_stream() {
+    use std::io::Write;
+
+    let mut writer = LimitedWriter::new(vec![], Some(4));
+    writer.write_all(b"abc").unwrap();
+
+    let err = writer.write_all(b"de").unwrap_err();
+    assert!(matches!(Error::io(err), Error::SizeLimit));
+    assert_eq!(writer.written(), 3);
+    assert_eq!(writer.into_inner(), b"abc");
+}
+
+#[test]
+fn staged_writes() {
+    use std::io::Write;
+
+    /// Counts the writes it gets.
+    struct Writes(Vec<u8>, usize);
+
+    impl io::Write for Writes {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.1 += 1;
+            self.0.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let events = vec![serde_json::json!({ "body": "hi", "n": [1, 2, 3] }); 1000];
+    let mut writes = Writes(vec![], 0);
+    let len = crate::Options::new()
+        .size_limit(None)
+        .to_writer(&mut writes, &events)
+        .unwrap();
+    assert_eq!(len, writes.0.len());
+    assert_eq!(
+        writes.0,
+        crate::to_canonical_string_unlimited(&events)
+            .unwrap()
+            .into_bytes()
+    );
+    assert!(
+        writes.1 <= len / (STAGING_CAPACITY / 2) + 1,
+        "{} writes",
+        writes.1
+    );
+
+    let mut staged = StagingWriter::new(Writes(vec![], 0));
+    staged.write_all(b"{").unwrap();
+    staged.write_all(&[b' '; STAGING_CAPACITY]).unwrap();
+    staged.write_all(b"}").unwrap();
+    let writes = staged.finish().unwrap();
+    assert_eq!(writes.0.len(), STAGING_CAPACITY + 2);
+    assert_eq!(writes.1, 3);
+}